@@ -60,9 +60,12 @@ fn name_from_cli(rt: &Runtime, subcmd: &str) -> String {
 
 fn create(rt: &Runtime) {
     let name = name_from_cli(rt, "create");
-    Note::new(rt.store(), name.clone(), String::new())
-        .map_err(|e| trace_error(&e))
-        .ok();
+    match Note::template_from_config(rt) {
+        Some(template) => Note::new_with_template(rt.store(), name.clone(), &template[..]),
+        None           => Note::new(rt.store(), name.clone(), String::new()),
+    }
+    .map_err(|e| trace_error(&e))
+    .ok();
 
     if rt.cli().subcommand_matches("create").unwrap().is_present("edit") {
         if !edit_entry(rt, name) {
@@ -0,0 +1,73 @@
+use libimagstore::store::Entry;
+
+use filter::Filter;
+
+/// Check whether the content of an Entry contains something other than whitespace
+pub struct HasContent;
+
+impl HasContent {
+
+    pub fn new() -> HasContent {
+        HasContent
+    }
+
+}
+
+impl Filter for HasContent {
+
+    fn filter(&self, e: &Entry) -> bool {
+        !e.get_content().trim().is_empty()
+    }
+
+}
+
+/// Check whether the content of an Entry is empty or contains only whitespace
+pub struct IsEmptyContent;
+
+impl IsEmptyContent {
+
+    pub fn new() -> IsEmptyContent {
+        IsEmptyContent
+    }
+
+}
+
+impl Filter for IsEmptyContent {
+
+    fn filter(&self, e: &Entry) -> bool {
+        e.get_content().trim().is_empty()
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::{HasContent, IsEmptyContent};
+    use filter::Filter;
+
+    fn entry_with_content(content: &str) -> Entry {
+        let text = format!("---\n[imag]\nversion = \"0.1.0\"\n---\n{}", content);
+        Entry::from_str(PathBuf::from("/store/notes/test~0.1.0"), &text[..]).unwrap()
+    }
+
+    #[test]
+    fn test_empty_content() {
+        for content in ["", "\n", "  \n\t\n"].iter() {
+            let entry = entry_with_content(content);
+            assert!(IsEmptyContent::new().filter(&entry));
+            assert!(!HasContent::new().filter(&entry));
+        }
+    }
+
+    #[test]
+    fn test_non_empty_content() {
+        let entry = entry_with_content("Some content\n");
+        assert!(!IsEmptyContent::new().filter(&entry));
+        assert!(HasContent::new().filter(&entry));
+    }
+
+}
@@ -3,6 +3,7 @@ use regex::Error as RError;
 
 use libimagstore::store::Entry;
 
+use error::{FilterCompilerError as FCE, FilterCompilerErrorKind as FCEK};
 use filter::Filter;
 
 pub trait IntoRegex {
@@ -25,6 +26,7 @@ impl<'a> IntoRegex for Regex {
     }
 }
 
+/// Check whether the content of an entry matches a regular expression
 pub struct ContentGrep {
     regex: Regex,
 }
@@ -42,6 +44,12 @@ impl ContentGrep {
             })
     }
 
+    /// Build a ContentGrep from a pattern, failing with an `InvalidRegex` error if the pattern is
+    /// no valid regular expression
+    pub fn from_pattern(pattern: &str) -> Result<ContentGrep, FCE> {
+        ContentGrep::new(pattern).map_err(|e| FCE::new(FCEK::InvalidRegex, Some(Box::new(e))))
+    }
+
 }
 
 impl Filter for ContentGrep {
@@ -52,3 +60,32 @@ impl Filter for ContentGrep {
 
 }
 
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::ContentGrep;
+    use error::FilterCompilerErrorKind as FCEK;
+    use filter::Filter;
+
+    fn entry(content: &str) -> Entry {
+        let text = format!("---\n[imag]\nversion = \"0.1.0\"\n---\n{}", content);
+        Entry::from_str(PathBuf::from("/store/test~0.1.0"), &text[..]).unwrap()
+    }
+
+    #[test]
+    fn test_content_grep() {
+        let filter = ContentGrep::from_pattern("buy (milk|bread)").unwrap();
+        assert!(filter.filter(&entry("todo\nbuy bread")));
+        assert!(!filter.filter(&entry("buy cheese")));
+        assert!(!filter.filter(&entry("")));
+    }
+
+    #[test]
+    fn test_content_grep_invalid_regex() {
+        assert_eq!(ContentGrep::from_pattern("(").err().unwrap().err_type(), FCEK::InvalidRegex);
+    }
+
+}
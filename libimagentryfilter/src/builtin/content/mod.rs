@@ -1,2 +1,3 @@
+pub mod empty;
 pub mod grep;
 pub mod length;
@@ -0,0 +1,84 @@
+use libimagstore::store::Entry;
+
+use filter::Filter;
+
+fn is_archived(e: &Entry) -> bool {
+    e.get_header().read_boolean("imag.archived").ok().and_then(|b| b).unwrap_or(false)
+}
+
+/// Check whether an Entry is archived, see `Store::archive()`
+///
+/// Entries without `imag.archived` in their header are not archived.
+pub struct IsArchived;
+
+impl IsArchived {
+
+    pub fn new() -> IsArchived {
+        IsArchived
+    }
+
+}
+
+impl Filter for IsArchived {
+
+    fn filter(&self, e: &Entry) -> bool {
+        is_archived(e)
+    }
+
+}
+
+/// Check whether an Entry is not archived, the inverse of `IsArchived`
+pub struct IsNotArchived;
+
+impl IsNotArchived {
+
+    pub fn new() -> IsNotArchived {
+        IsNotArchived
+    }
+
+}
+
+impl Filter for IsNotArchived {
+
+    fn filter(&self, e: &Entry) -> bool {
+        !is_archived(e)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::{IsArchived, IsNotArchived};
+    use filter::Filter;
+
+    fn entry_with_imag_header(imag: &str) -> Entry {
+        let text = format!("---\n[imag]\nversion = \"0.1.0\"\n{}\n---\n", imag);
+        Entry::from_str(PathBuf::from("/store/notes/test~0.1.0"), &text[..]).unwrap()
+    }
+
+    #[test]
+    fn test_archived() {
+        let entry = entry_with_imag_header("archived = true");
+        assert!(IsArchived::new().filter(&entry));
+        assert!(!IsNotArchived::new().filter(&entry));
+    }
+
+    #[test]
+    fn test_not_archived() {
+        let entry = entry_with_imag_header("archived = false");
+        assert!(!IsArchived::new().filter(&entry));
+        assert!(IsNotArchived::new().filter(&entry));
+    }
+
+    #[test]
+    fn test_archived_field_absent() {
+        let entry = entry_with_imag_header("");
+        assert!(!IsArchived::new().filter(&entry));
+        assert!(IsNotArchived::new().filter(&entry));
+    }
+
+}
@@ -0,0 +1,107 @@
+use chrono::{DateTime, Utc};
+
+use libimagstore::store::Entry;
+
+use builtin::header::field_path::FieldPath;
+use filter::Filter;
+
+/// Check whether a datetime header field in a entry lies within a range
+///
+/// Both bounds are inclusive and can be left open. The header field is read with
+/// `EntryHeader::read_datetime()`, entries where it is missing or no datetime do not match.
+pub struct FieldDateRange {
+    header_field_path: FieldPath,
+    start: Option<DateTime<Utc>>,
+    end: Option<DateTime<Utc>>,
+}
+
+impl FieldDateRange {
+
+    pub fn new(path: FieldPath, start: Option<DateTime<Utc>>, end: Option<DateTime<Utc>>)
+        -> FieldDateRange
+    {
+        FieldDateRange {
+            header_field_path: path,
+            start: start,
+            end: end,
+        }
+    }
+
+    /// Check whether the field is at `start` or later
+    pub fn after(path: FieldPath, start: DateTime<Utc>) -> FieldDateRange {
+        FieldDateRange::new(path, Some(start), None)
+    }
+
+    /// Check whether the field is at `end` or earlier
+    pub fn before(path: FieldPath, end: DateTime<Utc>) -> FieldDateRange {
+        FieldDateRange::new(path, None, Some(end))
+    }
+
+}
+
+impl Filter for FieldDateRange {
+
+    fn filter(&self, e: &Entry) -> bool {
+        match e.get_header().read_datetime(&self.header_field_path[..]) {
+            Ok(Some(date)) => {
+                self.start.map(|start| start <= date).unwrap_or(true) &&
+                    self.end.map(|end| date <= end).unwrap_or(true)
+            },
+            _ => false,
+        }
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use chrono::{DateTime, Utc};
+
+    use libimagstore::store::Entry;
+
+    use super::FieldDateRange;
+    use filter::Filter;
+
+    fn entry(date: &str) -> Entry {
+        let text = format!("---\n[imag]\nversion = \"0.1.0\"\n[note]\ndate = {}\n---\n", date);
+        Entry::from_str(PathBuf::from("/store/test~0.1.0"), &text[..]).unwrap()
+    }
+
+    fn date(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn range(start: Option<&str>, end: Option<&str>) -> FieldDateRange {
+        FieldDateRange::new(String::from("note.date"), start.map(date), end.map(date))
+    }
+
+    #[test]
+    fn test_field_date_range() {
+        let native = entry("2016-05-01T12:00:00Z");
+        let string = entry("\"2016-05-01T14:00:00+02:00\"");
+
+        for e in &[native, string] {
+            assert!(range(Some("2016-05-01T00:00:00Z"), Some("2016-05-02T00:00:00Z")).filter(e));
+            assert!(range(Some("2016-05-01T12:00:00Z"), Some("2016-05-01T12:00:00Z")).filter(e));
+            assert!(!range(Some("2016-05-02T00:00:00Z"), Some("2016-05-03T00:00:00Z")).filter(e));
+            assert!(range(None, None).filter(e));
+
+            let start = date("2016-04-30T00:00:00Z");
+            assert!(FieldDateRange::after(String::from("note.date"), start).filter(e));
+            assert!(!FieldDateRange::before(String::from("note.date"), start).filter(e));
+        }
+    }
+
+    #[test]
+    fn test_field_date_range_no_date() {
+        assert!(!range(None, None).filter(&entry("\"yesterday\"")));
+        assert!(!range(None, None).filter(&entry("1")));
+
+        let e = entry("2016-05-01T12:00:00Z");
+        let missing = FieldDateRange::new(String::from("note.missing"), None, None);
+        assert!(!missing.filter(&e));
+    }
+
+}
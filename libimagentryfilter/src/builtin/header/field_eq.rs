@@ -14,12 +14,19 @@ struct EqPred {
 impl Predicate for EqPred {
 
     fn evaluate(&self, v: Value) -> bool {
-        self.expected == v
+        match (&self.expected, &v) {
+            (&Value::Integer(i), &Value::Float(f)) |
+            (&Value::Float(f), &Value::Integer(i)) => (i as f64) == f,
+            (expected, v) => expected == v,
+        }
     }
 
 }
 
 /// Check whether certain header field in a entry is equal to a value
+///
+/// Integers and floats are compared by their numeric value, so `1` equals `1.0`. All other values
+/// are only equal to values of the same type. Entries without the header field do not match.
 pub struct FieldEq {
     filter: FieldPredicate<EqPred>,
 }
@@ -42,3 +49,51 @@ impl Filter for FieldEq {
 
 }
 
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+    use toml::Value;
+
+    use super::FieldEq;
+    use filter::Filter;
+
+    fn entry() -> Entry {
+        let text = "---\n[imag]\nversion = \"0.1.0\"\n\
+                    [test]\nstring = \"a\"\nint = 1\nfloat = 2.0\nbool = true\n\
+                    date = 2016-05-01T12:00:00Z\n---\n";
+        Entry::from_str(PathBuf::from("/store/test~0.1.0"), text).unwrap()
+    }
+
+    fn eq(path: &str, value: Value) -> bool {
+        FieldEq::new(String::from(path), value).filter(&entry())
+    }
+
+    #[test]
+    fn test_field_eq_scalars() {
+        assert!(eq("test.string", Value::String(String::from("a"))));
+        assert!(!eq("test.string", Value::String(String::from("b"))));
+        assert!(eq("test.int", Value::Integer(1)));
+        assert!(eq("test.float", Value::Float(2.0)));
+        assert!(eq("test.bool", Value::Boolean(true)));
+        assert!(!eq("test.bool", Value::Boolean(false)));
+        assert!(eq("test.date", Value::Datetime(String::from("2016-05-01T12:00:00Z"))));
+    }
+
+    #[test]
+    fn test_field_eq_numbers_and_types() {
+        assert!(eq("test.int", Value::Float(1.0)));
+        assert!(eq("test.float", Value::Integer(2)));
+        assert!(!eq("test.int", Value::Float(1.5)));
+        assert!(!eq("test.int", Value::String(String::from("1"))));
+        assert!(!eq("test.bool", Value::Integer(1)));
+    }
+
+    #[test]
+    fn test_field_eq_absent() {
+        assert!(!eq("test.missing", Value::Integer(1)));
+        assert!(!eq("missing.int", Value::Integer(1)));
+    }
+
+}
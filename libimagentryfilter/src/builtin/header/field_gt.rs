@@ -17,15 +17,15 @@ impl Predicate for EqGt {
         match &self.comp {
             &Value::Integer(i) => {
                 match v {
-                    Value::Integer(j) => i > j,
-                    Value::Float(f) => (i as f64) > f,
+                    Value::Integer(j) => j > i,
+                    Value::Float(f) => f > (i as f64),
                     _ => false,
                 }
             },
             &Value::Float(f) => {
                 match v {
-                    Value::Integer(i) => f > (i as f64),
-                    Value::Float(d) => f > d,
+                    Value::Integer(i) => (i as f64) > f,
+                    Value::Float(d) => d > f,
                     _ => false,
                 }
             },
@@ -35,7 +35,11 @@ impl Predicate for EqGt {
 
 }
 
-/// Check whether certain header field in a entry is equal to a value
+/// Check whether certain numeric header field in a entry is greater than a value
+///
+/// Matches entries whose field at `path` is greater than `expected_value`, that is `field > value`.
+/// Integers and floats can be compared with each other, entries where the field is missing or not
+/// a number do not match.
 pub struct FieldGt {
     filter: FieldPredicate<EqGt>,
 }
@@ -58,3 +62,39 @@ impl Filter for FieldGt {
 
 }
 
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+    use toml::Value;
+
+    use super::FieldGt;
+    use filter::Filter;
+
+    fn gt(field: &str, value: Value) -> bool {
+        let text = format!("---\n[imag]\nversion = \"0.1.0\"\n[test]\nfield = {}\n---\n",
+                           field);
+        let entry = Entry::from_str(PathBuf::from("/store/test~0.1.0"), &text[..]).unwrap();
+        FieldGt::new(String::from("test.field"), value).filter(&entry)
+    }
+
+    #[test]
+    fn test_field_gt() {
+        assert!(gt("4", Value::Integer(3)));
+        assert!(!gt("3", Value::Integer(3)));
+        assert!(!gt("2", Value::Integer(3)));
+        assert!(gt("3.5", Value::Integer(3)));
+        assert!(gt("4", Value::Float(3.5)));
+        assert!(!gt("2.5", Value::Float(3.5)));
+        assert!(!gt("\"2\"", Value::Integer(3)));
+    }
+
+    #[test]
+    fn test_field_gt_missing() {
+        let text = "---\n[imag]\nversion = \"0.1.0\"\n---\n";
+        let entry = Entry::from_str(PathBuf::from("/store/test~0.1.0"), text).unwrap();
+        assert!(!FieldGt::new(String::from("test.field"), Value::Integer(3)).filter(&entry));
+    }
+
+}
@@ -1,3 +1,5 @@
+pub mod archived;
+pub mod field_date;
 pub mod field_eq;
 pub mod field_exists;
 pub mod field_grep;
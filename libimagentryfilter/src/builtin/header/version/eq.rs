@@ -42,3 +42,14 @@ impl Filter for VersionEq {
 
 }
 
+#[cfg(test)]
+mod test {
+    use builtin::header::version::test::check_version_filter;
+    use super::VersionEq;
+
+    #[test]
+    fn test_version_eq() {
+        check_version_filter(VersionEq::new, false, true, false);
+    }
+
+}
@@ -42,5 +42,14 @@ impl Filter for VersionGt {
 
 }
 
+#[cfg(test)]
+mod test {
+    use builtin::header::version::test::check_version_filter;
+    use super::VersionGt;
+
+    #[test]
+    fn test_version_gt() {
+        check_version_filter(VersionGt::new, false, false, true);
+    }
 
-
+}
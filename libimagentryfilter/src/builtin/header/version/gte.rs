@@ -0,0 +1,54 @@
+use semver::Version;
+use toml::Value;
+
+use libimagstore::store::Entry;
+
+use filter::Filter;
+
+pub struct VersionGte {
+    version: Version,
+}
+
+impl VersionGte {
+
+    pub fn new(version: Version) -> VersionGte {
+        VersionGte { version: version }
+    }
+
+}
+
+impl Filter for VersionGte {
+
+    fn filter(&self, e: &Entry) -> bool {
+        e.get_header()
+            .read("imag.version")
+            .map(|val| {
+                val.map(|v| {
+                    match v {
+                        Value::String(s) => {
+                            match Version::parse(&s[..]) {
+                                Ok(v) => v >= self.version,
+                                _ => false
+                            }
+                        },
+                        _ => false,
+                    }
+                })
+                .unwrap_or(false)
+            })
+            .unwrap_or(false)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use builtin::header::version::test::check_version_filter;
+    use super::VersionGte;
+
+    #[test]
+    fn test_version_gte() {
+        check_version_filter(VersionGte::new, false, true, true);
+    }
+
+}
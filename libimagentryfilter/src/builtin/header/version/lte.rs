@@ -0,0 +1,54 @@
+use semver::Version;
+use toml::Value;
+
+use libimagstore::store::Entry;
+
+use filter::Filter;
+
+pub struct VersionLte {
+    version: Version,
+}
+
+impl VersionLte {
+
+    pub fn new(version: Version) -> VersionLte {
+        VersionLte { version: version }
+    }
+
+}
+
+impl Filter for VersionLte {
+
+    fn filter(&self, e: &Entry) -> bool {
+        e.get_header()
+            .read("imag.version")
+            .map(|val| {
+                val.map(|v| {
+                    match v {
+                        Value::String(s) => {
+                            match Version::parse(&s[..]) {
+                                Ok(v) => v <= self.version,
+                                _ => false
+                            }
+                        },
+                        _ => false,
+                    }
+                })
+                .unwrap_or(false)
+            })
+            .unwrap_or(false)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use builtin::header::version::test::check_version_filter;
+    use super::VersionLte;
+
+    #[test]
+    fn test_version_lte() {
+        check_version_filter(VersionLte::new, true, true, false);
+    }
+
+}
@@ -1,4 +1,41 @@
 pub mod eq;
 pub mod gt;
+pub mod gte;
 pub mod lt;
+pub mod lte;
 pub mod range;
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use semver::Version;
+    use toml::Value;
+
+    use libimagstore::store::Entry;
+
+    use filter::Filter;
+
+    /// Check a version filter built by `new` against an entry with version `0.2.0`
+    ///
+    /// The filter is expected to match if the entry version is less than, equal to or greater
+    /// than the version of the filter as given. Entries with an invalid or missing version never
+    /// match.
+    pub fn check_version_filter<F, T>(new: F, less: bool, equal: bool, greater: bool)
+        where F: Fn(Version) -> T,
+              T: Filter
+    {
+        let mut e = Entry::new(PathBuf::from("test~0.2.0"));
+        e.get_header_mut().set("imag.version", Value::String(String::from("0.2.0"))).unwrap();
+        assert_eq!(new(Version::parse("0.3.0").unwrap()).filter(&e), less);
+        assert_eq!(new(Version::parse("0.2.0").unwrap()).filter(&e), equal);
+        assert_eq!(new(Version::parse("0.1.0").unwrap()).filter(&e), greater);
+
+        e.get_header_mut().set("imag.version", Value::String(String::from("no version"))).unwrap();
+        assert!(!new(Version::parse("0.2.0").unwrap()).filter(&e));
+
+        e.get_header_mut().delete("imag.version").unwrap();
+        assert!(!new(Version::parse("0.2.0").unwrap()).filter(&e));
+    }
+
+}
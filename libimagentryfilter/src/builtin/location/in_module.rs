@@ -0,0 +1,78 @@
+use std::path::Component;
+
+use libimagstore::store::Entry;
+
+use filter::Filter;
+
+/// Check whether an Entry lives inside a certain module
+///
+/// As an entry does not know the path of the store it lives in, this checks whether one of the
+/// directories the entry is located in is named like the module. Use `PathPrefix` if the exact
+/// location is known.
+pub struct InModule {
+    module: String,
+}
+
+impl InModule {
+
+    pub fn new(module: String) -> InModule {
+        InModule {
+            module: module,
+        }
+    }
+
+}
+
+impl Filter for InModule {
+
+    fn filter(&self, e: &Entry) -> bool {
+        e.get_location()
+            .parent()
+            .map(|dir| {
+                dir.components().any(|c| match c {
+                    Component::Normal(name) => name.to_str() == Some(&self.module[..]),
+                    _ => false,
+                })
+            })
+            .unwrap_or(false)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::InModule;
+    use filter::Filter;
+
+    static TEST_ENTRY : &'static str = "---
+[imag]
+version = \"0.1.0\"
+---
+";
+
+    fn entry_at(loc: &str) -> Entry {
+        Entry::from_str(PathBuf::from(loc), TEST_ENTRY).unwrap()
+    }
+
+    #[test]
+    fn test_in_module() {
+        let diary = entry_at("/home/user/.imag/store/diary/2016/01/02~0.1.0");
+        let notes = entry_at("/home/user/.imag/store/notes/diary-thoughts~0.1.0");
+
+        assert!(InModule::new(String::from("diary")).filter(&diary));
+        assert!(!InModule::new(String::from("diary")).filter(&notes));
+        assert!(InModule::new(String::from("notes")).filter(&notes));
+    }
+
+    #[test]
+    fn test_in_module_ignores_file_name() {
+        let entry = entry_at("/store/notes/diary");
+
+        assert!(!InModule::new(String::from("diary")).filter(&entry));
+    }
+
+}
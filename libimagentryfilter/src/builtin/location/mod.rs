@@ -0,0 +1,2 @@
+pub mod in_module;
+pub mod path_prefix;
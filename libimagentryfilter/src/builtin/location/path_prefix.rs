@@ -0,0 +1,56 @@
+use std::path::PathBuf;
+
+use libimagstore::store::Entry;
+
+use filter::Filter;
+
+/// Check whether the location of an Entry starts with a certain path
+pub struct PathPrefix {
+    prefix: PathBuf,
+}
+
+impl PathPrefix {
+
+    pub fn new(prefix: PathBuf) -> PathPrefix {
+        PathPrefix {
+            prefix: prefix,
+        }
+    }
+
+}
+
+impl Filter for PathPrefix {
+
+    fn filter(&self, e: &Entry) -> bool {
+        e.get_location().starts_with(&self.prefix)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::PathPrefix;
+    use filter::Filter;
+
+    static TEST_ENTRY : &'static str = "---
+[imag]
+version = \"0.1.0\"
+---
+";
+
+    #[test]
+    fn test_path_prefix() {
+        let entry = Entry::from_str(PathBuf::from("/store/diary/2016/01/02~0.1.0"), TEST_ENTRY)
+            .unwrap();
+
+        assert!(PathPrefix::new(PathBuf::from("/store/diary")).filter(&entry));
+        assert!(PathPrefix::new(PathBuf::from("/store/diary/2016")).filter(&entry));
+        assert!(!PathPrefix::new(PathBuf::from("/store/notes")).filter(&entry));
+        assert!(!PathPrefix::new(PathBuf::from("/store/dia")).filter(&entry));
+    }
+
+}
@@ -1,2 +1,3 @@
 pub mod content;
 pub mod header;
+pub mod location;
@@ -0,0 +1,31 @@
+use clap::{Arg, ArgMatches};
+
+use compiler::lexer::tokenize;
+use compiler::parser::parse;
+use error::FilterCompilerError;
+use filter::Filter;
+
+/// Compile a filter expression, see `compiler::parser::parse()` for the syntax
+pub fn compile(source: &str) -> Result<Box<Filter>, FilterCompilerError> {
+    tokenize(source).and_then(parse)
+}
+
+pub fn build_filter_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name(filter_arg_name())
+        .long("filter")
+        .takes_value(true)
+        .required(false)
+        .multiple(false)
+        .value_name("EXPR")
+        .help("Only use entries matching EXPR, for example \
+               'header.imag.version >= 0.1.0 && content ~ \"todo\"'")
+}
+
+pub fn filter_arg_name() -> &'static str {
+    "filter"
+}
+
+/// Compile the filter expression passed via the argument from `build_filter_arg()`, if any
+pub fn filter_from_matches(m: &ArgMatches) -> Option<Result<Box<Filter>, FilterCompilerError>> {
+    m.value_of(filter_arg_name()).map(compile)
+}
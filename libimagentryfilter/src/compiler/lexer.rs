@@ -0,0 +1,210 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use error::{FilterCompilerError as FCE, FilterCompilerErrorKind as FCEK};
+
+pub type Result<T> = ::std::result::Result<T, FCE>;
+
+/// A token of the filter expression language
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// A bare word like `content` or `header.imag.version`
+    Ident(String),
+
+    /// A string literal, either quoted or a number-like word which is not a number, like `0.1.0`
+    Str(String),
+    Integer(i64),
+    Float(f64),
+
+    Eq,         // ==
+    Neq,        // !=
+    Lt,         // <
+    Lte,        // <=
+    Gt,         // >
+    Gte,        // >=
+    Contains,   // ~
+    Matches,    // =~
+    And,        // &&
+    Or,         // ||
+    Not,        // !
+    LParen,     // (
+    RParen,     // )
+}
+
+/// Split a filter expression into tokens
+///
+/// String literals can be quoted with `"` or `'` and may contain whitespace. Inside them, `\"`,
+/// `\'` and `\\` escape the quote characters and the backslash itself.
+pub fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars  = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        let token = match c {
+            c if c.is_whitespace() => {
+                chars.next();
+                continue;
+            },
+
+            '"' | '\'' => {
+                chars.next();
+                try!(read_string(&mut chars, c))
+            },
+
+            '(' => { chars.next(); Token::LParen },
+            ')' => { chars.next(); Token::RParen },
+            '~' => { chars.next(); Token::Contains },
+
+            '=' => {
+                chars.next();
+                match chars.next() {
+                    Some('=') => Token::Eq,
+                    Some('~') => Token::Matches,
+                    _ => return Err(FCE::new(FCEK::UnexpectedCharacter, None)),
+                }
+            },
+
+            '!' => {
+                chars.next();
+                if chars.peek() == Some(&'=') {
+                    chars.next();
+                    Token::Neq
+                } else {
+                    Token::Not
+                }
+            },
+
+            '<' | '>' => {
+                chars.next();
+                let eq = chars.peek() == Some(&'=');
+                if eq {
+                    chars.next();
+                }
+                match (c, eq) {
+                    ('<', false) => Token::Lt,
+                    ('<', true)  => Token::Lte,
+                    (_, false)   => Token::Gt,
+                    (_, true)    => Token::Gte,
+                }
+            },
+
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Some(c) {
+                    return Err(FCE::new(FCEK::UnexpectedCharacter, None));
+                }
+                if c == '&' { Token::And } else { Token::Or }
+            },
+
+            c if c == '-' || c.is_digit(10) => read_number(&mut chars),
+            c if c == '_' || c.is_alphabetic() => Token::Ident(read_word(&mut chars)),
+
+            _ => {
+                debug!("Unexpected character in filter: '{}'", c);
+                return Err(FCE::new(FCEK::UnexpectedCharacter, None));
+            },
+        };
+
+        tokens.push(token);
+    }
+
+    Ok(tokens)
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || c == '-'
+}
+
+fn read_word(chars: &mut Peekable<Chars>) -> String {
+    let mut s = String::new();
+    while let Some(&c) = chars.peek() {
+        if !is_word_char(c) {
+            break;
+        }
+        s.push(c);
+        chars.next();
+    }
+    s
+}
+
+fn read_number(chars: &mut Peekable<Chars>) -> Token {
+    use std::str::FromStr;
+
+    let word = read_word(chars);
+    i64::from_str(&word[..])
+        .map(Token::Integer)
+        .or_else(|_| f64::from_str(&word[..]).map(Token::Float))
+        .unwrap_or(Token::Str(word))
+}
+
+/// Read a string literal, the opening `quote` is already consumed
+fn read_string(chars: &mut Peekable<Chars>, quote: char) -> Result<Token> {
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            None => return Err(FCE::new(FCEK::UnterminatedString, None)),
+            Some(c) if c == quote => return Ok(Token::Str(s)),
+            Some('\\') => {
+                match chars.next() {
+                    Some(c @ '"') | Some(c @ '\'') | Some(c @ '\\') => s.push(c),
+                    Some(c) => {
+                        debug!("Invalid escape sequence in filter: '\\{}'", c);
+                        return Err(FCE::new(FCEK::InvalidEscape, None));
+                    },
+                    None => return Err(FCE::new(FCEK::UnterminatedString, None)),
+                }
+            },
+            Some(c) => s.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{tokenize, Token};
+    use error::FilterCompilerErrorKind as FCEK;
+
+    fn ident(s: &str) -> Token {
+        Token::Ident(String::from(s))
+    }
+
+    fn string(s: &str) -> Token {
+        Token::Str(String::from(s))
+    }
+
+    #[test]
+    fn test_quoted_multi_word_values() {
+        assert_eq!(tokenize("content ~ \"hello world\"").unwrap(),
+                   vec![ident("content"), Token::Contains, string("hello world")]);
+        assert_eq!(tokenize("title == 'My Note'").unwrap(),
+                   vec![ident("title"), Token::Eq, string("My Note")]);
+    }
+
+    #[test]
+    fn test_escaped_quotes() {
+        assert_eq!(tokenize(r#"title == "say \"hi\" \\ o'clock""#).unwrap(),
+                   vec![ident("title"), Token::Eq, string("say \"hi\" \\ o'clock")]);
+        assert_eq!(tokenize(r"title == 'it\'s'").unwrap(),
+                   vec![ident("title"), Token::Eq, string("it's")]);
+    }
+
+    #[test]
+    fn test_operators_and_numbers() {
+        assert_eq!(tokenize("!(header.a.b >= -2 || header.c<1.5)&&version != 0.1.0").unwrap(),
+                   vec![Token::Not, Token::LParen,
+                   ident("header.a.b"), Token::Gte, Token::Integer(-2), Token::Or,
+                   ident("header.c"), Token::Lt, Token::Float(1.5),
+                   Token::RParen, Token::And,
+                   ident("version"), Token::Neq, string("0.1.0")]);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(tokenize("title == \"unterminated").unwrap_err().err_type(),
+                   FCEK::UnterminatedString);
+        assert_eq!(tokenize(r#"title == "\n""#).unwrap_err().err_type(), FCEK::InvalidEscape);
+        assert_eq!(tokenize("a & b").unwrap_err().err_type(), FCEK::UnexpectedCharacter);
+        assert_eq!(tokenize("a = b").unwrap_err().err_type(), FCEK::UnexpectedCharacter);
+    }
+
+}
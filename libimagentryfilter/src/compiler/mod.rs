@@ -0,0 +1,6 @@
+//! Compiler for the filter expression language
+//!
+//! Expressions look like `version > 0.1.0 && content ~ "hello world"`, see `parser::parse()`.
+
+pub mod lexer;
+pub mod parser;
@@ -0,0 +1,263 @@
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use regex::{Regex, quote};
+use semver::Version;
+use toml::Value;
+
+use builtin::content::grep::ContentGrep;
+use builtin::header::field_eq::FieldEq;
+use builtin::header::field_grep::FieldGrep;
+use builtin::header::field_gt::FieldGt;
+use builtin::header::field_lt::FieldLt;
+use builtin::header::version::eq::VersionEq;
+use builtin::header::version::gt::VersionGt;
+use builtin::header::version::gte::VersionGte;
+use builtin::header::version::lt::VersionLt;
+use builtin::header::version::lte::VersionLte;
+use compiler::lexer::{Result, Token};
+use error::{FilterCompilerError as FCE, FilterCompilerErrorKind as FCEK};
+use filter::Filter;
+use ops::and::And;
+use ops::not::Not;
+use ops::or::Or;
+
+type Tokens = Peekable<IntoIter<Token>>;
+
+/// Build a filter from the tokens of a filter expression
+///
+/// The grammar is, with `&&` binding stronger than `||`:
+///
+/// ```text
+/// expr       := and ( "||" and )*
+/// and        := unary ( "&&" unary )*
+/// unary      := "!" unary | "(" expr ")" | comparison
+/// comparison := field operator value
+/// ```
+///
+/// The fields are `header.<path>`, `version` (`imag.version`, compared as semver) and `content`.
+/// Header fields can be compared with `==`, `!=`, `<`, `<=`, `>`, `>=` and, if they are strings,
+/// with `~` (contains) and `=~` (matches regex). Missing header fields never match, except for
+/// `!=`.
+///
+/// Values are numbers, `true` and `false`, or strings. Strings without whitespace or special
+/// characters do not have to be quoted.
+pub fn parse(tokens: Vec<Token>) -> Result<Box<Filter>> {
+    let mut tokens = tokens.into_iter().peekable();
+    let filter = try!(parse_or(&mut tokens));
+    match tokens.next() {
+        None        => Ok(filter),
+        Some(token) => unexpected(token),
+    }
+}
+
+fn parse_or(tokens: &mut Tokens) -> Result<Box<Filter>> {
+    let mut filter = try!(parse_and(tokens));
+    while tokens.peek() == Some(&Token::Or) {
+        tokens.next();
+        filter = Box::new(Or::new(filter, try!(parse_and(tokens))));
+    }
+    Ok(filter)
+}
+
+fn parse_and(tokens: &mut Tokens) -> Result<Box<Filter>> {
+    let mut filter = try!(parse_unary(tokens));
+    while tokens.peek() == Some(&Token::And) {
+        tokens.next();
+        filter = Box::new(And::new(filter, try!(parse_unary(tokens))));
+    }
+    Ok(filter)
+}
+
+fn parse_unary(tokens: &mut Tokens) -> Result<Box<Filter>> {
+    match tokens.next() {
+        Some(Token::Not) => parse_unary(tokens).map(|f| Box::new(Not::new(f)) as Box<Filter>),
+        Some(Token::LParen) => {
+            let filter = try!(parse_or(tokens));
+            match tokens.next() {
+                Some(Token::RParen) => Ok(filter),
+                Some(token)         => unexpected(token),
+                None                => Err(FCE::new(FCEK::UnexpectedEnd, None)),
+            }
+        },
+        Some(Token::Ident(field)) => {
+            let op    = try!(tokens.next().ok_or(FCE::new(FCEK::UnexpectedEnd, None)));
+            let value = try!(tokens.next().ok_or(FCE::new(FCEK::UnexpectedEnd, None)));
+            comparison(field, op, value)
+        },
+        Some(token) => unexpected(token),
+        None        => Err(FCE::new(FCEK::UnexpectedEnd, None)),
+    }
+}
+
+fn comparison(field: String, op: Token, value: Token) -> Result<Box<Filter>> {
+    if field == "content" {
+        content_comparison(op, value)
+    } else if field == "version" {
+        version_comparison(op, value)
+    } else if field.starts_with("header.") && field.len() > "header.".len() {
+        header_comparison(String::from(&field["header.".len()..]), op, value)
+    } else {
+        debug!("Unknown field in filter: '{}'", field);
+        Err(FCE::new(FCEK::UnknownField, None))
+    }
+}
+
+fn content_comparison(op: Token, value: Token) -> Result<Box<Filter>> {
+    let pattern = match (&op, string_value(value)) {
+        (&Token::Contains, Some(s)) => quote(&s[..]),
+        (&Token::Matches, Some(s))  => s,
+        (&Token::Contains, None) | (&Token::Matches, None) => {
+            return Err(FCE::new(FCEK::TypeMismatch, None))
+        },
+        _ => return invalid_operator("content", op),
+    };
+
+    ContentGrep::from_pattern(&pattern[..]).map(|f| Box::new(f) as Box<Filter>)
+}
+
+fn version_comparison(op: Token, value: Token) -> Result<Box<Filter>> {
+    let version = match string_value(value) {
+        Some(s) => try!(Version::parse(&s[..]).map_err(|_| FCE::new(FCEK::TypeMismatch, None))),
+        None    => return Err(FCE::new(FCEK::TypeMismatch, None)),
+    };
+
+    let eq = || Box::new(VersionEq::new(version.clone()));
+    Ok(match op {
+        Token::Eq  => eq(),
+        Token::Neq => Box::new(Not::new(eq())),
+        Token::Lt  => Box::new(VersionLt::new(version.clone())),
+        Token::Gt  => Box::new(VersionGt::new(version.clone())),
+        Token::Lte => Box::new(VersionLte::new(version.clone())),
+        Token::Gte => Box::new(VersionGte::new(version.clone())),
+        _ => return invalid_operator("version", op),
+    })
+}
+
+fn header_comparison(path: String, op: Token, value: Token) -> Result<Box<Filter>> {
+    let value = match value {
+        Token::Str(s)     => Value::String(s),
+        Token::Integer(i) => Value::Integer(i),
+        Token::Float(f)   => Value::Float(f),
+        Token::Ident(ref s) if s == "true"  => Value::Boolean(true),
+        Token::Ident(ref s) if s == "false" => Value::Boolean(false),
+        Token::Ident(s)   => Value::String(s),
+        token => return unexpected(token),
+    };
+
+    let is_number = match value {
+        Value::Integer(_) | Value::Float(_) => true,
+        _ => false,
+    };
+
+    let eq = || Box::new(FieldEq::new(path.clone(), value.clone()));
+    Ok(match op {
+        Token::Eq  => eq(),
+        Token::Neq => Box::new(Not::new(eq())),
+
+        Token::Lt | Token::Lte | Token::Gt | Token::Gte if !is_number => {
+            debug!("Header field '{}' can only be compared with a number", path);
+            return Err(FCE::new(FCEK::TypeMismatch, None))
+        },
+        Token::Lt  => Box::new(FieldLt::new(path.clone(), value.clone())),
+        Token::Gt  => Box::new(FieldGt::new(path.clone(), value.clone())),
+        Token::Lte => Box::new(Or::new(Box::new(FieldLt::new(path.clone(), value.clone())), eq())),
+        Token::Gte => Box::new(Or::new(Box::new(FieldGt::new(path.clone(), value.clone())), eq())),
+
+        Token::Contains | Token::Matches => {
+            let pattern = match (&op, &value) {
+                (&Token::Contains, &Value::String(ref s)) => quote(&s[..]),
+                (&Token::Matches, &Value::String(ref s))  => s.clone(),
+                _ => return Err(FCE::new(FCEK::TypeMismatch, None)),
+            };
+            let regex = try!(Regex::new(&pattern[..])
+                             .map_err(|e| FCE::new(FCEK::InvalidRegex, Some(Box::new(e)))));
+            Box::new(FieldGrep::new(path.clone(), regex))
+        },
+
+        _ => return invalid_operator("header", op),
+    })
+}
+
+/// Get the string of a quoted string or of a bare word
+fn string_value(token: Token) -> Option<String> {
+    match token {
+        Token::Str(s) | Token::Ident(s) => Some(s),
+        _ => None,
+    }
+}
+
+fn unexpected<T>(token: Token) -> Result<T> {
+    debug!("Unexpected token in filter: {:?}", token);
+    Err(FCE::new(FCEK::UnexpectedToken, None))
+}
+
+fn invalid_operator<T>(field: &str, op: Token) -> Result<T> {
+    debug!("Operator {:?} cannot be used with '{}'", op, field);
+    Err(FCE::new(FCEK::InvalidOperator, None))
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use cli::compile;
+    use error::FilterCompilerErrorKind as FCEK;
+
+    fn entry(header: &str, content: &str) -> Entry {
+        let s = format!("---\n[imag]\nversion = \"0.2.0\"\n{}---\n{}", header, content);
+        Entry::from_str(PathBuf::from("test~0.2.0"), &s).unwrap()
+    }
+
+    fn matches(filter: &str, e: &Entry) -> bool {
+        compile(filter).unwrap().filter(e)
+    }
+
+    #[test]
+    fn test_comparisons() {
+        let e = entry("[note]\nname = \"groceries\"\npriority = 3\n", "buy milk\n");
+
+        assert!(matches("header.note.name == \"groceries\"", &e));
+        assert!(matches("header.note.name != 'shopping'", &e));
+        assert!(matches("header.note.priority < 4", &e));
+        assert!(!matches("header.note.priority < 3", &e));
+        assert!(matches("header.note.priority <= 3", &e));
+        assert!(matches("header.note.priority > 2.5", &e));
+        assert!(!matches("header.note.missing >= 0", &e));
+        assert!(matches("header.note.name ~ roce", &e));
+        assert!(matches("content =~ '^buy (milk|bread)'", &e));
+        assert!(matches("version > 0.1.0", &e));
+        assert!(!matches("version < 0.2.0", &e));
+    }
+
+    #[test]
+    fn test_operators_and_precedence() {
+        let e = entry("[note]\npriority = 3\n", "buy milk\n");
+
+        assert!(matches("content ~ milk && header.note.priority == 3", &e));
+        assert!(!matches("!(content ~ milk)", &e));
+        assert!(matches("content ~ bread || content ~ milk && version == 0.2.0", &e));
+        assert!(!matches("(content ~ bread || content ~ milk) && version == 0.1.0", &e));
+        assert!(matches("!!(header.note.priority >= 3)", &e));
+    }
+
+    #[test]
+    fn test_errors() {
+        fn err(filter: &str) -> FCEK {
+            compile(filter).err().unwrap().err_type()
+        }
+
+        assert_eq!(err("title == 'x'"), FCEK::UnknownField);
+        assert_eq!(err("header. == 'x'"), FCEK::UnknownField);
+        assert_eq!(err("header.a < 'x'"), FCEK::TypeMismatch);
+        assert_eq!(err("version == 1"), FCEK::TypeMismatch);
+        assert_eq!(err("content == 'x'"), FCEK::InvalidOperator);
+        assert_eq!(err("content =~ '('"), FCEK::InvalidRegex);
+        assert_eq!(err("(content ~ x"), FCEK::UnexpectedEnd);
+        assert_eq!(err("content ~ x)"), FCEK::UnexpectedToken);
+        assert_eq!(err("content ~ x &&"), FCEK::UnexpectedEnd);
+    }
+
+}
@@ -0,0 +1,97 @@
+use std::error::Error;
+use std::fmt::Error as FmtError;
+use std::clone::Clone;
+use std::fmt::{Display, Formatter};
+
+/**
+ * Kind of error
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterCompilerErrorKind {
+    UnexpectedCharacter,
+    UnterminatedString,
+    InvalidEscape,
+    UnexpectedToken,
+    UnexpectedEnd,
+    UnknownField,
+    InvalidOperator,
+    TypeMismatch,
+    InvalidRegex,
+}
+
+fn filter_compiler_error_type_as_str(err: &FilterCompilerErrorKind) -> &'static str {
+    match err {
+        &FilterCompilerErrorKind::UnexpectedCharacter => "Unexpected character in filter",
+        &FilterCompilerErrorKind::UnterminatedString  => "Unterminated string in filter",
+        &FilterCompilerErrorKind::InvalidEscape       => "Invalid escape sequence in filter",
+        &FilterCompilerErrorKind::UnexpectedToken     => "Unexpected token in filter",
+        &FilterCompilerErrorKind::UnexpectedEnd       => "Unexpected end of filter",
+        &FilterCompilerErrorKind::UnknownField        => "Unknown field in filter, expected \
+                                                          'content', 'version' or 'header.<path>'",
+        &FilterCompilerErrorKind::InvalidOperator     => "Operator cannot be used with this field",
+        &FilterCompilerErrorKind::TypeMismatch        => "Value has the wrong type for comparison",
+        &FilterCompilerErrorKind::InvalidRegex        => "Invalid regular expression in filter",
+    }
+}
+
+impl Display for FilterCompilerErrorKind {
+
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), FmtError> {
+        try!(write!(fmt, "{}", filter_compiler_error_type_as_str(self)));
+        Ok(())
+    }
+
+}
+
+/**
+ * Filter compiler error type
+ */
+#[derive(Debug)]
+pub struct FilterCompilerError {
+    err_type: FilterCompilerErrorKind,
+    cause: Option<Box<Error>>,
+}
+
+impl FilterCompilerError {
+
+    /**
+     * Build a new FilterCompilerError from an FilterCompilerErrorKind, optionally with cause
+     */
+    pub fn new(errtype: FilterCompilerErrorKind, cause: Option<Box<Error>>)
+        -> FilterCompilerError
+    {
+        FilterCompilerError {
+            err_type: errtype,
+            cause: cause,
+        }
+    }
+
+    /**
+     * Get the error type of this FilterCompilerError
+     */
+    pub fn err_type(&self) -> FilterCompilerErrorKind {
+        self.err_type.clone()
+    }
+
+}
+
+impl Display for FilterCompilerError {
+
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), FmtError> {
+        try!(write!(fmt, "[{}]", filter_compiler_error_type_as_str(&self.err_type.clone())));
+        Ok(())
+    }
+
+}
+
+impl Error for FilterCompilerError {
+
+    fn description(&self) -> &str {
+        filter_compiler_error_type_as_str(&self.err_type.clone())
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        self.cause.as_ref().map(|e| &**e)
+    }
+
+}
@@ -1,5 +1,7 @@
 #[macro_use] extern crate log;
 
+extern crate chrono;
+extern crate clap;
 extern crate itertools;
 extern crate regex;
 extern crate toml;
@@ -13,6 +15,8 @@ extern crate libimagtag;
 
 pub mod cli;
 pub mod builtin;
+pub mod compiler;
+pub mod error;
 pub mod filter;
 pub mod ops;
 
@@ -1,10 +1,14 @@
 use clap::{Arg, ArgMatches, App, SubCommand};
 
 use libimagstore::store::FileLockEntry;
+use libimagutil::preview::DEFAULT_MARKER;
 
 use result::Result;
+use listers::abbrev::{AbbrevLister, DEFAULT_ABBREV_LENGTH};
 use listers::line::LineLister;
 use listers::path::PathLister;
+use listers::plain::PlainLister;
+use listers::table::TableLister;
 use lister::Lister;
 use error::{ListError, ListErrorKind};
 
@@ -38,6 +42,63 @@ pub fn build_list_cli_component<'a, 'b>() -> App<'a, 'b> {
              .multiple(false)
              .help("Use backend: Path (absolute)"))
 
+        .arg(Arg::with_name(list_backend_plain())
+             .long("plain")
+             .takes_value(false)
+             .required(false)
+             .multiple(false)
+             .help("Use backend: Plain, printing the entries with header and content"))
+
+        .arg(Arg::with_name(list_backend_plain_content_only())
+             .long("plain-content")
+             .takes_value(false)
+             .required(false)
+             .multiple(false)
+             .help("Use backend: Plain, printing only the content of the entries"))
+
+        .arg(Arg::with_name(list_backend_table())
+             .short("t")
+             .long("table")
+             .takes_value(true)
+             .required(false)
+             .multiple(false)
+             .value_name("COLUMNS")
+             .help("Use backend: Table, with the comma separated header fields as columns. \
+                    'location' is the location of the entry"))
+
+        .arg(Arg::with_name(list_backend_abbrev())
+             .short("a")
+             .long("abbrev")
+             .takes_value(false)
+             .required(false)
+             .multiple(false)
+             .help("Use backend: Abbrev, printing the location and the beginning of the content"))
+
+        .arg(Arg::with_name(list_abbrev_length())
+             .long("abbrev-length")
+             .takes_value(true)
+             .required(false)
+             .multiple(false)
+             .value_name("N")
+             .help("Print at most N characters of the content with the abbrev backend \
+                    (default: 80)"))
+
+        .arg(Arg::with_name(list_abbrev_header())
+             .long("abbrev-header")
+             .takes_value(true)
+             .required(false)
+             .multiple(false)
+             .value_name("FIELDS")
+             .help("Print the comma separated header fields with the abbrev backend"))
+
+        .arg(Arg::with_name(list_abbrev_marker())
+             .long("abbrev-marker")
+             .takes_value(true)
+             .required(false)
+             .multiple(false)
+             .value_name("MARKER")
+             .help("End shortened content with MARKER with the abbrev backend (default: '…')"))
+
 }
 
 pub fn list_subcommand_name() -> &'static str {
@@ -56,6 +117,34 @@ pub fn list_backend_path_absolute() -> &'static str {
     "path-absolute"
 }
 
+pub fn list_backend_plain() -> &'static str {
+    "plain"
+}
+
+pub fn list_backend_plain_content_only() -> &'static str {
+    "plain-content"
+}
+
+pub fn list_backend_table() -> &'static str {
+    "table"
+}
+
+pub fn list_backend_abbrev() -> &'static str {
+    "abbrev"
+}
+
+pub fn list_abbrev_length() -> &'static str {
+    "abbrev-length"
+}
+
+pub fn list_abbrev_header() -> &'static str {
+    "abbrev-header"
+}
+
+pub fn list_abbrev_marker() -> &'static str {
+    "abbrev-marker"
+}
+
 // TODO: Add Registry for listers where a HashMap name->lister is in and where we can fetch the
 // lister from.
 pub fn list_entries_with_lister<'a, I>(m: &ArgMatches, entries: I) -> Result<()>
@@ -75,6 +164,33 @@ pub fn list_entries_with_lister<'a, I>(m: &ArgMatches, entries: I) -> Result<()>
             return PathLister::new(true).list(entries)
         }
 
+        if matches.is_present(list_backend_plain()) {
+            return PlainLister::new(true).list(entries)
+        }
+
+        if matches.is_present(list_backend_plain_content_only()) {
+            return PlainLister::new(false).list(entries)
+        }
+
+        if let Some(columns) = matches.value_of(list_backend_table()) {
+            let columns = columns.split(",").map(|c| String::from(c.trim())).collect();
+            return TableLister::new(columns).list(entries)
+        }
+
+        if matches.is_present(list_backend_abbrev()) {
+            let length = match matches.value_of(list_abbrev_length()) {
+                Some(n) => try!(n.parse::<usize>()
+                                .map_err(|e| ListError::new(ListErrorKind::CLIError,
+                                                            Some(Box::new(e))))),
+                None => DEFAULT_ABBREV_LENGTH,
+            };
+            let fields = matches.value_of(list_abbrev_header())
+                .map(|fields| fields.split(",").map(|f| String::from(f.trim())).collect())
+                .unwrap_or(vec![]);
+            let marker = matches.value_of(list_abbrev_marker()).unwrap_or(DEFAULT_MARKER);
+            return AbbrevLister::new(length, fields, String::from(marker)).list(entries)
+        }
+
         Ok(())
     } else {
         Err(ListError::new(ListErrorKind::CLIError, None))
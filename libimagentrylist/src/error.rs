@@ -12,14 +12,20 @@ pub enum ListErrorKind {
     EntryError,
     IterationError,
     CLIError,
+    MultipleErrors,
+    IoError,
+    HeaderReadError,
 }
 
 fn counter_error_type_as_str(err: &ListErrorKind) -> &'static str{
     match err {
-        &ListErrorKind::FormatError    => "FormatError",
-        &ListErrorKind::EntryError     => "EntryError",
-        &ListErrorKind::IterationError => "IterationError",
-        &ListErrorKind::CLIError       => "No CLI subcommand for listing entries",
+        &ListErrorKind::FormatError     => "Error while formatting an entry",
+        &ListErrorKind::EntryError      => "Error in an entry",
+        &ListErrorKind::IterationError  => "Error while iterating over the entries",
+        &ListErrorKind::CLIError        => "No CLI subcommand for listing entries",
+        &ListErrorKind::MultipleErrors  => "Listing failed for several entries",
+        &ListErrorKind::IoError         => "Error while writing the list",
+        &ListErrorKind::HeaderReadError => "Error while reading the header of an entry",
     }
 }
 
@@ -83,3 +89,58 @@ impl Error for ListError {
 
 }
 
+impl From<::std::io::Error> for ListError {
+    fn from(e: ::std::io::Error) -> ListError {
+        ListError::new(ListErrorKind::IoError, Some(Box::new(e)))
+    }
+}
+
+/**
+ * Cause of a `MultipleErrors` ListError, holding the errors of all entries which could not be
+ * listed, in the order of the entries
+ */
+#[derive(Debug)]
+pub struct ListErrors {
+    errors: Vec<ListError>,
+}
+
+impl ListErrors {
+
+    pub fn new(errors: Vec<ListError>) -> ListErrors {
+        ListErrors {
+            errors: errors,
+        }
+    }
+
+    /**
+     * Get the errors of the entries which could not be listed
+     */
+    pub fn errors(&self) -> &Vec<ListError> {
+        &self.errors
+    }
+
+}
+
+impl Display for ListErrors {
+
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), FmtError> {
+        try!(write!(fmt, "{} entries could not be listed:", self.errors.len()));
+        for e in &self.errors {
+            try!(write!(fmt, " {}", e));
+        }
+        Ok(())
+    }
+
+}
+
+impl Error for ListErrors {
+
+    fn description(&self) -> &str {
+        "Listing failed for several entries"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        self.errors.first().map(|e| e as &Error)
+    }
+
+}
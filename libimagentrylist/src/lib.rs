@@ -1,8 +1,10 @@
 extern crate clap;
 #[macro_use] extern crate log;
 extern crate toml;
+#[cfg(test)] extern crate tempdir;
 
 extern crate libimagstore;
+extern crate libimagutil;
 
 pub mod cli;
 pub mod error;
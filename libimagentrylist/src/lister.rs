@@ -10,3 +10,76 @@ pub trait Lister : Sized {
 
 }
 
+/// List each of the `entries` with `f`, continuing with the next entry if listing one fails
+///
+/// If listing a single entry failed, its error is returned. If several failed, a
+/// `ListErrorKind::MultipleErrors` error is returned, with a `ListErrors` cause holding all of
+/// the errors.
+pub fn list_each<'a, I, F>(entries: I, f: F) -> Result<()>
+    where I: Iterator<Item = FileLockEntry<'a>>,
+          F: Fn(&FileLockEntry<'a>) -> Result<()>
+{
+    use error::{ListError, ListErrorKind, ListErrors};
+
+    let mut errors : Vec<ListError> = entries.filter_map(|entry| f(&entry).err()).collect();
+    match errors.len() {
+        0 => Ok(()),
+        1 => Err(errors.pop().unwrap()),
+        _ => {
+            let cause = ListErrors::new(errors);
+            Err(ListError::new(ListErrorKind::MultipleErrors, Some(Box::new(cause))))
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+    use std::path::PathBuf;
+
+    use tempdir::TempDir;
+
+    use libimagstore::store::{FileLockEntry, Store};
+
+    use error::{ListError, ListErrorKind};
+    use super::list_each;
+
+    #[test]
+    fn test_list_each_reports_all_errors() {
+        let dir   = TempDir::new("imag-entrylist-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        for name in &["a", "b", "c"] {
+            store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+        }
+
+        let ids = |store: &Store| {
+            let mut ids : Vec<PathBuf> = store.retrieve_for_module("test").unwrap().collect();
+            ids.sort();
+            ids
+        };
+        let fail_on = |names: &'static [&'static str]| {
+            move |entry: &FileLockEntry| {
+                let name = entry.get_location().file_name().unwrap().to_string_lossy().into_owned();
+                if names.iter().any(|n| name.starts_with(n)) {
+                    Err(ListError::new(ListErrorKind::FormatError, None))
+                } else {
+                    Ok(())
+                }
+            }
+        };
+
+        let entries = ids(&store).into_iter().map(|id| store.retrieve(id).unwrap());
+        assert!(list_each(entries, fail_on(&[])).is_ok());
+
+        let entries = ids(&store).into_iter().map(|id| store.retrieve(id).unwrap());
+        let err = list_each(entries, fail_on(&["b"])).err().unwrap();
+        assert_eq!(err.err_type(), ListErrorKind::FormatError);
+
+        let entries = ids(&store).into_iter().map(|id| store.retrieve(id).unwrap());
+        let err = list_each(entries, fail_on(&["a", "c"])).err().unwrap();
+        assert_eq!(err.err_type(), ListErrorKind::MultipleErrors);
+        let cause = format!("{}", err.cause().unwrap());
+        assert!(cause.starts_with("2 entries could not be listed"));
+    }
+
+}
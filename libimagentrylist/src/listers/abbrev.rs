@@ -0,0 +1,119 @@
+use std::io::stdout;
+use std::io::Write;
+
+use lister::{Lister, list_each};
+use listers::table::header_field;
+use result::Result;
+
+use libimagstore::store::Entry;
+use libimagstore::store::FileLockEntry;
+use libimagutil::preview::{DEFAULT_MARKER, preview_with_marker};
+
+/// The number of characters of the content which are printed by default
+pub const DEFAULT_ABBREV_LENGTH: usize = 80;
+
+/// Lister which prints the location of the entries with the beginning of their content
+///
+/// Optionally, some header fields are printed as well. The content is printed in one line and
+/// shortened to at most `length` characters, ending in `marker` if it was shortened.
+pub struct AbbrevLister {
+    length: usize,
+    header_fields: Vec<String>,
+    marker: String,
+}
+
+impl AbbrevLister {
+
+    pub fn new(length: usize, header_fields: Vec<String>, marker: String) -> AbbrevLister {
+        AbbrevLister {
+            length: length,
+            header_fields: header_fields,
+            marker: marker,
+        }
+    }
+
+    fn format_entry(&self, entry: &Entry) -> Result<String> {
+        let mut line = entry.get_location().to_string_lossy().into_owned();
+
+        for field in &self.header_fields {
+            if let Some(value) = try!(header_field(entry, field)) {
+                line.push_str(&format!(" {}={}", field, value));
+            }
+        }
+
+        let content = entry.get_content().split_whitespace().collect::<Vec<&str>>().join(" ");
+        if !content.is_empty() {
+            line.push_str(": ");
+            line.push_str(&preview_with_marker(&content, self.length, &self.marker[..]));
+        }
+        Ok(line)
+    }
+
+}
+
+impl Default for AbbrevLister {
+
+    fn default() -> AbbrevLister {
+        AbbrevLister::new(DEFAULT_ABBREV_LENGTH, vec![], String::from(DEFAULT_MARKER))
+    }
+
+}
+
+impl Lister for AbbrevLister {
+
+    fn list<'a, I: Iterator<Item = FileLockEntry<'a>>>(&self, entries: I) -> Result<()> {
+        use error::ListError as LE;
+
+        list_each(entries, |entry| {
+            let line = try!(self.format_entry(entry));
+            write!(stdout(), "{}\n", line).map_err(LE::from)
+        })
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+    use libimagutil::preview::DEFAULT_MARKER;
+
+    use super::AbbrevLister;
+
+    fn entry(content: &str) -> Entry {
+        let s = format!("---\n[imag]\nversion = \"0.1.0\"\n---\n{}", content);
+        Entry::from_str(PathBuf::from("test~0.1.0"), &s).unwrap()
+    }
+
+    #[test]
+    fn test_abbrev_lister_output() {
+        use error::ListErrorKind;
+
+        let marker = String::from(DEFAULT_MARKER);
+        let lister = AbbrevLister::new(10, vec![String::from("imag.version")], marker.clone());
+        assert_eq!(lister.format_entry(&entry("Hello\nWorld, longer text\n")).unwrap(),
+                   "test~0.1.0 imag.version=0.1.0: Hello Wor…");
+        assert_eq!(lister.format_entry(&entry("")).unwrap(), "test~0.1.0 imag.version=0.1.0");
+
+        let lister = AbbrevLister::new(10, vec![String::from("imag.version.major")], marker);
+        assert_eq!(lister.format_entry(&entry("")).err().unwrap().err_type(),
+                   ListErrorKind::HeaderReadError);
+    }
+
+    #[test]
+    fn test_abbrev_lister_multibyte_content() {
+        let lister = AbbrevLister::new(5, vec![], String::from(DEFAULT_MARKER));
+        assert_eq!(lister.format_entry(&entry("日本語のテキスト")).unwrap(), "test~0.1.0: 日本語の…");
+        assert_eq!(lister.format_entry(&entry("äöüß")).unwrap(), "test~0.1.0: äöüß");
+    }
+
+    #[test]
+    fn test_abbrev_lister_marker() {
+        let lister = AbbrevLister::new(10, vec![], String::from(" [...]"));
+        assert_eq!(lister.format_entry(&entry("Hello World, longer text")).unwrap(),
+                   "test~0.1.0: Hell [...]");
+        assert_eq!(lister.format_entry(&entry("Hello")).unwrap(), "test~0.1.0: Hello");
+    }
+
+}
@@ -2,7 +2,7 @@ use std::io::stdout;
 use std::io::Write;
 use std::ops::Deref;
 
-use lister::Lister;
+use lister::{Lister, list_each};
 use result::Result;
 
 use libimagstore::store::FileLockEntry;
@@ -26,14 +26,11 @@ impl<'a> Lister for CoreLister<'a> {
 
     fn list<'b, I: Iterator<Item = FileLockEntry<'b>>>(&self, entries: I) -> Result<()> {
         use error::ListError as LE;
-        use error::ListErrorKind as LEK;
-
-        entries.fold(Ok(()), |accu, entry| {
-            accu.and_then(|_| {
-                    write!(stdout(), "{:?}\n", (self.lister)(entry.deref()))
-                        .map_err(|e| LE::new(LEK::FormatError, Some(Box::new(e))))
-                })
-            })
+
+        list_each(entries, |entry| {
+            write!(stdout(), "{:?}\n", (self.lister)(entry.deref()))
+                .map_err(LE::from)
+        })
     }
 
 }
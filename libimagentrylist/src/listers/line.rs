@@ -2,7 +2,7 @@ use std::io::stdout;
 use std::io::Write;
 
 use cli::list_subcommand_name;
-use lister::Lister;
+use lister::{Lister, list_each};
 use result::Result;
 
 use clap::ArgMatches;
@@ -26,15 +26,11 @@ impl<'a> Lister for LineLister<'a> {
 
     fn list<'b, I: Iterator<Item = FileLockEntry<'b>>>(&self, entries: I) -> Result<()> {
         use error::ListError as LE;
-        use error::ListErrorKind as LEK;
-
-        entries.fold(Ok(()), |accu, entry| {
-            accu.and_then(|_| {
-                    write!(stdout(), "{:?}\n",
-                            entry.get_location().to_str().unwrap_or(self.unknown_output))
-                        .map_err(|e| LE::new(LEK::FormatError, Some(Box::new(e))))
-                })
-            })
+
+        list_each(entries, |entry| {
+            write!(stdout(), "{:?}\n", entry.get_location().to_str().unwrap_or(self.unknown_output))
+                .map_err(LE::from)
+        })
     }
 
 }
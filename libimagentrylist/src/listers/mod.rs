@@ -1,3 +1,6 @@
+pub mod abbrev;
 pub mod core;
 pub mod line;
 pub mod path;
+pub mod plain;
+pub mod table;
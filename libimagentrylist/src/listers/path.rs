@@ -3,7 +3,7 @@ use std::io::Write;
 use std::ops::Deref;
 
 use cli::list_subcommand_name;
-use lister::Lister;
+use lister::{Lister, list_each};
 use result::Result;
 
 use clap::ArgMatches;
@@ -29,8 +29,8 @@ impl Lister for PathLister {
         use error::ListError as LE;
         use error::ListErrorKind as LEK;
 
-        entries.fold(Ok(()), |accu, entry| {
-            accu.and_then(|_| Ok(entry.deref().get_location().clone()))
+        list_each(entries, |entry| {
+            Ok(entry.deref().get_location().clone())
                 .and_then(|pb| {
                     if self.absolute {
                         pb.canonicalize().map_err(|e| LE::new(LEK::FormatError, Some(Box::new(e))))
@@ -0,0 +1,70 @@
+use std::io::stdout;
+use std::io::Write;
+
+use lister::{Lister, list_each};
+use result::Result;
+
+use libimagstore::store::Entry;
+use libimagstore::store::FileLockEntry;
+
+/// Lister which prints the entries themselves, as they are written to the store
+pub struct PlainLister {
+    include_header: bool,
+}
+
+impl PlainLister {
+
+    pub fn new(include_header: bool) -> PlainLister {
+        PlainLister {
+            include_header: include_header,
+        }
+    }
+
+    fn write_entry<W: Write>(&self, out: &mut W, entry: &Entry) -> Result<()> {
+        use error::ListError as LE;
+
+        if self.include_header {
+            write!(out, "{}", entry.to_str())
+        } else {
+            write!(out, "{}", entry.get_content())
+        }
+        .map_err(LE::from)
+    }
+
+}
+
+impl Lister for PlainLister {
+
+    fn list<'a, I: Iterator<Item = FileLockEntry<'a>>>(&self, entries: I) -> Result<()> {
+        list_each(entries, |entry| self.write_entry(&mut stdout(), entry))
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::PlainLister;
+
+    fn entry() -> Entry {
+        let s = "---\n[imag]\nversion = \"0.1.0\"\n---\nHello World\n";
+        Entry::from_str(PathBuf::from("test~0.1.0"), s).unwrap()
+    }
+
+    #[test]
+    fn test_plain_lister_output() {
+        let entry = entry();
+
+        let mut with_header = vec![];
+        PlainLister::new(true).write_entry(&mut with_header, &entry).unwrap();
+        assert_eq!(String::from_utf8(with_header).unwrap(), entry.to_str());
+
+        let mut without_header = vec![];
+        PlainLister::new(false).write_entry(&mut without_header, &entry).unwrap();
+        assert_eq!(String::from_utf8(without_header).unwrap(), "Hello World\n");
+    }
+
+}
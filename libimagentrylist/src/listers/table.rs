@@ -0,0 +1,152 @@
+use std::io::stdout;
+use std::io::Write;
+
+use lister::Lister;
+use result::Result;
+
+use libimagstore::store::Entry;
+use libimagstore::store::FileLockEntry;
+use toml::Value;
+
+/// Name of the column which shows the location of the entry instead of a header field
+pub const LOCATION_COLUMN: &'static str = "location";
+
+/// Read the header field `spec` of `entry` as string
+///
+/// Returns `None` if the field is missing or is no scalar value, and a `HeaderReadError` if the
+/// header cannot be read at `spec`.
+pub fn header_field(entry: &Entry, spec: &str) -> Result<Option<String>> {
+    use error::ListError as LE;
+    use error::ListErrorKind as LEK;
+
+    match entry.get_header().read(spec) {
+        Ok(Some(Value::String(s)))   => Ok(Some(s)),
+        Ok(Some(Value::Integer(i)))  => Ok(Some(format!("{}", i))),
+        Ok(Some(Value::Float(f)))    => Ok(Some(format!("{}", f))),
+        Ok(Some(Value::Boolean(b)))  => Ok(Some(format!("{}", b))),
+        Ok(Some(Value::Datetime(d))) => Ok(Some(d)),
+        Ok(_)  => Ok(None),
+        Err(e) => Err(LE::new(LEK::HeaderReadError, Some(Box::new(e)))),
+    }
+}
+
+/// Lister which prints the entries as table, with configurable columns
+///
+/// Each column is either a header spec like `imag.version` or the `LOCATION_COLUMN`. Header
+/// fields which are missing or which are no scalar values are left blank.
+pub struct TableLister {
+    columns: Vec<String>,
+}
+
+impl TableLister {
+
+    pub fn new(columns: Vec<String>) -> TableLister {
+        TableLister {
+            columns: columns,
+        }
+    }
+
+    fn cell(&self, entry: &Entry, column: &str) -> Result<String> {
+        if column == LOCATION_COLUMN {
+            return Ok(entry.get_location().to_string_lossy().into_owned());
+        }
+
+        header_field(entry, column).map(|field| field.unwrap_or(String::new()))
+    }
+
+    fn render(&self, entries: &[&Entry]) -> Result<String> {
+        let mut rows : Vec<Vec<String>> = vec![];
+        for entry in entries {
+            let mut row = vec![];
+            for column in &self.columns {
+                row.push(try!(self.cell(entry, column)));
+            }
+            rows.push(row);
+        }
+
+        let widths : Vec<usize> = self.columns.iter()
+            .enumerate()
+            .map(|(i, c)| {
+                rows.iter()
+                    .map(|row| row[i].chars().count())
+                    .fold(c.chars().count(), ::std::cmp::max)
+            })
+            .collect();
+
+        let separator = {
+            let mut s = String::from("+");
+            for width in &widths {
+                s.push_str(&"-".repeat(width + 2));
+                s.push('+');
+            }
+            s.push('\n');
+            s
+        };
+
+        let line = |cells: &[String]| {
+            let mut s = String::from("|");
+            for (cell, width) in cells.iter().zip(widths.iter()) {
+                let padding = width - cell.chars().count();
+                s.push_str(&format!(" {}{} |", cell, " ".repeat(padding)));
+            }
+            s.push('\n');
+            s
+        };
+
+        let mut table = separator.clone();
+        table.push_str(&line(&self.columns));
+        table.push_str(&separator);
+        for row in &rows {
+            table.push_str(&line(row));
+        }
+        table.push_str(&separator);
+        Ok(table)
+    }
+
+}
+
+impl Lister for TableLister {
+
+    fn list<'a, I: Iterator<Item = FileLockEntry<'a>>>(&self, entries: I) -> Result<()> {
+        use error::ListError as LE;
+
+        let entries : Vec<FileLockEntry<'a>> = entries.collect();
+        let entries : Vec<&Entry> = entries.iter().map(|e| &**e).collect();
+
+        let table = try!(self.render(&entries));
+        write!(stdout(), "{}", table).map_err(LE::from)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Entry;
+
+    use super::TableLister;
+
+    fn entry(id: &str, header: &str) -> Entry {
+        let s = format!("---\n[imag]\nversion = \"0.1.0\"\n{}---\n", header);
+        Entry::from_str(PathBuf::from(id), &s).unwrap()
+    }
+
+    #[test]
+    fn test_table_lister_output() {
+        let first = entry("diary/first~0.1.0", "[diary]\ntype = \"private\"\n");
+        let second = entry("diary/second~0.1.0", "");
+        let columns = vec![String::from("location"),
+                           String::from("imag.version"),
+                           String::from("diary.type")];
+
+        let table = TableLister::new(columns).render(&[&first, &second]).unwrap();
+        assert_eq!(table, "+--------------------+--------------+------------+\n\
+                           | location           | imag.version | diary.type |\n\
+                           +--------------------+--------------+------------+\n\
+                           | diary/first~0.1.0  | 0.1.0        | private    |\n\
+                           | diary/second~0.1.0 | 0.1.0        |            |\n\
+                           +--------------------+--------------+------------+\n");
+    }
+
+}
@@ -12,6 +12,7 @@ pub enum LinkErrorKind {
     LinkTargetDoesNotExist,
     InternalConversionError,
     InvalidUri,
+    StoreReadError,
 }
 
 fn link_error_type_as_str(e: &LinkErrorKind) -> &'static str {
@@ -33,6 +34,9 @@ fn link_error_type_as_str(e: &LinkErrorKind) -> &'static str {
 
         &LinkErrorKind::InvalidUri
             => "URI is not valid",
+
+        &LinkErrorKind::StoreReadError
+            => "Error while reading from the store",
     }
 }
 
@@ -1,13 +1,13 @@
+use std::collections::BTreeMap;
+
+use libimagstore::error::StoreErrorKind;
 use libimagstore::storeid::StoreId;
 use libimagstore::store::Entry;
-use libimagstore::store::EntryHeader;
-use libimagstore::store::Result as StoreResult;
+use libimagstore::store::Store;
 
 use error::{LinkError, LinkErrorKind};
 use result::Result;
 
-use toml::Value;
-
 pub type Link = StoreId;
 
 pub trait InternalLinker {
@@ -29,7 +29,8 @@ pub trait InternalLinker {
 impl InternalLinker for Entry {
 
     fn get_internal_links(&self) -> Result<Vec<Link>> {
-        process_rw_result(self.get_header().read("imag.links"))
+        self.internal_links()
+            .map_err(|e| LinkError::new(LinkErrorKind::EntryHeaderReadError, Some(Box::new(e))))
     }
 
     /// Set the links in a header and return the old links, if any.
@@ -41,17 +42,11 @@ impl InternalLinker for Entry {
             if let Err(e) = add_foreign_link(link, self_location.clone()) {
                 return Err(e);
             }
-            let link = link.get_location().clone();
-            let link = link.to_str();
-            if link.is_none() {
-                return Err(LinkError::new(LinkErrorKind::InternalConversionError, None));
-            }
-            let link = link.unwrap();
-
-            new_links.push(Value::String(String::from(link)));
+            new_links.push(link.get_location().clone());
         }
 
-        process_rw_result(self.get_header_mut().set("imag.links", Value::Array(new_links)))
+        self.get_internal_links()
+            .and_then(|old_links| rewrite_links(self, new_links).map(|_| old_links))
     }
 
     fn add_internal_link(&mut self, link: &mut Entry) -> Result<()> {
@@ -62,7 +57,7 @@ impl InternalLinker for Entry {
                 self.get_internal_links()
                     .and_then(|mut links| {
                         links.push(new_link);
-                        rewrite_links(self.get_header_mut(), links)
+                        rewrite_links(self, links)
                     })
             })
     }
@@ -74,34 +69,23 @@ impl InternalLinker for Entry {
         link.get_internal_links()
             .and_then(|links| {
                 let links = links.into_iter().filter(|l| l.clone() != own_loc).collect();
-                rewrite_links(self.get_header_mut(), links)
+                rewrite_links(self, links)
             })
             .and_then(|_| {
                 self.get_internal_links()
                     .and_then(|links| {
                         let links = links.into_iter().filter(|l| l.clone() != other_loc).collect();
-                        rewrite_links(link.get_header_mut(), links)
+                        rewrite_links(link, links)
                     })
             })
     }
 
 }
 
-fn rewrite_links(header: &mut EntryHeader, links: Vec<StoreId>) -> Result<()> {
-    let links : Vec<Option<Value>> = links
-        .into_iter()
-        .map(|s| s.to_str().map(|s| Value::String(String::from(s))))
-        .collect();
-
-    if links.iter().any(|o| o.is_none()) {
-        // if any type convert failed we fail as well
-        Err(LinkError::new(LinkErrorKind::InternalConversionError, None))
-    } else {
-        // I know it is ugly
-        let links = links.into_iter().map(|opt| opt.unwrap()).collect();
-        let process = header.set("imag.links", Value::Array(links));
-        process_rw_result(process).map(|_| ())
-    }
+fn rewrite_links(entry: &mut Entry, links: Vec<StoreId>) -> Result<()> {
+    // Explicitely call the Entry function, not the one from the InternalLinker trait
+    Entry::set_internal_links(entry, &links[..])
+        .map_err(|e| LinkError::new(LinkErrorKind::EntryHeaderWriteError, Some(Box::new(e))))
 }
 
 /// When Linking A -> B, the specification wants us to link back B -> A.
@@ -110,46 +94,143 @@ fn add_foreign_link(target: &mut Entry, from: StoreId) -> Result<()> {
     target.get_internal_links()
         .and_then(|mut links| {
             links.push(from);
-            let links : Vec<Option<Value>> = links
-                .into_iter()
-                .map(|s| {
-                    match s.to_str() {
-                        Some(s) => Some(Value::String(String::from(s))),
-                        _ => None
-                    }
-                })
-                .collect();
-            if links.iter().any(|o| o.is_none()) {
-                Err(LinkError::new(LinkErrorKind::InternalConversionError, None))
-            } else {
-                let links = links.into_iter().map(|opt| opt.unwrap()).collect();
-                process_rw_result(target.get_header_mut().set("imag.links", Value::Array(links)))
-                    .map(|_| ())
-            }
+            rewrite_links(target, links)
         })
 }
 
-fn process_rw_result(links: StoreResult<Option<Value>>) -> Result<Vec<Link>> {
-    if links.is_err() {
-        let lerr  = LinkError::new(LinkErrorKind::EntryHeaderReadError,
-                                   Some(Box::new(links.err().unwrap())));
-        return Err(lerr);
+/// Anomalies in the internal links of a store, see `consistency_report()`
+#[derive(Debug, Default, PartialEq)]
+pub struct LinkReport {
+    /// Links whose target does not exist in the store, as `(from, to)`
+    pub dangling: Vec<(StoreId, StoreId)>,
+
+    /// Links which are not linked back from their target, as `(from, to)`
+    pub asymmetric: Vec<(StoreId, StoreId)>,
+
+    /// Entries which link to themselves
+    pub self_links: Vec<StoreId>,
+
+    /// Entries which were not checked because they are currently borrowed
+    pub skipped: Vec<StoreId>,
+}
+
+impl LinkReport {
+
+    /// Whether no anomalies were found
+    ///
+    /// Skipped entries are no anomaly, but their links were not checked.
+    pub fn is_consistent(&self) -> bool {
+        self.dangling.is_empty() && self.asymmetric.is_empty() && self.self_links.is_empty()
     }
-    let links = links.unwrap();
 
-    if links.iter().any(|l| match l { &Value::String(_) => true, _ => false }) {
-        return Err(LinkError::new(LinkErrorKind::ExistingLinkTypeWrong, None));
+}
+
+/// Check the internal links of all entries in the store
+///
+/// As linking A -> B also links B -> A, every link is expected to be bidirectional. The store is
+/// not modified, the entries are only read. Entries which are currently borrowed cannot be read,
+/// they are listed as skipped and links to them are not reported.
+pub fn consistency_report(store: &Store) -> Result<LinkReport> {
+    let ids = try!(store.retrieve_all()
+        .map_err(|e| LinkError::new(LinkErrorKind::StoreReadError, Some(Box::new(e)))));
+
+    let mut report = LinkReport::default();
+    let mut links = BTreeMap::new();
+    for id in ids {
+        let entry = match store.retrieve_copy(id.clone()) {
+            Ok(entry) => entry,
+            Err(ref e) if e.err_type() == StoreErrorKind::IdLocked => {
+                debug!("Entry {:?} is borrowed, skipping it", id);
+                report.skipped.push(id);
+                continue;
+            },
+            Err(e) => return Err(LinkError::new(LinkErrorKind::StoreReadError, Some(Box::new(e)))),
+        };
+        links.insert(id, try!(entry.get_internal_links()));
     }
 
-    let links : Vec<Link> = links.into_iter()
-        .map(|link| {
-            match link {
-                Value::String(s) => StoreId::from(s),
-                _ => unreachable!(),
+    for (from, targets) in links.iter() {
+        for to in targets {
+            if to == from {
+                report.self_links.push(from.clone());
+            } else if report.skipped.contains(to) {
+                continue;
+            } else {
+                match links.get(to) {
+                    None => report.dangling.push((from.clone(), to.clone())),
+                    Some(back) if !back.contains(from) => {
+                        report.asymmetric.push((from.clone(), to.clone()))
+                    },
+                    Some(_) => {},
+                }
             }
-        })
-        .collect();
+        }
+    }
 
-    Ok(links)
+    Ok(report)
 }
 
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Store;
+    use libimagstore::storeid::StoreId;
+    use tempdir::TempDir;
+
+    use super::consistency_report;
+
+    fn set_links(store: &Store, id: &StoreId, links: &[&StoreId]) {
+        let mut entry = store.retrieve(id.clone()).unwrap();
+        let links : Vec<StoreId> = links.iter().map(|l| (*l).clone()).collect();
+        entry.set_internal_links(&links[..]).unwrap();
+    }
+
+    #[test]
+    fn test_consistency_report() {
+        let dir = TempDir::new("imag-link-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        let id = |name: &str| dir.path().join(format!("test/{}~0.1.0", name));
+
+        let (a, b, c, d, e) = (id("a"), id("b"), id("c"), id("d"), id("e"));
+        for entry in [&a, &b, &c, &d, &e].iter() {
+            let _ = store.create((*entry).clone()).unwrap();
+        }
+
+        set_links(&store, &a, &[&b]);              // asymmetric
+        set_links(&store, &c, &[&id("missing")]);  // dangling
+        set_links(&store, &d, &[&d, &e]);          // self-link, d <-> e is fine
+        set_links(&store, &e, &[&d]);
+
+        let report = consistency_report(&store).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.asymmetric, vec![(a.clone(), b.clone())]);
+        assert_eq!(report.dangling, vec![(c.clone(), id("missing"))]);
+        assert_eq!(report.self_links, vec![d.clone()]);
+
+        // The store was not touched
+        assert_eq!(consistency_report(&store).unwrap(), report);
+        assert!(!id("missing").exists());
+    }
+
+    #[test]
+    fn test_consistency_report_skips_borrowed_entries() {
+        let dir = TempDir::new("imag-link-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        let id = |name: &str| dir.path().join(format!("test/{}~0.1.0", name));
+
+        let (a, b, c) = (id("a"), id("b"), id("c"));
+        for entry in [&a, &b, &c].iter() {
+            let _ = store.create((*entry).clone()).unwrap();
+        }
+        set_links(&store, &a, &[&b]);
+        set_links(&store, &c, &[&id("missing")]);
+
+        let _borrowed = store.retrieve(b.clone()).unwrap();
+        let report = consistency_report(&store).unwrap();
+        assert_eq!(report.skipped, vec![b.clone()]);
+        assert!(report.asymmetric.is_empty());
+        assert_eq!(report.dangling, vec![(c.clone(), id("missing"))]);
+    }
+
+}
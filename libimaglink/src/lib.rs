@@ -4,6 +4,8 @@ extern crate url;
 
 extern crate libimagstore;
 
+#[cfg(test)] extern crate tempdir;
+
 pub mod error;
 pub mod external;
 pub mod internal;
@@ -1,10 +1,12 @@
 #[macro_use] extern crate log;
 extern crate semver;
 extern crate toml;
+#[cfg(test)] extern crate tempdir;
 
 extern crate libimagrt;
 #[macro_use] extern crate libimagstore;
 extern crate libimagtag;
+extern crate libimagutil;
 
 module_entry_path_mod!("notes", "0.1.0");
 
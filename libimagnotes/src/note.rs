@@ -63,6 +63,43 @@ impl<'a> Note<'a> {
         Ok(Note { entry: fle })
     }
 
+    /// Create a new Note with its content rendered from `template`
+    ///
+    /// The template can use `{{name}}` for the name of the note, see
+    /// `libimagutil::template::render_template()`.
+    pub fn new_with_template<'b>(store: &'b Store, name: String, template: &str)
+        -> Result<Note<'b>>
+    {
+        use libimagutil::template::render_template;
+
+        let mut vars = BTreeMap::new();
+        vars.insert(String::from("name"), name.clone());
+        let text = render_template(template, &vars);
+        Note::new(store, name, text)
+    }
+
+    /// Get the default content template for new notes from the configuration
+    ///
+    /// This is the `template` key in the `[notes]` section of the configuration.
+    pub fn template_from_config(rt: &Runtime) -> Option<String> {
+        rt.config()
+            .and_then(|cfg| match cfg.config() {
+                &Value::Table(ref t) => t.get("notes"),
+                _ => None,
+            })
+            .and_then(|notes| match notes {
+                &Value::Table(ref t) => t.get("template"),
+                _ => None,
+            })
+            .and_then(|template| match template {
+                &Value::String(ref s) => Some(s.clone()),
+                _ => {
+                    warn!("'notes.template' configuration key should contain String, does not");
+                    None
+                },
+            })
+    }
+
     pub fn set_name(&mut self, n: String) -> Result<()> {
         let mut header = self.entry.deref_mut().get_header_mut();
         header.set("note.name", Value::String(n))
@@ -174,6 +211,7 @@ impl<'a> Deref for Note<'a> {
 pub struct NoteIterator<'a> {
     store: &'a Store,
     iditer: StoreIdIterator,
+    skip_malformed: bool,
 }
 
 impl<'a> NoteIterator<'a> {
@@ -182,19 +220,81 @@ impl<'a> NoteIterator<'a> {
         NoteIterator {
             store: store,
             iditer: iditer,
+            skip_malformed: true,
         }
     }
 
+    /// Whether notes which cannot be parsed are skipped with a warning (the default) or yielded
+    /// as errors
+    pub fn skip_malformed(mut self, skip: bool) -> NoteIterator<'a> {
+        self.skip_malformed = skip;
+        self
+    }
+
 }
 
 impl<'a> Iterator for NoteIterator<'a> {
     type Item = Result<Note<'a>>;
 
     fn next(&mut self) -> Option<Result<Note<'a>>> {
-        self.iditer
-            .next()
-            .map(|id| Note::from_storeid(self.store, id))
+        use libimagstore::error::StoreErrorKind as SEK;
+
+        while let Some(id) = self.iditer.next() {
+            debug!("Loading note from storeid: '{:?}'", id);
+            match self.store.retrieve(id.clone()) {
+                Err(ref e) if self.skip_malformed && e.err_type() == SEK::MalformedEntry => {
+                    warn!("Skipping malformed note: {:?}", id);
+                },
+                Err(e)    => return Some(Err(NE::new(NEK::StoreReadError, Some(Box::new(e))))),
+                Ok(entry) => return Some(Ok(Note { entry: entry })),
+            }
+        }
+        None
     }
 
 }
 
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use tempdir::TempDir;
+
+    use libimagstore::store::Store;
+
+    use super::Note;
+
+    #[test]
+    fn test_new_with_template() {
+        let dir   = TempDir::new("imag-notes-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+
+        let note = Note::new_with_template(&store, String::from("groceries"),
+                                           "# {{name}}\n\n- item\n").unwrap();
+        assert_eq!(note.get_name().unwrap(), "groceries");
+        assert_eq!(note.get_text(), "# groceries\n\n- item\n");
+    }
+
+    #[test]
+    fn test_iterator_skips_malformed_notes() {
+        use std::fs::File;
+        use std::io::Write;
+
+        let dir   = TempDir::new("imag-notes-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+
+        Note::new(&store, String::from("valid"), String::from("text")).unwrap();
+        let mut file = File::create(dir.path().join("notes/corrupt~0.1.0")).unwrap();
+        file.write_all(b"This is no entry").unwrap();
+
+        let notes = Note::all_notes(&store).unwrap().collect::<Vec<_>>();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].as_ref().unwrap().get_name().unwrap(), "valid");
+        drop(notes);
+
+        let notes = Note::all_notes(&store).unwrap().skip_malformed(false).collect::<Vec<_>>();
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes.iter().filter(|n| n.is_err()).count(), 1);
+    }
+
+}
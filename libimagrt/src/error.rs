@@ -4,6 +4,12 @@ use std::fmt::Formatter;
 use std::fmt::Error as FmtError;
 use std::io::Error as IOError;
 
+use libimagentryfilter::error::FilterCompilerError;
+use libimagstore::error::StoreError;
+use libimagstore::hook::error::HookError;
+
+use configuration::error::ConfigError;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum RuntimeErrorKind {
     Instantiate,
@@ -67,3 +73,166 @@ impl From<IOError> for RuntimeError {
 
 }
 
+
+/// Error type which unifies the errors of the imag libraries
+///
+/// Commands can use `Result<T, ImagError>` and `try!()` across subsystems. The `Display` and
+/// `Error` implementations are transparent, so `trace_error()` prints the same trace as for the
+/// wrapped error.
+#[derive(Debug)]
+pub enum ImagError {
+    Store(StoreError),
+    Hook(HookError),
+    Config(ConfigError),
+    FilterCompiler(FilterCompilerError),
+    Runtime(RuntimeError),
+}
+
+impl ImagError {
+
+    fn inner(&self) -> &Error {
+        match self {
+            &ImagError::Store(ref e)   => e,
+            &ImagError::Hook(ref e)    => e,
+            &ImagError::Config(ref e)  => e,
+            &ImagError::FilterCompiler(ref e) => e,
+            &ImagError::Runtime(ref e) => e,
+        }
+    }
+
+}
+
+impl Display for ImagError {
+
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), FmtError> {
+        Display::fmt(self.inner(), fmt)
+    }
+
+}
+
+impl Error for ImagError {
+
+    fn description(&self) -> &str {
+        self.inner().description()
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        self.inner().cause()
+    }
+
+}
+
+impl From<StoreError> for ImagError {
+
+    fn from(e: StoreError) -> ImagError {
+        ImagError::Store(e)
+    }
+
+}
+
+impl From<HookError> for ImagError {
+
+    fn from(e: HookError) -> ImagError {
+        ImagError::Hook(e)
+    }
+
+}
+
+impl From<ConfigError> for ImagError {
+
+    fn from(e: ConfigError) -> ImagError {
+        ImagError::Config(e)
+    }
+
+}
+
+impl From<FilterCompilerError> for ImagError {
+
+    fn from(e: FilterCompilerError) -> ImagError {
+        ImagError::FilterCompiler(e)
+    }
+
+}
+
+impl From<RuntimeError> for ImagError {
+
+    fn from(e: RuntimeError) -> ImagError {
+        ImagError::Runtime(e)
+    }
+
+}
+
+#[cfg(test)]
+mod test {
+    use std::error::Error;
+    use std::io::{Error as IOError, ErrorKind as IOErrorKind};
+
+    use libimagentryfilter::error::{FilterCompilerError, FilterCompilerErrorKind};
+    use libimagstore::error::{StoreError, StoreErrorKind};
+    use libimagstore::hook::error::{HookError, HookErrorKind};
+    use libimagutil::trace::trace_error;
+
+    use configuration::error::{ConfigError, ConfigErrorKind};
+    use super::{ImagError, RuntimeError, RuntimeErrorKind};
+
+    fn check<E: Error + Into<ImagError>>(e: E) -> ImagError {
+        let description = String::from(e.description());
+        let has_cause = e.cause().is_some();
+
+        let ie : ImagError = e.into();
+        assert_eq!(ie.description(), description);
+        assert_eq!(ie.cause().is_some(), has_cause);
+        trace_error(&ie);
+        ie
+    }
+
+    #[test]
+    fn test_from_store_error() {
+        let ie = check(StoreError::new(StoreErrorKind::IdNotFound, None));
+        match ie {
+            ImagError::Store(e) => assert_eq!(e.err_type(), StoreErrorKind::IdNotFound),
+            _ => panic!("Expected ImagError::Store, got {:?}", ie),
+        }
+    }
+
+    #[test]
+    fn test_from_hook_error() {
+        let ie = check(HookError::new(HookErrorKind::HookExecutionError, None));
+        match ie {
+            ImagError::Hook(_) => {},
+            _ => panic!("Expected ImagError::Hook, got {:?}", ie),
+        }
+    }
+
+    #[test]
+    fn test_from_config_error() {
+        let ie = check(ConfigError::new(ConfigErrorKind::NoConfigFileFound, None));
+        match ie {
+            ImagError::Config(e) => assert_eq!(e.kind(), ConfigErrorKind::NoConfigFileFound),
+            _ => panic!("Expected ImagError::Config, got {:?}", ie),
+        }
+    }
+
+    #[test]
+    fn test_from_filter_compiler_error() {
+        let ie = check(FilterCompilerError::new(FilterCompilerErrorKind::UnexpectedEnd, None));
+        match ie {
+            ImagError::FilterCompiler(e) => {
+                assert_eq!(e.err_type(), FilterCompilerErrorKind::UnexpectedEnd)
+            },
+            _ => panic!("Expected ImagError::FilterCompiler, got {:?}", ie),
+        }
+    }
+
+    #[test]
+    fn test_from_runtime_error_keeps_cause() {
+        let cause = IOError::new(IOErrorKind::Other, "broken pipe");
+        let ie = check(RuntimeError::new(RuntimeErrorKind::IOError, Some(Box::new(cause))));
+        assert_eq!(ie.cause().map(|c| format!("{}", c)), Some(String::from("broken pipe")));
+        match ie {
+            ImagError::Runtime(_) => {},
+            _ => panic!("Expected ImagError::Runtime, got {:?}", ie),
+        }
+    }
+
+}
@@ -20,6 +20,7 @@ extern crate tempfile;
 extern crate clap;
 extern crate toml;
 
+extern crate libimagentryfilter;
 extern crate libimagstore;
 extern crate libimagstorestdhook;
 extern crate libimagutil;
@@ -107,6 +107,7 @@ impl<'a> Runtime<'a> {
                     (DebugHook::new(HookPosition::PostRetrieve), HookPosition::PostRetrieve),
                     (DebugHook::new(HookPosition::PreUpdate), HookPosition::PreUpdate),
                     (DebugHook::new(HookPosition::PostUpdate), HookPosition::PostUpdate),
+                    (DebugHook::new(HookPosition::PostWrite), HookPosition::PostWrite),
                     (DebugHook::new(HookPosition::PreDelete), HookPosition::PreDelete),
                     (DebugHook::new(HookPosition::PostDelete), HookPosition::PostDelete),
                 ];
@@ -1,5 +1,9 @@
+use std::collections::BTreeMap;
+
 use toml::Value;
 
+use storeid::StoreLayout;
+
 /// Check whether the configuration is valid for the store
 ///
 /// The passed `Value` _must be_ the `[store]` sub-tree of the configuration. Otherwise this will
@@ -176,6 +180,19 @@ pub fn get_post_update_aspect_names(value: &Option<Value>) -> Vec<String> {
     get_aspect_names_for_aspect_position("post-update-hook-aspects", value)
 }
 
+/// Get the names of the aspects for the `PostWrite` hook position
+///
+/// Unlike the other `*-hook-aspects` keys, `post-write-hook-aspects` does not have to be present
+/// in the store configuration.
+pub fn get_post_write_aspect_names(value: &Option<Value>) -> Vec<String> {
+    match value {
+        &Some(Value::Table(ref t)) if t.contains_key("post-write-hook-aspects") => {
+            get_aspect_names_for_aspect_position("post-write-hook-aspects", value)
+        },
+        _ => vec![],
+    }
+}
+
 pub fn get_pre_delete_aspect_names(value: &Option<Value>) -> Vec<String> {
     get_aspect_names_for_aspect_position("pre-delete-hook-aspects", value)
 }
@@ -184,9 +201,220 @@ pub fn get_post_delete_aspect_names(value: &Option<Value>) -> Vec<String> {
     get_aspect_names_for_aspect_position("post-delete-hook-aspects", value)
 }
 
+/// Get the layout of the store entries on the filesystem
+///
+/// Reads the `layout` key of the store configuration, which can either be "hierarchical" (the
+/// default) or "flat":
+///
+/// ```toml
+/// [store]
+/// layout = "flat"
+/// ```
+pub fn get_store_layout(value: &Option<Value>) -> StoreLayout {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("layout") {
+                Some(&Value::String(ref s)) => match &s[..] {
+                    "flat"         => StoreLayout::Flat,
+                    "hierarchical" => StoreLayout::Hierarchical,
+                    _ => {
+                        warn!("Unknown store layout '{}', using hierarchical layout", s);
+                        StoreLayout::Hierarchical
+                    },
+                },
+                Some(_) => {
+                    warn!("'layout' configuration key should contain String, does not");
+                    StoreLayout::Hierarchical
+                },
+                None => StoreLayout::Hierarchical,
+            }
+        },
+        _ => StoreLayout::Hierarchical,
+    }
+}
+
+/// Check whether a store version mismatch should be an error
+///
+/// Reads the `version-mismatch` key of the store configuration, which can either be "warn" (the
+/// default) or "error".
+pub fn version_mismatch_is_error(value: &Option<Value>) -> bool {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("version-mismatch") {
+                Some(&Value::String(ref s)) => match &s[..] {
+                    "error" => true,
+                    "warn"  => false,
+                    _ => {
+                        warn!("Unknown version-mismatch behaviour '{}', warning only", s);
+                        false
+                    },
+                },
+                Some(_) => {
+                    warn!("'version-mismatch' configuration key should contain String, does not");
+                    false
+                },
+                None => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Check whether the store should maintain an index of its entries
+///
+/// Reads the `index` key of the store configuration, which defaults to `false`.
+pub fn is_index_enabled(value: &Option<Value>) -> bool {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("index") {
+                Some(&Value::Boolean(b)) => b,
+                Some(_) => {
+                    warn!("'index' configuration key should contain Boolean, does not");
+                    false
+                },
+                None => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Check whether the content of entries should be stored in a separate file
+///
+/// Reads the `separate-content` key of the store configuration, which defaults to `false`.
+pub fn is_separate_content_enabled(value: &Option<Value>) -> bool {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("separate-content") {
+                Some(&Value::Boolean(b)) => b,
+                Some(_) => {
+                    warn!("'separate-content' configuration key should contain Boolean, does not");
+                    false
+                },
+                None => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Check whether written entries should be synced to disk
+///
+/// Reads the `durable-writes` key of the store configuration, which defaults to `true`. Without
+/// syncing, writing is faster, but entries which were written right before a power loss may be
+/// lost.
+pub fn is_durable_writes_enabled(value: &Option<Value>) -> bool {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("durable-writes") {
+                Some(&Value::Boolean(b)) => b,
+                Some(_) => {
+                    warn!("'durable-writes' configuration key should contain Boolean, does not");
+                    true
+                },
+                None => true,
+            }
+        },
+        _ => true,
+    }
+}
+
+/// Get the maximum nesting depth of entry headers
+///
+/// Reads the `header-max-depth` key of the store configuration, which defaults to
+/// `DEFAULT_HEADER_MAX_DEPTH`.
+pub fn get_header_max_depth(value: &Option<Value>) -> usize {
+    use store::DEFAULT_HEADER_MAX_DEPTH;
+
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("header-max-depth") {
+                Some(&Value::Integer(i)) if i > 0 => i as usize,
+                Some(_) => {
+                    warn!("'header-max-depth' configuration key should contain a positive \
+                           Integer, does not");
+                    DEFAULT_HEADER_MAX_DEPTH
+                },
+                None => DEFAULT_HEADER_MAX_DEPTH,
+            }
+        },
+        _ => DEFAULT_HEADER_MAX_DEPTH,
+    }
+}
+
+/// Get the width to which the content of entries is wrapped when they are written
+///
+/// Reads the `wrap-content-at` key of the store configuration. Content is not wrapped if it is
+/// not set, which is the default.
+pub fn get_wrap_content_at(value: &Option<Value>) -> Option<usize> {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("wrap-content-at") {
+                Some(&Value::Integer(i)) if i > 0 => Some(i as usize),
+                Some(_) => {
+                    warn!("'wrap-content-at' configuration key should contain a positive \
+                           Integer, does not");
+                    None
+                },
+                None => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Get the maximum number of items in the `imag.history` header of entries
+///
+/// Reads the `history-length` key of the store configuration. The history is not recorded if it
+/// is not set, which is the default.
+pub fn get_history_length(value: &Option<Value>) -> Option<usize> {
+    match value {
+        &Some(Value::Table(ref t)) => {
+            match t.get("history-length") {
+                Some(&Value::Integer(i)) if i > 0 => Some(i as usize),
+                Some(_) => {
+                    warn!("'history-length' configuration key should contain a positive \
+                           Integer, does not");
+                    None
+                },
+                None => None,
+            }
+        },
+        _ => None,
+    }
+}
+
+/// Get the aliases for store ids which are set in the store configuration
+///
+/// Reads the `aliases` table of the store configuration, which maps alias names to store ids
+/// relative to the store:
+///
+/// ```toml
+/// [store.aliases]
+/// todo = "notes/todo~0.1.0"
+/// ```
+pub fn get_aliases(value: &Option<Value>) -> BTreeMap<String, String> {
+    let mut aliases = BTreeMap::new();
+    match value {
+        &Some(Value::Table(ref t)) => match t.get("aliases") {
+            Some(&Value::Table(ref a)) => for (name, id) in a {
+                match id {
+                    &Value::String(ref id) => { aliases.insert(name.clone(), id.clone()); },
+                    _ => warn!("Alias '{}' should be a String, is not", name),
+                }
+            },
+            Some(_) => warn!("'aliases' configuration key should contain Table, does not"),
+            None => {},
+        },
+        _ => {},
+    }
+    aliases
+}
+
 #[derive(Debug)]
 pub struct AspectConfig {
     parallel: bool,
+    non_fatal: bool,
     config: Value,
 }
 
@@ -194,9 +422,31 @@ impl AspectConfig {
 
     pub fn new(init: Value) -> AspectConfig {
         let parallel = AspectConfig::is_parallel(&init);
+        let non_fatal = AspectConfig::is_non_fatal(&init);
         AspectConfig {
             config: init,
             parallel: parallel,
+            non_fatal: non_fatal,
+        }
+    }
+
+    /// Whether errors of the hooks of the aspect should only be logged instead of aborting the
+    /// store operation, set by `non-fatal = true` in the aspect configuration
+    pub fn non_fatal(&self) -> bool {
+        self.non_fatal
+    }
+
+    fn is_non_fatal(init: &Value) -> bool {
+        match init {
+            &Value::Table(ref t) => match t.get("non-fatal") {
+                Some(&Value::Boolean(b)) => b,
+                Some(_) => {
+                    warn!("'non-fatal' aspect configuration key should contain Boolean, does not");
+                    false
+                },
+                None => false,
+            },
+            _ => false,
         }
     }
 
@@ -37,6 +37,15 @@ pub enum StoreErrorKind {
     StorePathLacksVersion,
     GlobError,
     EncodingError,
+    StoreVersionMismatch,
+    IndexError,
+    EntryNotArchived,
+    PartialDeletion,
+    InvalidStoreId,
+    ConcurrentModification,
+    HeaderKeyExists,
+    AliasesError,
+    MigrationError,
         // maybe more
 }
 
@@ -70,6 +79,15 @@ fn store_error_type_as_str(e: &StoreErrorKind) -> &'static str {
         &StoreErrorKind::StorePathLacksVersion => "The supplied store path has no version part",
         &StoreErrorKind::GlobError => "glob() error",
         &StoreErrorKind::EncodingError => "Encoding error",
+        &StoreErrorKind::StoreVersionMismatch => "Store was created by an incompatible version",
+        &StoreErrorKind::IndexError => "Store index error",
+        &StoreErrorKind::EntryNotArchived => "Entry is not archived",
+        &StoreErrorKind::PartialDeletion => "Deleting entries failed partway",
+        &StoreErrorKind::InvalidStoreId => "Store id points outside of the store",
+        &StoreErrorKind::ConcurrentModification => "Entry was modified by another program",
+        &StoreErrorKind::HeaderKeyExists => "Header Key exists already",
+        &StoreErrorKind::AliasesError => "Aliases file could not be read or written",
+        &StoreErrorKind::MigrationError => "Migrating the entry header failed",
     }
 }
 
@@ -135,6 +153,55 @@ impl Error for StoreError {
 
 }
 
+/**
+ * Cause of a `PartialDeletion` StoreError, holding the number of entries which were deleted before
+ * the deletion failed
+ */
+#[derive(Debug)]
+pub struct PartialDeletion {
+    deleted: usize,
+    cause: StoreError,
+}
+
+impl PartialDeletion {
+
+    pub fn new(deleted: usize, cause: StoreError) -> PartialDeletion {
+        PartialDeletion {
+            deleted: deleted,
+            cause: cause,
+        }
+    }
+
+    /**
+     * Get the number of entries which were deleted successfully
+     */
+    pub fn deleted(&self) -> usize {
+        self.deleted
+    }
+
+}
+
+impl Display for PartialDeletion {
+
+    fn fmt(&self, fmt: &mut Formatter) -> Result<(), FmtError> {
+        try!(write!(fmt, "{} entries were deleted before failing: {}", self.deleted, self.cause));
+        Ok(())
+    }
+
+}
+
+impl Error for PartialDeletion {
+
+    fn description(&self) -> &str {
+        "Deleting entries failed partway"
+    }
+
+    fn cause(&self) -> Option<&Error> {
+        Some(&self.cause)
+    }
+
+}
+
 impl From<ParserError> for StoreError {
     fn from(ps: ParserError) -> StoreError {
         StoreError {
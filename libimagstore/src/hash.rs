@@ -0,0 +1,26 @@
+//! Stable hashing of strings
+//!
+//! The hashes are written to disk (for example to the index), so they have to be the same on
+//! every platform and with every Rust version, which the hashers of the standard library do not
+//! guarantee.
+
+/// Hash a string with 64 bit FNV-1a, formatted as 16 hex digits
+pub fn hash_str(s: &str) -> String {
+    let hash = s.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ (byte as u64)).wrapping_mul(0x100000001b3)
+    });
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod test {
+    use super::hash_str;
+
+    #[test]
+    fn test_hash_str_is_fnv1a() {
+        assert_eq!(hash_str(""), "cbf29ce484222325");
+        assert_eq!(hash_str("a"), "af63dc4c8601ec8c");
+        assert_eq!(hash_str("foobar"), "85944171f73967e8");
+    }
+
+}
@@ -34,6 +34,18 @@ impl Aspect {
         self.hooks.push(h);
     }
 
+    /// Whether an error of a hook in this aspect aborts the store operation
+    ///
+    /// Aspects are fatal unless `non-fatal = true` is set in their configuration.
+    pub fn is_fatal(&self) -> bool {
+        self.cfg.as_ref().map(|c| !c.non_fatal()).unwrap_or(true)
+    }
+
+    /// Get the names of the hooks registered in this aspect
+    pub fn hook_names(&self) -> Vec<String> {
+        self.hooks.iter().map(|h| String::from(h.name())).collect()
+    }
+
 }
 
 impl StoreIdAccessor for Aspect {
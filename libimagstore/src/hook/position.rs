@@ -6,6 +6,11 @@ pub enum HookPosition {
     PostRetrieve,
     PreUpdate,
     PostUpdate,
+    /// After an entry was written to disk by `Store::update()`, before the `PostUpdate` hooks
+    ///
+    /// The hooks in this position only get read access to the entry. Unless `durable-writes` is
+    /// disabled, the file of the entry is synced to disk already when they run.
+    PostWrite,
     PreDelete,
     PostDelete,
 }
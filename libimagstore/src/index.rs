@@ -0,0 +1,156 @@
+//! On-disk index of the entries in the store
+//!
+//! The index maps the ids of the entries (relative to the store path) to a summary of the entry:
+//! its header and a hash of its content. It lives in the `.imag_index` file in the store root.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use toml::{Parser, Value};
+
+use error::{StoreError, StoreErrorKind};
+use hash::hash_str;
+use store::{Entry, Result};
+
+/// Name of the index file in the store root
+pub const INDEX_FILE: &'static str = ".imag_index";
+
+/// Summary of an entry in the index
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexEntry {
+    header: Value,
+    content_hash: String,
+}
+
+impl IndexEntry {
+
+    pub fn new(e: &Entry) -> IndexEntry {
+        IndexEntry {
+            header: e.get_header().header().clone(),
+            content_hash: content_hash(&e.get_content()[..]),
+        }
+    }
+
+    /// The header of the entry
+    pub fn header(&self) -> &Value {
+        &self.header
+    }
+
+    /// The hash of the content of the entry, see `content_hash()`
+    pub fn content_hash(&self) -> &str {
+        &self.content_hash[..]
+    }
+
+    fn from_value(v: &Value) -> Option<IndexEntry> {
+        match v {
+            &Value::Table(ref t) => {
+                match (t.get("header"), t.get("hash")) {
+                    (Some(header @ &Value::Table(_)), Some(&Value::String(ref hash))) => {
+                        Some(IndexEntry {
+                            header: header.clone(),
+                            content_hash: hash.clone(),
+                        })
+                    },
+                    _ => None,
+                }
+            },
+            _ => None,
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        let mut t = BTreeMap::new();
+        t.insert(String::from("header"), self.header.clone());
+        t.insert(String::from("hash"), Value::String(self.content_hash.clone()));
+        Value::Table(t)
+    }
+
+}
+
+/// Compute the hash of the content of an entry, as it is stored in the index
+///
+/// This is the same hash as `Entry::content_hash()`.
+pub fn content_hash(content: &str) -> String {
+    hash_str(content)
+}
+
+#[derive(Debug)]
+pub struct Index {
+    path: PathBuf,
+    entries: BTreeMap<String, IndexEntry>,
+}
+
+impl Index {
+
+    /// Create a new, empty index which is written to `path`
+    pub fn new(path: PathBuf) -> Index {
+        Index {
+            path: path,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Load the index from `path`
+    pub fn load(path: PathBuf) -> Result<Index> {
+        let mut s = String::new();
+        try!(File::open(&path)
+             .and_then(|mut f| f.read_to_string(&mut s))
+             .map_err(|e| StoreError::new(StoreErrorKind::IndexError, Some(Box::new(e)))));
+
+        let table = try!(Parser::new(&s[..])
+            .parse()
+            .ok_or(StoreError::new(StoreErrorKind::IndexError, None)));
+
+        let mut entries = BTreeMap::new();
+        for (key, value) in table.into_iter() {
+            let ie = try!(IndexEntry::from_value(&value)
+                .ok_or(StoreError::new(StoreErrorKind::IndexError, None)));
+            entries.insert(key, ie);
+        }
+
+        Ok(Index {
+            path: path,
+            entries: entries,
+        })
+    }
+
+    /// Write the index to disk
+    pub fn save(&self) -> Result<()> {
+        let table : BTreeMap<String, Value> = self.entries
+            .iter()
+            .map(|(k, ie)| (k.clone(), ie.to_value()))
+            .collect();
+        let s = ::toml::encode_str(&Value::Table(table));
+
+        File::create(&self.path)
+            .and_then(|mut f| f.write_all(s.as_bytes()))
+            .map_err(|e| StoreError::new(StoreErrorKind::IndexError, Some(Box::new(e))))
+    }
+
+    pub fn insert(&mut self, key: String, ie: IndexEntry) {
+        self.entries.insert(key, ie);
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+    }
+
+    /// Move the index entry at `old` to `new`
+    pub fn rename(&mut self, old: &str, new: String) {
+        if let Some(ie) = self.entries.remove(old) {
+            self.entries.insert(new, ie);
+        }
+    }
+
+    /// Replace all entries of the index
+    pub fn replace(&mut self, entries: BTreeMap<String, IndexEntry>) {
+        self.entries = entries;
+    }
+
+    pub fn entries(&self) -> &BTreeMap<String, IndexEntry> {
+        &self.entries
+    }
+
+}
@@ -1,13 +1,18 @@
 
 use error::{StoreError, StoreErrorKind};
-use std::io::{Seek, SeekFrom};
+use std::io::{Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::fs::{File, OpenOptions, create_dir_all};
+use std::fs::{File, OpenOptions, create_dir_all, remove_file, rename};
+
+use fs2::FileExt;
 
 /**
  * LazyFile type
  *
  * A lazy file is either absent, but a path to it is available, or it is present.
+ *
+ * A present file is always locked with an exclusive advisory lock (`flock()`), which is released
+ * when the file is closed or `LazyFile::unlock()` is called.
  */
 #[derive(Debug)]
 pub enum LazyFile {
@@ -29,6 +34,88 @@ fn create_file<A: AsRef<Path>>(p: A) -> ::std::io::Result<File> {
     OpenOptions::new().write(true).read(true).create(true).open(p)
 }
 
+/// Create a new file at `p`, failing if there is a file already
+///
+/// This is atomic, so it also fails if another process creates the file at the same time.
+fn create_new_file<A: AsRef<Path>>(p: A) -> ::std::io::Result<File> {
+    if let Some(parent) = p.as_ref().parent() {
+        debug!("Implicitely creating directory: {:?}", parent);
+        try!(create_dir_all(parent));
+    }
+    OpenOptions::new().write(true).read(true).create_new(true).open(p)
+}
+
+fn lock_file(f: File) -> Result<File, StoreError> {
+    f.try_lock_exclusive()
+        .map_err(|e| StoreError::new(StoreErrorKind::IdLocked, Some(Box::new(e))))
+        .map(|_| f)
+}
+
+/// Open the file at `p` and lock it
+///
+/// If `p` is replaced (see `LazyFile::replace_with()`) between opening and locking it, the lock is
+/// held on the old file, which is not at `p` anymore. The file is opened again in that case, so the
+/// lock is always taken on the file which is at `p`.
+fn open_locked_file<A: AsRef<Path>>(p: A) -> Result<File, StoreError> {
+    loop {
+        let file = try!(open_file(&p).map_err(|e| {
+            StoreError::new(StoreErrorKind::FileNotFound, Some(Box::new(e)))
+        }).and_then(lock_file));
+
+        if try!(is_file_at(&file, p.as_ref())) {
+            return Ok(file);
+        }
+        debug!("{:?} was replaced while it was locked, opening it again", p.as_ref());
+    }
+}
+
+/// Whether `file` is the file at `p`
+#[cfg(unix)]
+fn is_file_at(file: &File, p: &Path) -> Result<bool, StoreError> {
+    use std::fs::metadata;
+    use std::os::unix::fs::MetadataExt;
+
+    let opened = try!(file.metadata()
+        .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))));
+    Ok(metadata(p).map(|m| m.dev() == opened.dev() && m.ino() == opened.ino()).unwrap_or(false))
+}
+
+#[cfg(not(unix))]
+fn is_file_at(_: &File, _: &Path) -> Result<bool, StoreError> {
+    Ok(true)
+}
+
+/// Path of the temporary file which is used to write `p`, in the same directory as `p`
+fn temp_path_for<A: AsRef<Path>>(p: A) -> PathBuf {
+    let name = p.as_ref()
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or(String::new());
+    p.as_ref().with_file_name(format!(".{}.tmp", name))
+}
+
+/// Write `content` to the (locked) temporary file for `p`, without touching `p` itself
+///
+/// With `durable`, the temporary file is synced to disk before this returns.
+fn write_temp_file<A: AsRef<Path>>(p: A, content: &[u8], durable: bool)
+    -> Result<(PathBuf, File), StoreError>
+{
+    let temp_path = temp_path_for(&p);
+    let mut file = try!(create_file(&temp_path)
+        .map_err(|e| StoreError::new(StoreErrorKind::FileNotCreated, Some(Box::new(e))))
+        .and_then(lock_file));
+
+    let written = file.set_len(0)
+        .and_then(|_| file.write_all(content))
+        .and_then(|_| if durable { file.sync_all() } else { Ok(()) });
+
+    if let Err(e) = written {
+        let _ = remove_file(&temp_path);
+        return Err(StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))));
+    }
+    Ok((temp_path, file))
+}
+
 impl LazyFile {
 
     /**
@@ -44,12 +131,7 @@ impl LazyFile {
                     .map_err(|e| StoreError::new(StoreErrorKind::FileNotCreated, Some(Box::new(e))))
                     .map(|_| f)
             },
-            LazyFile::Absent(ref p) => {
-                try!(open_file(p).map_err(|e| {
-                    StoreError::new(StoreErrorKind::FileNotFound,
-                                    Some(Box::new(e)))
-                }))
-            }
+            LazyFile::Absent(ref p) => try!(open_locked_file(p)),
         };
         *self = LazyFile::File(file);
         if let LazyFile::File(ref mut f) = *self {
@@ -59,17 +141,26 @@ impl LazyFile {
     }
 
     /**
-     * Create a file out of this LazyFile object
+     * Create a new file out of this LazyFile object
+     *
+     * This fails with `EntryAlreadyExists` if the file exists on disk already, even if it was
+     * created by another process just now.
      */
-    pub fn create_file(&mut self) -> Result<&mut File, StoreError> {
-        debug!("Creating lazy file: {:?}", self);
+    pub fn create_new_file(&mut self) -> Result<&mut File, StoreError> {
+        use std::io::ErrorKind;
+
+        debug!("Creating new lazy file: {:?}", self);
         let file = match *self {
-            LazyFile::File(ref mut f) => return Ok(f),
+            LazyFile::File(_) => return Err(StoreError::new(StoreErrorKind::EntryAlreadyExists,
+                                                            None)),
             LazyFile::Absent(ref p) => {
-                try!(create_file(p).map_err(|e| {
-                    StoreError::new(StoreErrorKind::FileNotFound,
-                                    Some(Box::new(e)))
-                }))
+                try!(create_new_file(p).map_err(|e| {
+                    if e.kind() == ErrorKind::AlreadyExists {
+                        StoreError::new(StoreErrorKind::EntryAlreadyExists, Some(Box::new(e)))
+                    } else {
+                        StoreError::new(StoreErrorKind::FileNotCreated, Some(Box::new(e)))
+                    }
+                }).and_then(lock_file))
             }
         };
         *self = LazyFile::File(file);
@@ -78,13 +169,64 @@ impl LazyFile {
         }
         unreachable!()
     }
+
+    /**
+     * Atomically replace the file at `path` with `content`
+     *
+     * The content is written to a temporary file in the same directory first, which is then
+     * renamed over `path`, so a crash in between leaves the old file untouched. The new file is
+     * locked before it is renamed and becomes the file behind this LazyFile object.
+     *
+     * With `durable`, the new file and its directory are synced to disk, so the new content
+     * survives a power loss once this returns.
+     */
+    pub fn replace_with(&mut self, path: &Path, content: &[u8], durable: bool)
+        -> Result<&mut File, StoreError>
+    {
+        debug!("Replacing lazy file: {:?}", self);
+        let (temp_path, file) = try!(write_temp_file(path, content, durable));
+        if let Err(e) = rename(&temp_path, path) {
+            let _ = remove_file(&temp_path);
+            return Err(StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))));
+        }
+
+        // The content is synced already, syncing the directory makes the rename durable too
+        if let Some(dir) = path.parent().and_then(|dir| if durable { Some(dir) } else { None }) {
+            if let Err(e) = File::open(dir).and_then(|d| d.sync_all()) {
+                warn!("Could not sync directory {:?}: {:?}", dir, e);
+            }
+        }
+
+        *self = LazyFile::File(file);
+        self.get_file_mut()
+    }
+
+    /**
+     * Close the file behind this LazyFile object, which releases its lock
+     *
+     * `path` has to be the path of the file, it is opened again from there when it is needed.
+     */
+    pub fn close(&mut self, path: PathBuf) {
+        debug!("Closing lazy file: {:?}", self);
+        *self = LazyFile::Absent(path);
+    }
+
+    /**
+     * Release the lock on the file behind this LazyFile object, if it is present
+     */
+    pub fn unlock(&self) -> Result<(), StoreError> {
+        match *self {
+            LazyFile::File(ref f) => FileExt::unlock(f)
+                .map_err(|e| StoreError::new(StoreErrorKind::LockError, Some(Box::new(e)))),
+            LazyFile::Absent(_) => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use super::LazyFile;
+    use super::{LazyFile, is_file_at, lock_file, temp_path_for, write_temp_file};
     use std::io::{Read, Write};
-    use std::path::PathBuf;
     use tempdir::TempDir;
 
     fn get_dir() -> TempDir {
@@ -94,23 +236,21 @@ mod test {
     #[test]
     fn lazy_file() {
         let dir = get_dir();
-        let mut path = PathBuf::from(dir.path());
-        path.set_file_name("test1");
+        let path = dir.path().join("test1");
         let mut lf = LazyFile::Absent(path);
 
-        write!(lf.create_file().unwrap(), "Hello World").unwrap();
+        write!(lf.create_new_file().unwrap(), "Hello World").unwrap();
         dir.close().unwrap();
     }
 
     #[test]
     fn lazy_file_with_file() {
         let dir = get_dir();
-        let mut path = PathBuf::from(dir.path());
-        path.set_file_name("test2");
+        let path = dir.path().join("test2");
         let mut lf = LazyFile::Absent(path.clone());
 
         {
-            let mut file = lf.create_file().unwrap();
+            let file = lf.create_new_file().unwrap();
 
             file.write(b"Hello World").unwrap();
             file.sync_all().unwrap();
@@ -125,4 +265,100 @@ mod test {
 
         dir.close().unwrap();
     }
+
+    #[test]
+    fn lazy_file_is_locked() {
+        use error::StoreErrorKind;
+
+        let dir = get_dir();
+        let path = dir.path().join("test3");
+        let mut lf = LazyFile::Absent(path.clone());
+        lf.create_new_file().unwrap();
+
+        let mut other = LazyFile::Absent(path.clone());
+        let err = other.get_file_mut().unwrap_err();
+        assert_eq!(err.err_type(), StoreErrorKind::IdLocked);
+
+        lf.unlock().unwrap();
+        assert!(other.get_file_mut().is_ok());
+    }
+
+    #[test]
+    fn lazy_file_create_new_file() {
+        use error::StoreErrorKind;
+
+        let dir = get_dir();
+        let path = dir.path().join("test6");
+        assert!(LazyFile::Absent(path.clone()).create_new_file().is_ok());
+
+        let err = LazyFile::Absent(path.clone()).create_new_file().unwrap_err();
+        assert_eq!(err.err_type(), StoreErrorKind::EntryAlreadyExists);
+    }
+
+    #[test]
+    fn lazy_file_replace_with() {
+        let dir = get_dir();
+        let path = dir.path().join("test4");
+        let mut lf = LazyFile::Absent(path.clone());
+        write!(lf.create_new_file().unwrap(), "Hello World").unwrap();
+
+        lf.replace_with(&path, b"Hello Replaced World", true).unwrap();
+        let mut s = String::new();
+        lf.get_file_mut().unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello Replaced World");
+        assert!(!temp_path_for(&path).exists());
+
+        // The new file is locked as well
+        assert!(LazyFile::Absent(path.clone()).get_file_mut().is_err());
+    }
+
+    #[test]
+    fn lazy_file_interrupted_write() {
+        use std::fs::File;
+
+        let dir = get_dir();
+        let path = dir.path().join("test5");
+        write!(File::create(&path).unwrap(), "Hello World").unwrap();
+
+        // Write, but "crash" before renaming
+        let large = vec![b'a'; 1024 * 1024];
+        let (temp_path, _) = write_temp_file(&path, &large[..], false).unwrap();
+        assert_eq!(temp_path.parent(), path.parent());
+        assert_eq!(temp_path.metadata().unwrap().len(), large.len() as u64);
+
+        let mut s = String::new();
+        File::open(&path).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "Hello World");
+    }
+
+    #[test]
+    fn lazy_file_close() {
+        let dir = get_dir();
+        let path = dir.path().join("test7");
+        let mut lf = LazyFile::Absent(path.clone());
+        lf.create_new_file().unwrap();
+        assert!(LazyFile::Absent(path.clone()).get_file_mut().is_err());
+
+        lf.close(path.clone());
+        assert!(LazyFile::Absent(path.clone()).get_file_mut().is_ok());
+    }
+
+    #[test]
+    fn lazy_file_replaced_file_is_not_at_path() {
+        use std::fs::File;
+
+        let dir = get_dir();
+        let path = dir.path().join("test8");
+        let mut lf = LazyFile::Absent(path.clone());
+        write!(lf.create_new_file().unwrap(), "Hello World").unwrap();
+
+        // Opened by another process right before the file is replaced
+        let old = File::open(&path).unwrap();
+        lf.replace_with(&path, b"Hello Replaced World", false).unwrap();
+
+        // The old file can be locked, but it is not the entry anymore
+        let old = lock_file(old).unwrap();
+        assert!(!is_file_at(&old, &path).unwrap());
+        assert!(is_file_at(lf.get_file_mut().unwrap(), &path).unwrap());
+    }
 }
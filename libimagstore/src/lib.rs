@@ -14,19 +14,26 @@
 
 #[macro_use] extern crate log;
 #[macro_use] extern crate version;
+extern crate chrono;
 extern crate fs2;
 extern crate glob;
 #[macro_use] extern crate lazy_static;
 extern crate regex;
 extern crate toml;
-#[cfg(test)] extern crate tempdir;
+#[cfg(any(test, feature = "testing"))] extern crate tempdir;
 extern crate semver;
 extern crate crossbeam;
+extern crate rustc_serialize;
 
 pub mod storeid;
 pub mod error;
 pub mod hook;
+pub mod index;
+pub mod migration;
 pub mod store;
+#[cfg(feature = "testing")] pub mod testing;
 mod configuration;
+mod hash;
 mod lazyfile;
+mod wrap;
 
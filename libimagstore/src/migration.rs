@@ -0,0 +1,149 @@
+//! Migrations of entry headers which were written by older versions of imag
+//!
+//! A migration is registered for a version range: it is applied to headers whose `imag.version`
+//! is at least `from` and lower than `to`, and bumps the header to `to`. Migrations are applied
+//! in the order of their `from` version, so they can be chained.
+//!
+//! To migrate entries whenever they are retrieved, register the `MigrationHook` of the store as
+//! post-retrieve hook, see `Store::migration_hook()`.
+
+use std::fmt::{Debug, Formatter, Error as FmtError};
+use std::sync::{Arc, Mutex};
+
+use semver::Version;
+use toml::Value;
+
+use error::{StoreError, StoreErrorKind};
+use hook::Hook;
+use hook::accessor::{HookDataAccessor, HookDataAccessorProvider, MutableHookDataAccessor};
+use hook::error::{HookError, HookErrorKind};
+use hook::result::HookResult;
+use store::{EntryHeader, FileLockEntry, Result};
+
+/// A function which migrates a header, see `Store::register_migration()`
+///
+/// A migration which fails stops the migration of the header, which is left unchanged then.
+pub type Migration = Box<Fn(&mut EntryHeader) -> Result<()> + Send + Sync>;
+
+struct RegisteredMigration {
+    from: Version,
+    to: Version,
+    migration: Migration,
+}
+
+/// The migrations registered in a store
+pub struct Migrations {
+    migrations: Vec<RegisteredMigration>,
+}
+
+impl Migrations {
+
+    pub fn new() -> Migrations {
+        Migrations {
+            migrations: vec![],
+        }
+    }
+
+    /// Register a migration for headers with a version in `[from, to)`
+    pub fn register(&mut self, from: Version, to: Version, migration: Migration) {
+        self.migrations.push(RegisteredMigration {
+            from: from,
+            to: to,
+            migration: migration,
+        });
+        self.migrations.sort_by(|a, b| a.from.cmp(&b.from));
+    }
+
+    /// Migrate `header` to the `current` version
+    ///
+    /// Applies all matching migrations and sets `imag.version` to `current` afterwards. Headers
+    /// which are not older than `current` are not touched. Returns whether the header was changed.
+    ///
+    /// The migrations are applied to a copy of the header. If one of them fails, the remaining
+    /// ones are not applied and `header` is left unchanged.
+    pub fn apply(&self, header: &mut EntryHeader, current: &Version) -> Result<bool> {
+        let mut version = match try!(header.read("imag.version")) {
+            Some(Value::String(s)) => try!(Version::parse(&s[..])
+                .map_err(|_| StoreError::new(StoreErrorKind::HeaderTypeFailure, None))),
+            Some(_) => return Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
+            None    => return Err(StoreError::new(StoreErrorKind::HeaderKeyNotFound, None)),
+        };
+
+        if version >= *current {
+            return Ok(false);
+        }
+
+        let mut migrated = header.clone();
+        for m in self.migrations.iter() {
+            if m.from <= version && version < m.to && m.to <= *current {
+                debug!("Migrating header from {} to {}", version, m.to);
+                try!((m.migration)(&mut migrated).map_err(|e| {
+                    StoreError::new(StoreErrorKind::MigrationError, Some(Box::new(e)))
+                }));
+                version = m.to.clone();
+            }
+        }
+
+        try!(migrated.set("imag.version", Value::String(format!("{}", current))));
+        *header = migrated;
+        Ok(true)
+    }
+
+}
+
+/// Post-retrieve hook which migrates the retrieved entries with the migrations of a store
+///
+/// The hook only changes the entry in memory, it is written when it is dropped or updated.
+pub struct MigrationHook {
+    migrations: Arc<Mutex<Migrations>>,
+}
+
+impl MigrationHook {
+
+    pub fn new(migrations: Arc<Mutex<Migrations>>) -> MigrationHook {
+        MigrationHook {
+            migrations: migrations,
+        }
+    }
+
+}
+
+impl Debug for MigrationHook {
+
+    fn fmt(&self, fmt: &mut Formatter) -> ::std::result::Result<(), FmtError> {
+        write!(fmt, "MigrationHook")
+    }
+
+}
+
+impl Hook for MigrationHook {
+
+    fn name(&self) -> &'static str {
+        "migration"
+    }
+
+    fn set_config(&mut self, _: &Value) { }
+
+}
+
+impl HookDataAccessorProvider for MigrationHook {
+
+    fn accessor(&self) -> HookDataAccessor {
+        HookDataAccessor::MutableAccess(self)
+    }
+
+}
+
+impl MutableHookDataAccessor for MigrationHook {
+
+    fn access_mut(&self, fle: &mut FileLockEntry) -> HookResult<()> {
+        let current = Version::parse(version!()).unwrap();
+        self.migrations
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None))
+            .and_then(|migrations| migrations.apply(fle.get_header_mut(), &current))
+            .map(|_| ())
+            .map_err(|e| HookError::new(HookErrorKind::HookExecutionError, Some(Box::new(e))))
+    }
+
+}
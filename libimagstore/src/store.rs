@@ -1,36 +1,47 @@
 use std::collections::HashMap;
-use std::fs::{File, remove_file};
+use std::fs::{File, Metadata, remove_file};
 use std::ops::Drop;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::result::Result as RResult;
 use std::sync::Arc;
 use std::sync::RwLock;
 use std::collections::BTreeMap;
-use std::io::{Seek, SeekFrom};
+use std::io::{Read, Seek, SeekFrom};
 use std::io::Write;
 use std::convert::From;
 use std::convert::Into;
 use std::sync::Mutex;
+use std::time::SystemTime;
+use std::thread::{ThreadId, current as current_thread};
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::fmt::Formatter;
 use std::fmt::Debug;
 use std::fmt::Error as FMTError;
 
+use chrono::{DateTime, Utc};
 use toml::{Table, Value};
 use regex::Regex;
 use glob::glob;
+use semver::Version;
+use rustc_serialize::json::{Json, Object};
 
 use error::{ParserErrorKind, ParserError};
-use error::{StoreError, StoreErrorKind};
-use storeid::{StoreId, StoreIdIterator};
+use hash::hash_str;
+use error::{StoreError, StoreErrorKind, PartialDeletion};
+use storeid::{StoreId, StoreIdIterator, StoreLayout};
 use lazyfile::LazyFile;
+use index::{Index, IndexEntry, INDEX_FILE};
+use migration::{Migration, MigrationHook, Migrations};
+use wrap::wrap_content;
 
 use hook::aspect::Aspect;
 use hook::accessor::{ MutableHookDataAccessor,
             NonMutableHookDataAccessor,
             StoreIdAccessor};
 use hook::position::HookPosition;
+use hook::error::HookError;
+use hook::result::HookResult;
 use hook::Hook;
 
 /// The Result Type returned by any interaction with the store that could fail
@@ -43,6 +54,80 @@ enum StoreEntryStatus {
     Borrowed
 }
 
+/// Counters about how the store accesses the filesystem, see `Store::metrics()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StoreMetrics {
+    /// How often an entry file was opened
+    pub files_opened: usize,
+
+    /// How many bytes were read from entry files
+    pub bytes_read: usize,
+
+    /// How often a written entry file was synced to disk, see the `durable-writes` store
+    /// configuration key
+    pub fsyncs: usize,
+}
+
+impl StoreMetrics {
+
+    fn add(&mut self, other: &StoreMetrics) {
+        self.files_opened += other.files_opened;
+        self.bytes_read   += other.bytes_read;
+        self.fsyncs       += other.fsyncs;
+    }
+
+}
+
+/// Where `Store::retrieve_with_source()` got an entry from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntrySource {
+    /// The entry was in the cache of the store already
+    Cache,
+
+    /// The entry was not in the cache and was read from its file
+    Disk,
+
+    /// The entry was neither in the cache nor on disk, so a new entry was built
+    New,
+}
+
+/// What was removed by `Store::vacuum()`
+#[derive(Debug, Default, PartialEq)]
+pub struct VacuumReport {
+    /// Entries without content and without any header data besides the imag version
+    pub empty_entries: Vec<StoreId>,
+
+    /// Temporary files left by interrupted writes of entries which are not borrowed, like
+    /// `.<entry>.tmp` and `.<entry>.body.tmp`
+    pub temp_files: Vec<PathBuf>,
+
+    /// Directories which were empty
+    pub directories: Vec<PathBuf>,
+}
+
+/// Modification time and size of an entry file, to detect changes by other programs
+#[derive(Debug, Clone, PartialEq)]
+struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+}
+
+impl FileStamp {
+
+    fn of(metadata: &Metadata) -> FileStamp {
+        FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        }
+    }
+
+}
+
+/// Name of the file in the store root in which `Store::set_alias()` stores aliases
+///
+/// The file is hidden, so it is never mistaken for an entry.
+pub const ALIASES_FILE: &'static str = ".imag_aliases";
+
 /// A store entry, depending on the option type it is either borrowed currently
 /// or not.
 #[derive(Debug)]
@@ -50,15 +135,46 @@ struct StoreEntry {
     id: StoreId,
     file: LazyFile,
     status: StoreEntryStatus,
+    metrics: StoreMetrics,
+
+    /// The state of the file when it was last read or written by the store
+    stamp: Option<FileStamp>,
 }
 
 impl StoreEntry {
 
-    fn new(id: StoreId) -> StoreEntry {
+    fn new(id: StoreId, path: PathBuf) -> StoreEntry {
         StoreEntry {
-            id: id.clone(),
-            file: LazyFile::Absent(id),
+            id: id,
+            file: LazyFile::Absent(path),
             status: StoreEntryStatus::Present,
+            metrics: StoreMetrics::default(),
+            stamp: None,
+        }
+    }
+
+    /// Remember the state of the opened file, see `StoreEntry::check_unmodified()`
+    fn update_stamp(&mut self) {
+        self.stamp = match self.file {
+            LazyFile::File(ref f) => f.metadata().ok().map(|m| FileStamp::of(&m)),
+            LazyFile::Absent(_)   => None,
+        };
+    }
+
+    /// Check that the file at `path` was not changed since the store read or wrote it last
+    ///
+    /// Fails with `ConcurrentModification` if the modification time or the size of the file
+    /// differ, for example because it was edited by another program while the entry was
+    /// borrowed.
+    fn check_unmodified(&self, path: &Path) -> Result<()> {
+        use std::fs::metadata;
+
+        match (&self.stamp, metadata(path)) {
+            (&Some(ref stamp), Ok(ref m)) if *stamp != FileStamp::of(m) => {
+                debug!("{:?} was modified externally", path);
+                Err(StoreError::new(StoreErrorKind::ConcurrentModification, None))
+            },
+            _ => Ok(()),
         }
     }
 
@@ -68,37 +184,104 @@ impl StoreEntry {
         self.status == StoreEntryStatus::Borrowed
     }
 
-    fn get_entry(&mut self) -> Result<Entry> {
+    fn is_opened(&self) -> bool {
+        match self.file {
+            LazyFile::File(_)   => true,
+            LazyFile::Absent(_) => false,
+        }
+    }
+
+    /// Read the entry from disk to borrow it
+    ///
+    /// Remembers the state of the file, so changes by other programs while the entry is borrowed
+    /// are detected when it is written.
+    fn get_entry(&mut self, max_depth: usize) -> Result<Entry> {
         if !self.is_borrowed() {
-            let file = self.file.get_file_mut();
-            if let Err(err) = file {
-                if err.err_type() == StoreErrorKind::FileNotFound {
-                    Ok(Entry::new(self.id.clone()))
-                } else {
-                    Err(err)
-                }
+            let entry = self.read_entry(max_depth);
+            self.update_stamp();
+            entry
+        } else {
+            return Err(StoreError::new(StoreErrorKind::EntryAlreadyBorrowed, None))
+        }
+    }
+
+    /// Read the entry from disk, regardless whether it is borrowed
+    ///
+    /// This does not change the remembered state of the file, see `StoreEntry::get_entry()`.
+    /// Headers nested deeper than `max_depth` fail to parse, see `EntryHeader::set_max_depth()`.
+    fn read_entry(&mut self, max_depth: usize) -> Result<Entry> {
+        let opened = self.is_opened();
+        let file = self.file.get_file_mut();
+        if file.is_ok() && !opened {
+            self.metrics.files_opened += 1;
+        }
+        if let Err(err) = file {
+            if err.err_type() == StoreErrorKind::FileNotFound {
+                Ok(Entry::new(self.id.clone()))
             } else {
-                // TODO:
-                let mut file = file.unwrap();
-                let entry = Entry::from_file(self.id.clone(), &mut file);
-                file.seek(SeekFrom::Start(0)).ok();
-                entry
+                Err(err)
             }
         } else {
-            return Err(StoreError::new(StoreErrorKind::EntryAlreadyBorrowed, None))
+            // TODO:
+            let file = file.unwrap();
+            let mut text = String::new();
+            let read = file.read_to_string(&mut text);
+            self.metrics.bytes_read += text.len();
+            file.seek(SeekFrom::Start(0)).ok();
+
+            try!(read);
+            Entry::from_str_with_max_depth(self.id.clone(), &text[..], max_depth)
         }
     }
 
-    fn write_entry(&mut self, entry: &Entry) -> Result<()> {
+    /// Write the entry to `path`, which has to be the path of this entry
+    ///
+    /// The entry is written to a temporary file which is then renamed to `path`, so a crash while
+    /// writing does not leave a truncated entry behind. If the file was changed by another program
+    /// since the store read it, it is not overwritten and this fails with
+    /// `ConcurrentModification`.
+    ///
+    /// With `separate_content`, the content is written to the body file of the entry (see
+    /// `body_path_for()`) and only the header, which refers to it in `imag.body`, to `path`.
+    /// Otherwise a body file which might exist from before is removed.
+    ///
+    /// With `durable`, the written files are synced to disk before this returns.
+    fn write_entry(&mut self, entry: &Entry, path: &Path, separate_content: bool, durable: bool)
+        -> Result<()>
+    {
         if self.is_borrowed() {
-            use std::io::Write;
-            let file = try!(self.file.create_file());
+            try!(self.check_unmodified(path));
+            if !self.is_opened() {
+                self.metrics.files_opened += 1;
+            }
 
             assert_eq!(self.id, entry.location);
-            try!(file.set_len(0)
-                .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))));
-            file.write_all(entry.to_str().as_bytes())
-                .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))))
+            let body_path = body_path_for(path);
+            let text = if separate_content {
+                let name = body_path.file_name().map(|n| n.to_string_lossy().into_owned());
+                let mut stub = entry.clone();
+                try!(stub.header.set("imag.body", Value::String(name.unwrap_or(String::new()))));
+                stub.content = String::new();
+                try!(write_body_file(&body_path, entry.get_content(), durable));
+                if durable {
+                    self.metrics.fsyncs += 1;
+                }
+                stub.to_str()
+            } else {
+                entry.to_str()
+            };
+
+            try!(self.file.replace_with(path, text.as_bytes(), durable));
+            if durable {
+                self.metrics.fsyncs += 1;
+            }
+            self.update_stamp();
+
+            if separate_content {
+                Ok(())
+            } else {
+                remove_body_file(path)
+            }
         } else {
             Ok(())
         }
@@ -114,6 +297,11 @@ pub struct Store {
      */
     configuration: Option<Value>,
 
+    /**
+     * How the entries are laid out on the filesystem
+     */
+    layout: StoreLayout,
+
     /*
      * Registered hooks
      */
@@ -124,9 +312,61 @@ pub struct Store {
     post_retrieve_aspects : Arc<Mutex<Vec<Aspect>>>,
     pre_update_aspects    : Arc<Mutex<Vec<Aspect>>>,
     post_update_aspects   : Arc<Mutex<Vec<Aspect>>>,
+    post_write_aspects    : Arc<Mutex<Vec<Aspect>>>,
     pre_delete_aspects    : Arc<Mutex<Vec<Aspect>>>,
     post_delete_aspects   : Arc<Mutex<Vec<Aspect>>>,
 
+    /**
+     * How deep each thread is in `Store::with_hooks_disabled()` calls, hooks are not executed
+     * for threads in here
+     */
+    hooks_disabled: Mutex<HashMap<ThreadId, usize>>,
+
+    /**
+     * Metrics of entries which are not in the `entries` cache anymore
+     */
+    metrics: Mutex<StoreMetrics>,
+
+    /**
+     * Index of the entries, if enabled in the configuration
+     */
+    index: Option<Mutex<Index>>,
+
+    /**
+     * Registered header migrations, see `Store::register_migration()`
+     */
+    migrations: Arc<Mutex<Migrations>>,
+
+    /**
+     * Held while the aliases file is changed, see `Store::set_alias()`
+     */
+    aliases_lock: Mutex<()>,
+
+    /**
+     * Maximum nesting depth of entry headers, see `EntryHeader::set_max_depth()`
+     */
+    header_max_depth: usize,
+
+    /**
+     * Width to which the content of entries is wrapped when they are written, if any
+     */
+    wrap_content_at: Option<usize>,
+
+    /**
+     * Maximum number of items in the `imag.history` header of entries, if it is recorded
+     */
+    history_length: Option<usize>,
+
+    /**
+     * Whether the content of entries is written to a separate file, see `body_path_for()`
+     */
+    separate_content: bool,
+
+    /**
+     * Whether written entry files are synced to disk, see `Store::update()`
+     */
+    durable_writes: bool,
+
     /**
      * Internal Path->File cache map
      *
@@ -165,6 +405,8 @@ impl Store {
             }
         }
 
+        try!(check_store_version(&location, version_mismatch_is_error(&store_config)));
+
         let pre_create_aspects = get_pre_create_aspect_names(&store_config)
             .into_iter().map(|n| {
                 let cfg = AspectConfig::get_for(&store_config, n.clone());
@@ -201,6 +443,12 @@ impl Store {
                 Aspect::new(n, cfg)
             }).collect();
 
+        let post_write_aspects = get_post_write_aspect_names(&store_config)
+            .into_iter().map(|n| {
+                let cfg = AspectConfig::get_for(&store_config, n.clone());
+                Aspect::new(n, cfg)
+            }).collect();
+
         let pre_delete_aspects = get_pre_delete_aspect_names(&store_config)
             .into_iter().map(|n| {
                 let cfg = AspectConfig::get_for(&store_config, n.clone());
@@ -213,20 +461,63 @@ impl Store {
                 Aspect::new(n, cfg)
             }).collect();
 
+        let layout = get_store_layout(&store_config);
+
+        let mut rebuild_index = false;
+        let index = if is_index_enabled(&store_config) {
+            let path = location.join(INDEX_FILE);
+            let index = if path.exists() {
+                Index::load(path.clone()).map_err(|e| {
+                    warn!("Could not load store index, rebuilding it: {:?}", e);
+                }).ok()
+            } else {
+                None
+            };
+
+            Some(Mutex::new(index.unwrap_or_else(|| {
+                rebuild_index = true;
+                Index::new(path)
+            })))
+        } else {
+            None
+        };
+
+        let header_max_depth = get_header_max_depth(&store_config);
+        let wrap_content_at = get_wrap_content_at(&store_config);
+        let history_length = get_history_length(&store_config);
+        let separate_content = is_separate_content_enabled(&store_config);
+        let durable_writes = is_durable_writes_enabled(&store_config);
+
         let store = Store {
             location: location,
             configuration: store_config,
+            layout: layout,
             pre_create_aspects    : Arc::new(Mutex::new(pre_create_aspects)),
             post_create_aspects   : Arc::new(Mutex::new(post_create_aspects)),
             pre_retrieve_aspects  : Arc::new(Mutex::new(pre_retrieve_aspects)),
             post_retrieve_aspects : Arc::new(Mutex::new(post_retrieve_aspects)),
             pre_update_aspects    : Arc::new(Mutex::new(pre_update_aspects)),
             post_update_aspects   : Arc::new(Mutex::new(post_update_aspects)),
+            post_write_aspects    : Arc::new(Mutex::new(post_write_aspects)),
             pre_delete_aspects    : Arc::new(Mutex::new(pre_delete_aspects)),
             post_delete_aspects   : Arc::new(Mutex::new(post_delete_aspects)),
+            hooks_disabled: Mutex::new(HashMap::new()),
+            metrics: Mutex::new(StoreMetrics::default()),
+            index: index,
+            migrations: Arc::new(Mutex::new(Migrations::new())),
+            aliases_lock: Mutex::new(()),
+            header_max_depth: header_max_depth,
+            wrap_content_at: wrap_content_at,
+            history_length: history_length,
+            separate_content: separate_content,
+            durable_writes: durable_writes,
             entries: Arc::new(RwLock::new(HashMap::new())),
         };
 
+        if rebuild_index {
+            try!(store.rebuild_index());
+        }
+
         debug!("Store building succeeded");
         Ok(store)
     }
@@ -236,17 +527,72 @@ impl Store {
         self.configuration.as_ref()
     }
 
-    fn storify_id(&self, id: StoreId) -> StoreId {
+    /// Make an id relative to the store an id inside the store
+    ///
+    /// Ids which already point into the store are kept. Ids which contain `..` components or are
+    /// absolute paths outside of the store would point to a file which is not in the store and
+    /// are rejected with `InvalidStoreId`.
+    fn storify_id(&self, id: StoreId) -> Result<StoreId> {
+        use std::path::Component;
+
         debug!("Create new store id out of: {:?} and {:?}", self.location, id);
+        if id.components().any(|c| c == Component::ParentDir) {
+            debug!("Id '{:?}' contains a parent directory component", id);
+            return Err(StoreError::new(StoreErrorKind::InvalidStoreId, None));
+        }
+
+        if id.starts_with(&self.location) {
+            return Ok(id);
+        }
+
+        if id.has_root() {
+            debug!("Id '{:?}' is absolute but not inside the store", id);
+            return Err(StoreError::new(StoreErrorKind::InvalidStoreId, None));
+        }
+
         let mut new_id = self.location.clone();
         new_id.push(id);
         debug!("Created: '{:?}'", new_id);
-        new_id
+        Ok(new_id)
+    }
+
+    /// Get the path on the filesystem for an already storified id
+    ///
+    /// For the hierarchical layout, this is the id itself. For the flat layout, everything after
+    /// the module name is encoded into a single file name.
+    fn id_to_path(&self, id: &StoreId) -> PathBuf {
+        use storeid::encode_path;
+
+        if self.layout != StoreLayout::Flat {
+            return id.clone();
+        }
+
+        let relative = match id.strip_prefix(&self.location) {
+            Ok(relative) => relative,
+            Err(_) => return id.clone(),
+        };
+
+        let mut components = relative.iter();
+        let module = match components.next() {
+            Some(module) => module,
+            None => return id.clone(),
+        };
+
+        let rest : Vec<&str> = components.filter_map(|c| c.to_str()).collect();
+        if rest.is_empty() {
+            return id.clone();
+        }
+
+        let mut path = self.location.clone();
+        path.push(module);
+        path.push(encode_path(&rest.join("/")[..]));
+        debug!("Flat layout path for {:?}: {:?}", id, path);
+        path
     }
 
     /// Creates the Entry at the given location (inside the entry)
     pub fn create<'a>(&'a self, id: StoreId) -> Result<FileLockEntry<'a>> {
-        let id = self.storify_id(id);
+        let id = try!(self.storify_id(id));
         if let Err(e) = self.execute_hooks_for_id(self.pre_create_aspects.clone(), &id) {
             return Err(e);
         }
@@ -259,11 +605,14 @@ impl Store {
         if hsmap.contains_key(&id) {
             return Err(StoreError::new(StoreErrorKind::EntryAlreadyExists, None))
         }
-        hsmap.insert(id.clone(), {
-            let mut se = StoreEntry::new(id.clone());
-            se.status = StoreEntryStatus::Borrowed;
-            se
-        });
+        let mut se = StoreEntry::new(id.clone(), self.id_to_path(&id));
+        // Create the file exclusively and lock it right away, so an entry which exists on disk or
+        // is created by another process meanwhile is never overwritten
+        try!(se.file.create_new_file());
+        se.update_stamp();
+        se.metrics.files_opened += 1;
+        se.status = StoreEntryStatus::Borrowed;
+        hsmap.insert(id.clone(), se);
 
         let mut fle = FileLockEntry::new(self, Entry::new(id.clone()), id);
         self.execute_hooks_for_mut_file(self.post_create_aspects.clone(), &mut fle)
@@ -274,7 +623,15 @@ impl Store {
     /// Borrow a given Entry. When the `FileLockEntry` is either `update`d or
     /// dropped, the new Entry is written to disk
     pub fn retrieve<'a>(&'a self, id: StoreId) -> Result<FileLockEntry<'a>> {
-        let id = self.storify_id(id);
+        self.retrieve_with_source(id).map(|(fle, _)| fle)
+    }
+
+    /// Like `Store::retrieve()`, but also tell whether the entry came from the cache, from disk
+    /// or is new
+    pub fn retrieve_with_source<'a>(&'a self, id: StoreId)
+        -> Result<(FileLockEntry<'a>, EntrySource)>
+    {
+        let id = try!(self.storify_id(id));
         if let Err(e) = self.execute_hooks_for_id(self.pre_retrieve_aspects.clone(), &id) {
             return Err(e);
         }
@@ -283,1522 +640,5455 @@ impl Store {
             .write()
             .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None))
             .and_then(|mut es| {
-                let mut se = es.entry(id.clone()).or_insert_with(|| StoreEntry::new(id.clone()));
-                let entry = se.get_entry();
+                let path = self.id_to_path(&id);
+                let cached = es.contains_key(&id);
+                let se = es.entry(id.clone()).or_insert_with(|| StoreEntry::new(id.clone(), path));
+                let entry = try!(se.get_entry(self.header_max_depth)
+                                 .and_then(|e| self.load_body(e)));
+                let source = if cached {
+                    EntrySource::Cache
+                } else if se.is_opened() {
+                    EntrySource::Disk
+                } else {
+                    EntrySource::New
+                };
                 se.status = StoreEntryStatus::Borrowed;
-                entry
+                Ok((entry, source))
             })
-            .map(|e| FileLockEntry::new(self, e, id))
-            .and_then(|mut fle| {
+            .map(|(e, source)| (FileLockEntry::new(self, e, id), source))
+            .and_then(|(mut fle, source)| {
                 if let Err(e) = self.execute_hooks_for_mut_file(self.post_retrieve_aspects.clone(), &mut fle) {
                     Err(StoreError::new(StoreErrorKind::HookExecutionError, Some(Box::new(e))))
                 } else {
-                    Ok(fle)
+                    Ok((fle, source))
                 }
 
             })
    }
 
+    /// Register a migration for entry headers with an `imag.version` in `[from, to)`
+    ///
+    /// Migrations are applied by `Store::migrate_entry()` and, if the hook returned by
+    /// `Store::migration_hook()` is registered, whenever an entry is retrieved.
+    pub fn register_migration(&self, from: Version, to: Version, migration: Migration)
+        -> Result<()>
+    {
+        self.migrations
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None))
+            .map(|mut migrations| migrations.register(from, to, migration))
+    }
+
+    /// Migrate an entry which was written by an older version of imag to the current version
+    ///
+    /// Returns whether the entry was migrated.
+    pub fn migrate_entry(&self, id: StoreId) -> Result<bool> {
+        let mut entry = try!(self.retrieve(id));
+        let migrated = try!(self.migrate_header(entry.get_header_mut()));
+        self.update(entry).map(|_| migrated)
+    }
+
+    /// Get a hook which applies the migrations of this store to the entries it is executed for
+    ///
+    /// Register it as post-retrieve hook to migrate entries whenever they are retrieved.
+    pub fn migration_hook(&self) -> MigrationHook {
+        MigrationHook::new(self.migrations.clone())
+    }
+
+    fn migrate_header(&self, header: &mut EntryHeader) -> Result<bool> {
+        let current = Version::parse(version!()).unwrap();
+        self.migrations
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None))
+            .and_then(|migrations| migrations.apply(header, &current))
+    }
+
+    /// Check whether an entry exists, without loading it
+    ///
+    /// An entry exists if it is in the cache, for example because it was created but not written
+    /// yet, or if its file exists. Invalid ids never exist.
+    pub fn exists(&self, id: StoreId) -> bool {
+        let id = match self.storify_id(id) {
+            Ok(id) => id,
+            Err(_) => return false,
+        };
+        let cached = match self.entries.read() {
+            Ok(entries) => entries.contains_key(&id),
+            Err(_) => {
+                warn!("Store lock poisoned, checking the filesystem only");
+                false
+            },
+        };
+
+        cached || self.id_to_path(&id).is_file()
+    }
+
     /// Iterate over all StoreIds for one module name
+    ///
+    /// If the module has no directory in the store yet, because no entry was ever created for it,
+    /// the iterator is empty.
     pub fn retrieve_for_module(&self, mod_name: &str) -> Result<StoreIdIterator> {
         let mut path = self.path().clone();
         path.push(mod_name);
 
+        if !path.is_dir() {
+            debug!("No directory for module '{}', no entries", mod_name);
+            return Ok(StoreIdIterator::empty());
+        }
+
         if let Some(path) = path.to_str() {
             let path = [ path, "/*" ].join("");
             debug!("glob()ing with '{}'", path);
+            let layout = self.layout;
             glob(&path[..])
-                .map(StoreIdIterator::new)
+                .map(|paths| StoreIdIterator::with_layout(paths, layout))
                 .map_err(|e| StoreError::new(StoreErrorKind::GlobError, Some(Box::new(e))))
         } else {
             Err(StoreError::new(StoreErrorKind::EncodingError, None))
         }
     }
 
-    /// Return the `FileLockEntry` and write to disk
-    pub fn update<'a>(&'a self, mut entry: FileLockEntry<'a>) -> Result<()> {
-        if let Err(e) = self.execute_hooks_for_mut_file(self.pre_update_aspects.clone(), &mut entry) {
-            return Err(e);
-        }
-
-        if let Err(e) = self._update(&entry) {
-            return Err(e);
-        }
-
-        self.execute_hooks_for_mut_file(self.post_update_aspects.clone(), &mut entry)
+    /// Iterate over all entries for one module name
+    ///
+    /// The entries are retrieved lazily, one per call to `next()`.
+    pub fn entries_for_module<'a>(&'a self, mod_name: &str) -> Result<FileLockEntryIterator<'a>> {
+        self.retrieve_for_module(mod_name).map(|ids| FileLockEntryIterator::new(self, ids))
     }
 
-    /// Internal method to write to the filesystem store.
+    /// Export all entries of the store as JSON, one object per line
     ///
-    /// # Assumptions
-    /// This method assumes that entry is dropped _right after_ the call, hence
-    /// it is not public.
-    fn _update<'a>(&'a self, entry: &FileLockEntry<'a>) -> Result<()> {
-        let hsmap = self.entries.write();
-        if hsmap.is_err() {
-            return Err(StoreError::new(StoreErrorKind::LockPoisoned, None))
+    /// The entries are read one after another, so the store is never loaded into memory as a
+    /// whole. An entry which cannot be read (for example because it is malformed or currently
+    /// borrowed) is written as `{"id": ..., "error": ...}` object instead of aborting the export.
+    pub fn export_jsonl<W: Write>(&self, w: &mut W) -> Result<()> {
+        for id in try!(self.retrieve_all()) {
+            let json = match self.retrieve_copy(id.clone()) {
+                Ok(entry) => entry.to_json(),
+                Err(e) => {
+                    let mut obj = Object::new();
+                    obj.insert(String::from("id"), Json::String(id.to_string_lossy().into_owned()));
+                    obj.insert(String::from("error"), Json::String(format!("{}", e.err_type())));
+                    Json::Object(obj)
+                },
+            };
+            try!(writeln!(w, "{}", json));
         }
-        let mut hsmap = hsmap.unwrap();
-        let mut se = try!(hsmap.get_mut(&entry.key)
-              .ok_or(StoreError::new(StoreErrorKind::IdNotFound, None)));
+        Ok(())
+    }
 
-        assert!(se.is_borrowed(), "Tried to update a non borrowed entry.");
+    /// Get the ids of all entries which were created on a day of the year, in any year
+    ///
+    /// The creation date is read from `imag.created`, which has to be a TOML datetime or a string
+    /// starting with a date like `2016-01-31`. Entries without (or with an unparsable) creation
+    /// date and borrowed entries are skipped. The ids are sorted.
+    pub fn entries_on_day(&self, month: u32, day: u32) -> Result<Vec<StoreId>> {
+        let mut ids = vec![];
+        for id in try!(self.retrieve_all()) {
+            let entry = match self.retrieve_copy(id.clone()) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    warn!("Skipping {:?}: {}", id, e);
+                    continue;
+                },
+            };
 
-        debug!("Verifying Entry");
-        try!(entry.entry.verify());
+            let created = match entry.get_header().read("imag.created") {
+                Ok(Some(Value::Datetime(s))) | Ok(Some(Value::String(s))) => month_and_day(&s),
+                _ => None,
+            };
 
-        debug!("Writing Entry");
-        try!(se.write_entry(&entry.entry));
-        se.status = StoreEntryStatus::Present;
+            if created == Some((month, day)) {
+                ids.push(id);
+            }
+        }
 
-        Ok(())
+        ids.sort();
+        Ok(ids)
     }
 
-    /// Retrieve a copy of a given entry, this cannot be used to mutate
-    /// the one on disk
-    pub fn retrieve_copy(&self, id: StoreId) -> Result<Entry> {
-        let id = self.storify_id(id);
-        let entries_lock = self.entries.write();
-        if entries_lock.is_err() {
-            return Err(StoreError::new(StoreErrorKind::LockPoisoned, None))
-        }
+    /// Export an entry together with the local files its header refers to into `dest`
+    ///
+    /// All `file://` URLs in the header (for example an external link in `imag.content.uri`) are
+    /// copied into `dest` next to the entry and the header values of the exported entry are
+    /// replaced by the names of the copies, so `dest` can be shared as a whole. Files with the
+    /// same name get a number appended, like `picture-1.png`. References to non-local URLs are
+    /// skipped with a warning. `dest` is created if it does not exist.
+    pub fn export_entry_bundle(&self, id: StoreId, dest: &Path) -> Result<()> {
+        use std::fs::{copy, create_dir_all};
+
+        let mut entry = try!(self.retrieve_copy(id));
+        try!(create_dir_all(dest)
+             .map_err(|e| StoreError::new(StoreErrorKind::FileNotCreated, Some(Box::new(e)))));
+
+        let entry_name = try!(entry.get_location()
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(String::from)
+            .ok_or(StoreError::new(StoreErrorKind::FileNotCreated, None)));
+
+        let references : Vec<(String, String)> = entry.get_header()
+            .iter_flat()
+            .filter_map(|(path, value)| match value {
+                &Value::String(ref s) if s.contains("://") => Some((path, s.clone())),
+                _ => None,
+            })
+            .collect();
+
+        // The names of the files in the bundle, by the path of the file they are a copy of
+        let mut exported : HashMap<PathBuf, String> = HashMap::new();
+        for (path, url) in references {
+            if !url.starts_with("file://") {
+                warn!("Not exporting non-local reference in '{}': {}", path, url);
+                continue;
+            }
 
-        let entries = entries_lock.unwrap();
+            let file = try!(file_url_path(&url)
+                .ok_or(StoreError::new(StoreErrorKind::EncodingError, None)));
+
+            let name = match exported.get(&file).cloned() {
+                Some(name) => name,
+                None => {
+                    let name = {
+                        let taken : Vec<&str> = exported.values()
+                            .map(|n| &n[..])
+                            .chain(Some(&entry_name[..]))
+                            .collect();
+                        try!(bundle_file_name(&file, &taken)
+                            .ok_or(StoreError::new(StoreErrorKind::FileNotFound, None)))
+                    };
+
+                    debug!("Exporting {:?} referenced in '{}' as '{}'", file, path, name);
+                    try!(copy(&file, dest.join(&name)).map_err(|e| {
+                        StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))
+                    }));
+                    exported.insert(file, name.clone());
+                    name
+                },
+            };
 
-        // if the entry is currently modified by the user, we cannot drop it
-        if entries.get(&id).map(|e| e.is_borrowed()).unwrap_or(false) {
-            return Err(StoreError::new(StoreErrorKind::IdLocked, None));
+            try!(entry.get_header_mut().set(&path[..], Value::String(name)));
         }
 
-        StoreEntry::new(id).get_entry()
+        let mut file = try!(File::create(dest.join(&entry_name))
+            .map_err(|e| StoreError::new(StoreErrorKind::FileNotCreated, Some(Box::new(e)))));
+        file.write_all(entry.to_str().as_bytes())
+            .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))))
     }
 
-    /// Delete an entry
-    pub fn delete(&self, id: StoreId) -> Result<()> {
-        let id = self.storify_id(id);
-        if let Err(e) = self.execute_hooks_for_id(self.pre_delete_aspects.clone(), &id) {
-            return Err(e);
+    /// Iterate over all StoreIds in the store
+    pub fn retrieve_all(&self) -> Result<StoreIdIterator> {
+        let mut path = self.path().clone();
+        path.push("**");
+        path.push("*");
+
+        if let Some(path) = path.to_str() {
+            debug!("glob()ing with '{}'", path);
+            let layout = self.layout;
+            glob(path)
+                .map(|paths| StoreIdIterator::with_layout(paths, layout))
+                .map_err(|e| StoreError::new(StoreErrorKind::GlobError, Some(Box::new(e))))
+        } else {
+            Err(StoreError::new(StoreErrorKind::EncodingError, None))
         }
+    }
 
-        let entries_lock = self.entries.write();
-        if entries_lock.is_err() {
-            return Err(StoreError::new(StoreErrorKind::LockPoisoned, None))
+    /// Find the ids of existing entries matching a partial id, for example one typed by a user
+    ///
+    /// The partial id is relative to the store, a leading slash is ignored. Entries whose id
+    /// matches it exactly, with or without the version suffix, are returned. If there is no such
+    /// entry, all entries whose id starts with the partial id are returned. The result is sorted
+    /// and empty if nothing matches, callers which need a single entry have to check that there
+    /// is exactly one candidate.
+    ///
+    /// A partial id starting with `@` is an alias, see `Store::resolve_alias()`.
+    pub fn resolve(&self, partial: &str) -> Result<Vec<StoreId>> {
+        if partial.starts_with("@") {
+            return Ok(self.resolve_alias(&partial[1..]).into_iter().collect());
         }
 
-        let mut entries = entries_lock.unwrap();
+        let partial = partial.trim_left_matches('/');
+        let mut exact = vec![];
+        let mut prefixed = vec![];
 
-        // if the entry is currently modified by the user, we cannot drop it
-        if entries.get(&id).map(|e| e.is_borrowed()).unwrap_or(false) {
-            return Err(StoreError::new(StoreErrorKind::IdLocked, None));
-        }
+        for id in try!(self.retrieve_all()) {
+            let relative = match id.strip_prefix(&self.location).ok().and_then(|p| p.to_str()) {
+                Some(relative) => String::from(relative),
+                None => {
+                    warn!("Cannot match id: {:?}", id);
+                    continue;
+                },
+            };
+            let unversioned = relative.rsplitn(2, '~').last().unwrap_or(&relative[..]);
 
-        // remove the entry first, then the file
-        entries.remove(&id);
-        if let Err(e) = remove_file(&id) {
-            return Err(StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))));
+            if relative == partial || unversioned == partial {
+                exact.push(id.clone());
+            } else if relative.starts_with(partial) {
+                prefixed.push(id.clone());
+            }
         }
 
-        self.execute_hooks_for_id(self.post_delete_aspects.clone(), &id)
+        let mut candidates = if exact.is_empty() { prefixed } else { exact };
+        candidates.sort();
+        Ok(candidates)
     }
 
-    /// Gets the path where this store is on the disk
-    pub fn path(&self) -> &PathBuf {
-        &self.location
+    /// Get the id an alias points to
+    ///
+    /// Aliases set with `Store::set_alias()` take precedence over the ones in the `aliases` table
+    /// of the store configuration. Returns `None` if there is no such alias or the aliases cannot
+    /// be read.
+    pub fn resolve_alias(&self, name: &str) -> Option<StoreId> {
+        use configuration::get_aliases;
+
+        let id = match self.read_aliases() {
+            Ok(mut aliases) => aliases.remove(name),
+            Err(e) => {
+                warn!("Could not read aliases: {}", e);
+                None
+            },
+        };
+
+        id.or_else(|| get_aliases(&self.configuration).remove(name))
+            .and_then(|id| self.storify_id(PathBuf::from(id)).ok())
     }
 
-    pub fn register_hook(&mut self,
-                         position: HookPosition,
-                         aspect_name: &String,
-                         mut h: Box<Hook>)
-        -> Result<()>
-    {
-        debug!("Registering hook: {:?}", h);
-        debug!("     in position: {:?}", position);
-        debug!("     with aspect: {:?}", aspect_name);
+    /// Let the alias `name` point to `id`, replacing the alias if it exists
+    ///
+    /// The alias is stored in the `ALIASES_FILE` of the store.
+    pub fn set_alias(&self, name: &str, id: StoreId) -> Result<()> {
+        let id = try!(self.storify_id(id));
+        let relative = try!(id.strip_prefix(&self.location)
+            .ok()
+            .and_then(|p| p.to_str())
+            .map(String::from)
+            .ok_or(StoreError::new(StoreErrorKind::EncodingError, None)));
+
+        self.update_aliases(|aliases| {
+            aliases.insert(String::from(name), Value::String(relative));
+        })
+    }
 
-        let guard = match position {
-                HookPosition::PreCreate    => self.pre_create_aspects.clone(),
-                HookPosition::PostCreate   => self.post_create_aspects.clone(),
-                HookPosition::PreRetrieve  => self.pre_retrieve_aspects.clone(),
-                HookPosition::PostRetrieve => self.post_retrieve_aspects.clone(),
-                HookPosition::PreUpdate    => self.pre_update_aspects.clone(),
-                HookPosition::PostUpdate   => self.post_update_aspects.clone(),
-                HookPosition::PreDelete    => self.pre_delete_aspects.clone(),
-                HookPosition::PostDelete   => self.post_delete_aspects.clone(),
-            };
+    /// Remove the alias `name`, returns whether it existed
+    ///
+    /// Only aliases set with `Store::set_alias()` can be removed, not the ones in the store
+    /// configuration.
+    pub fn remove_alias(&self, name: &str) -> Result<bool> {
+        let mut removed = false;
+        try!(self.update_aliases(|aliases| removed = aliases.remove(name).is_some()));
+        Ok(removed)
+    }
 
-        let guard = guard
-            .deref()
-            .lock()
-            .map_err(|_| StoreError::new(StoreErrorKind::LockError, None));
+    fn read_aliases(&self) -> Result<BTreeMap<String, String>> {
+        self.read_aliases_table().map(|aliases| {
+            aliases.into_iter()
+                .filter_map(|(name, id)| match id {
+                    Value::String(id) => Some((name, id)),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
 
-        if guard.is_err() {
-            return Err(StoreError::new(StoreErrorKind::HookRegisterError,
-                                       Some(Box::new(guard.err().unwrap()))));
+    fn read_aliases_table(&self) -> Result<BTreeMap<String, Value>> {
+        use std::io::Read;
+        use toml::Parser;
+
+        let path = self.location.join(ALIASES_FILE);
+        if !path.exists() {
+            return Ok(BTreeMap::new());
         }
-        let mut guard  = guard.unwrap();
-        for mut aspect in guard.deref_mut() {
-            if aspect.name().clone() == aspect_name.clone() {
-                self.get_config_for_hook(h.name()).map(|config| h.set_config(config));
-                aspect.register_hook(h);
-                return Ok(());
+
+        let mut s = String::new();
+        try!(File::open(&path)
+             .and_then(|mut f| f.read_to_string(&mut s))
+             .map_err(|e| StoreError::new(StoreErrorKind::AliasesError, Some(Box::new(e)))));
+
+        Parser::new(&s[..])
+            .parse()
+            .ok_or(StoreError::new(StoreErrorKind::AliasesError, None))
+    }
+
+    fn update_aliases<F>(&self, f: F) -> Result<()>
+        where F: FnOnce(&mut BTreeMap<String, Value>)
+    {
+        let _lock = try!(self.aliases_lock
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+        let mut aliases = try!(self.read_aliases_table());
+        f(&mut aliases);
+        let s = ::toml::encode_str(&Value::Table(aliases));
+
+        File::create(self.location.join(ALIASES_FILE))
+            .and_then(|mut f| f.write_all(s.as_bytes()))
+            .map_err(|e| StoreError::new(StoreErrorKind::AliasesError, Some(Box::new(e))))
+    }
+
+    /// Move the header value at `from` to `to` in all entries of the store, see
+    /// `EntryHeader::rename_field()`
+    ///
+    /// Entries which are currently borrowed are skipped with a warning. If renaming the field
+    /// fails for an entry, the entries before it were changed already. Returns the number of
+    /// entries which were changed.
+    pub fn rename_header_field(&self, from: &str, to: &str, overwrite: bool) -> Result<usize> {
+        let mut count = 0;
+
+        for id in try!(self.retrieve_all()) {
+            // Rename on a copy first, so entries without the field are not rewritten
+            let mut copy = match self.retrieve_copy(id.clone()) {
+                Ok(entry) => entry,
+                Err(ref e) if e.err_type() == StoreErrorKind::IdLocked => {
+                    warn!("Entry {:?} is borrowed, skipping it", id);
+                    continue;
+                },
+                Err(e) => return Err(e),
+            };
+            if !try!(copy.get_header_mut().rename_field(from, to, overwrite)) {
+                continue;
             }
+
+            let mut entry = try!(self.retrieve(id));
+            try!(entry.get_header_mut().rename_field(from, to, overwrite));
+            try!(self.update(entry));
+            count += 1;
         }
 
-        let annfe = StoreError::new(StoreErrorKind::AspectNameNotFoundError, None);
-        return Err(StoreError::new(StoreErrorKind::HookRegisterError, Some(Box::new(annfe))));
+        Ok(count)
     }
 
-    fn get_config_for_hook(&self, name: &str) -> Option<&Value> {
-        match &self.configuration {
-            &Some(Value::Table(ref tabl)) => {
-                tabl.get("hooks")
-                    .map(|hook_section| {
-                        match hook_section {
-                            &Value::Table(ref tabl) => tabl.get(name),
-                            _ => None
-                        }
-                    })
-                    .unwrap_or(None)
-            },
-            _ => None,
-        }
+    /// Apply `mutate` to all entries of the store for which `filter` returns true and write them
+    /// to disk
+    ///
+    /// Entries which are currently borrowed are skipped with a warning. Returns the number of
+    /// entries which were changed.
+    pub fn update_matching<F, M>(&self, filter: F, mutate: M) -> Result<usize>
+        where F: Fn(&Entry) -> bool,
+              M: FnMut(&mut Entry)
+    {
+        self.update_matching_with_progress(filter, mutate, |_, _| {})
     }
 
-    fn execute_hooks_for_id(&self,
-                            aspects: Arc<Mutex<Vec<Aspect>>>,
-                            id: &StoreId)
-        -> Result<()>
+    /// Like `Store::update_matching()`, but `progress` is called after each entry of the store
+    /// was processed, with the number of processed entries and the number of all entries
+    pub fn update_matching_with_progress<F, M, P>(&self, filter: F, mut mutate: M, progress: P)
+        -> Result<usize>
+        where F: Fn(&Entry) -> bool,
+              M: FnMut(&mut Entry),
+              P: Fn(usize, Option<usize>)
     {
-        let guard = aspects.deref().lock();
-        if guard.is_err() { return Err(StoreError::new(StoreErrorKind::PreHookExecuteError, None)) }
+        let mut count = 0;
+        let ids : Vec<StoreId> = try!(self.retrieve_all()).collect();
+        let total = Some(ids.len());
 
-        guard.unwrap().deref().iter()
-            .fold(Ok(()), |acc, aspect| {
-                debug!("[Aspect][exec]: {:?}", aspect);
-                acc.and_then(|_| (aspect as &StoreIdAccessor).access(id))
-            })
-            .map_err(|e| StoreError::new(StoreErrorKind::PreHookExecuteError, Some(Box::new(e))))
+        for (processed, id) in ids.into_iter().enumerate() {
+            if try!(self.update_if_matching(id, &filter, &mut mutate)) {
+                count += 1;
+            }
+            progress(processed + 1, total);
+        }
+
+        Ok(count)
     }
 
-    fn execute_hooks_for_mut_file(&self,
-                                  aspects: Arc<Mutex<Vec<Aspect>>>,
-                                  fle: &mut FileLockEntry)
-        -> Result<()>
+    /// Apply `mutate` to the entry and write it, if `filter` returns true for it
+    ///
+    /// Returns whether the entry was changed, borrowed entries are skipped with a warning.
+    fn update_if_matching<F, M>(&self, id: StoreId, filter: &F, mutate: &mut M) -> Result<bool>
+        where F: Fn(&Entry) -> bool,
+              M: FnMut(&mut Entry)
     {
-        let guard = aspects.deref().lock();
-        if guard.is_err() { return Err(StoreError::new(StoreErrorKind::PreHookExecuteError, None)) }
+        // Check on a copy first, so non-matching entries are not rewritten
+        let matches = match self.retrieve_copy(id.clone()) {
+            Ok(entry) => filter(&entry),
+            Err(ref e) if e.err_type() == StoreErrorKind::IdLocked => {
+                warn!("Entry {:?} is borrowed, skipping it", id);
+                return Ok(false);
+            },
+            Err(e) => return Err(e),
+        };
 
-        guard.unwrap().deref().iter()
-            .fold(Ok(()), |acc, aspect| {
-                debug!("[Aspect][exec]: {:?}", aspect);
-                acc.and_then(|_| aspect.access_mut(fle))
-            })
-            .map_err(|e| StoreError::new(StoreErrorKind::PreHookExecuteError, Some(Box::new(e))))
+        if !matches {
+            return Ok(false);
+        }
+
+        let mut entry = match self.retrieve(id.clone()) {
+            Ok(entry) => entry,
+            Err(ref e) if e.err_type() == StoreErrorKind::EntryAlreadyBorrowed => {
+                warn!("Entry {:?} is borrowed, skipping it", id);
+                return Ok(false);
+            },
+            Err(e) => return Err(e),
+        };
+
+        mutate(&mut entry);
+        try!(self.update(entry));
+        Ok(true)
     }
 
-}
+    /// Apply `f` to the content of an entry and write it back, leaving the header untouched
+    pub fn rewrite_content<F>(&self, id: StoreId, mut f: F) -> Result<()>
+        where F: FnMut(&str) -> String
+    {
+        let mut entry = try!(self.retrieve(id));
+        let content = f(&entry.get_content()[..]);
+        *entry.get_content_mut() = content;
+        self.update(entry)
+    }
 
-impl Debug for Store {
+    /// Return the `FileLockEntry` and write to disk
+    ///
+    /// The hooks run in this order: `PreUpdate` before the entry is written, then `PostWrite`
+    /// once the file is written, then `PostUpdate`. Hooks which need the written file, for
+    /// example to commit it to a VCS, belong in `PostWrite`.
+    ///
+    /// Unless `durable-writes` is disabled in the store configuration, the file is synced to disk
+    /// before the `PostWrite` hooks run, so it survives a power loss.
+    ///
+    /// If `history-length` is set in the store configuration, an item with the current time is
+    /// appended to the `imag.history` header of the entry, see `Store::update_with_note()`.
+    pub fn update<'a>(&'a self, entry: FileLockEntry<'a>) -> Result<()> {
+        self.update_noted(entry, None)
+    }
 
-    fn fmt(&self, fmt: &mut Formatter) -> RResult<(), FMTError> {
-        try!(write!(fmt, " --- Store ---\n"));
-        try!(write!(fmt, "\n"));
-        try!(write!(fmt, " - location               : {:?}\n", self.location));
-        try!(write!(fmt, " - configuration          : {:?}\n", self.configuration));
-        try!(write!(fmt, " - pre_create_aspects     : {:?}\n", self.pre_create_aspects    ));
-        try!(write!(fmt, " - post_create_aspects    : {:?}\n", self.post_create_aspects   ));
-        try!(write!(fmt, " - pre_retrieve_aspects   : {:?}\n", self.pre_retrieve_aspects  ));
-        try!(write!(fmt, " - post_retrieve_aspects  : {:?}\n", self.post_retrieve_aspects ));
-        try!(write!(fmt, " - pre_update_aspects     : {:?}\n", self.pre_update_aspects    ));
-        try!(write!(fmt, " - post_update_aspects    : {:?}\n", self.post_update_aspects   ));
-        try!(write!(fmt, " - pre_delete_aspects     : {:?}\n", self.pre_delete_aspects    ));
-        try!(write!(fmt, " - post_delete_aspects    : {:?}\n", self.post_delete_aspects   ));
-        try!(write!(fmt, "\n"));
-        try!(write!(fmt, "Entries:\n"));
-        try!(write!(fmt, "{:?}", self.entries));
-        try!(write!(fmt, "\n"));
-        Ok(())
+    /// Like `Store::update()`, but with a note for the item which is appended to `imag.history`
+    ///
+    /// The item is a table with the keys `timestamp` (seconds since the UNIX epoch) and `note`.
+    /// The oldest items are removed so the history has at most `history-length` items. Without
+    /// `history-length` in the store configuration, the note is dropped.
+    pub fn update_with_note<'a>(&'a self, entry: FileLockEntry<'a>, note: &str) -> Result<()> {
+        self.update_noted(entry, Some(note))
     }
 
-}
+    fn update_noted<'a>(&'a self, mut entry: FileLockEntry<'a>, note: Option<&str>) -> Result<()> {
+        if let Err(e) = self.execute_hooks_for_mut_file(self.pre_update_aspects.clone(), &mut entry) {
+            return Err(e);
+        }
 
-impl Drop for Store {
+        if let Some(length) = self.history_length {
+            try!(append_history(entry.get_header_mut(), note, length));
+        }
 
-    /**
-     * Unlock all files on drop
-     *
-     * TODO: Unlock them
-     */
-    fn drop(&mut self) {
-        debug!("Dropping store");
+        if let Err(e) = self._update(&mut entry) {
+            return Err(e);
+        }
+
+        if let Err(e) = self.execute_hooks_for_file(self.post_write_aspects.clone(), &entry) {
+            return Err(e);
+        }
+
+        self.execute_hooks_for_mut_file(self.post_update_aspects.clone(), &mut entry)
     }
 
-}
+    /// Internal method to write to the filesystem store.
+    ///
+    /// If `wrap-content-at` is configured, the content of the entry is wrapped before it is
+    /// written.
+    ///
+    /// # Assumptions
+    /// This method assumes that entry is dropped _right after_ the call, hence
+    /// it is not public.
+    fn _update<'a>(&'a self, entry: &mut FileLockEntry<'a>) -> Result<()> {
+        let hsmap = self.entries.write();
+        if hsmap.is_err() {
+            return Err(StoreError::new(StoreErrorKind::LockPoisoned, None))
+        }
+        let mut hsmap = hsmap.unwrap();
+        let se = try!(hsmap.get_mut(&entry.key)
+              .ok_or(StoreError::new(StoreErrorKind::IdNotFound, None)));
 
-/// A struct that allows you to borrow an Entry
-pub struct FileLockEntry<'a> {
-    store: &'a Store,
-    entry: Entry,
-    key: StoreId,
-}
+        if !se.is_borrowed() {
+            // The entry was already written by `Store::update()`, this is the call from
+            // `FileLockEntry::drop()` right afterwards.
+            debug!("Entry {:?} is not borrowed anymore, not writing it again", entry.key);
+            return Ok(());
+        }
 
-impl<'a> FileLockEntry<'a, > {
-    fn new(store: &'a Store, entry: Entry, key: StoreId) -> FileLockEntry<'a> {
-        FileLockEntry {
-            store: store,
-            entry: entry,
-            key: key,
+        if let Some(width) = self.wrap_content_at {
+            debug!("Wrapping content at {}", width);
+            entry.entry.content = wrap_content(&entry.entry.content, width);
         }
-    }
-}
 
-impl<'a> ::std::ops::Deref for FileLockEntry<'a> {
-    type Target = Entry;
+        debug!("Verifying Entry");
+        try!(entry.entry.verify());
 
-    fn deref(&self) -> &Self::Target {
-        &self.entry
+        debug!("Writing Entry");
+        let path = self.id_to_path(&entry.key);
+        try!(se.write_entry(&entry.entry, &path, self.separate_content, self.durable_writes));
+        se.status = StoreEntryStatus::Present;
+        // The file is only kept open, and so locked, while the entry is borrowed
+        se.file.close(path);
+
+        let key = self.index_key(&entry.key);
+        self.update_index(|index| index.insert(key, IndexEntry::new(&entry.entry)));
+
+        Ok(())
     }
-}
 
-impl<'a> ::std::ops::DerefMut for FileLockEntry<'a> {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.entry
+    /// Move a borrowed entry to `new_id`
+    ///
+    /// The content of the entry is written to the new location, the old file is removed and the
+    /// returned `FileLockEntry` is a borrow of the entry at its new id.
+    pub fn update_id<'a>(&'a self, mut entry: FileLockEntry<'a>, new_id: StoreId)
+        -> Result<FileLockEntry<'a>>
+    {
+        let new_id = try!(self.storify_id(new_id));
+        try!(self.rekey_borrowed(&mut entry, new_id));
+        Ok(entry)
     }
-}
 
-impl<'a> Drop for FileLockEntry<'a> {
-    /// This will silently ignore errors, use `Store::update` if you want to catch the errors
-    fn drop(&mut self) {
-        let _ = self.store._update(self);
+    /// Write a borrowed entry to `new_id` and remove it from its old location
+    ///
+    /// Like `Store::update_id()`, but the entry is released afterwards. Fails with
+    /// `EntryAlreadyExists` if there is an entry at `new_id` already.
+    pub fn save_as(&self, entry: FileLockEntry, new_id: StoreId) -> Result<()> {
+        self.update_id(entry, new_id).map(|_| ())
     }
-}
 
-/**
- * EntryContent type
- */
-pub type EntryContent = String;
+    /// Move an entry which is not borrowed from `old_id` to `new_id`
+    ///
+    /// Fails with `IdLocked` if the entry is currently borrowed, use `Store::update_id()` for
+    /// borrowed entries.
+    pub fn move_by_id(&self, old_id: StoreId, new_id: StoreId) -> Result<()> {
+        use std::fs::{create_dir_all, rename};
 
-/**
- * EntryHeader
- *
- * This is basically a wrapper around toml::Table which provides convenience to the user of the
- * librray.
- */
-#[derive(Debug, Clone)]
-pub struct EntryHeader {
-    header: Value,
-}
+        let old_id = try!(self.storify_id(old_id));
+        let new_id = try!(self.storify_id(new_id));
 
-pub type EntryResult<V> = RResult<V, ParserError>;
+        let mut hsmap = try!(self.entries
+            .write()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum Token {
-    Key(String),
-    Index(usize),
-}
+        if hsmap.get(&old_id).map(|e| e.is_borrowed()).unwrap_or(false) {
+            return Err(StoreError::new(StoreErrorKind::IdLocked, None));
+        }
 
-/**
- * Wrapper type around file header (TOML) object
- */
-impl EntryHeader {
+        let new_path = self.id_to_path(&new_id);
+        if hsmap.contains_key(&new_id) || new_path.exists() {
+            return Err(StoreError::new(StoreErrorKind::EntryAlreadyExists, None));
+        }
 
-    pub fn new() -> EntryHeader {
-        EntryHeader {
-            header: build_default_header()
+        debug!("Moving {:?} -> {:?}", old_id, new_id);
+        if let Some(parent) = new_path.parent() {
+            try!(create_dir_all(parent)
+                 .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))));
+        }
+        try!(rename(self.id_to_path(&old_id), &new_path)
+             .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))));
+        let old_body_path = body_path_for(&self.id_to_path(&old_id));
+        if old_body_path.exists() {
+            try!(rename(&old_body_path, body_path_for(&new_path))
+                 .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))));
         }
-    }
 
-    pub fn header(&self) -> &Value {
-        &self.header
+        if let Some(mut se) = hsmap.remove(&old_id) {
+            // An already opened file stays valid, it was only renamed
+            if let LazyFile::Absent(_) = se.file {
+                se.file = LazyFile::Absent(new_path);
+            }
+            se.id = new_id.clone();
+            hsmap.insert(new_id.clone(), se);
+        }
+
+        let (old_key, new_key) = (self.index_key(&old_id), self.index_key(&new_id));
+        self.update_index(|index| index.rename(&old_key[..], new_key));
+        Ok(())
     }
 
-    fn from_table(t: Table) -> EntryHeader {
-        EntryHeader {
-            header: Value::Table(t)
-        }
+    /// Move an entry like `Store::move_by_id()` and rewrite the links pointing to it
+    ///
+    /// All entries which have the old id in their `imag.links` are changed to link to the new id.
+    /// Borrowed entries cannot be changed and keep the old link, see `Store::update_matching()`.
+    /// Returns the number of entries which were relinked.
+    pub fn move_and_relink(&self, old_id: StoreId, new_id: StoreId) -> Result<usize> {
+        let old_link = try!(self.storify_id(old_id.clone()));
+        let new_link = try!(self.storify_id(new_id.clone()));
+
+        try!(self.move_by_id(old_id, new_id));
+
+        self.update_matching(|e| {
+            e.internal_links().map(|links| links.contains(&old_link)).unwrap_or(false)
+        }, |e| {
+            let links : Vec<StoreId> = match e.internal_links() {
+                Ok(links) => links,
+                Err(_) => return,
+            }
+            .into_iter()
+            .map(|l| if l == old_link { new_link.clone() } else { l })
+            .collect();
+
+            if let Err(e) = e.set_internal_links(&links[..]) {
+                warn!("Could not rewrite links: {:?}", e);
+            }
+        })
     }
 
-    pub fn parse(s: &str) -> EntryResult<EntryHeader> {
-        use toml::Parser;
+    /// Move an entry into `archive_module`, keeping its path relative to the store
+    ///
+    /// `imag.archived` is set in the header of the entry and links pointing to it are rewritten,
+    /// see `Store::move_and_relink()`. Returns the new id of the entry.
+    pub fn archive(&self, id: StoreId, archive_module: &str) -> Result<StoreId> {
+        let id = try!(self.storify_id(id));
+        let new_id = {
+            let relative = id.strip_prefix(&self.location).unwrap_or(&id);
+            try!(self.storify_id(PathBuf::from(archive_module).join(relative)))
+        };
 
-        let mut parser = Parser::new(s);
-        parser.parse()
-            .ok_or(ParserError::new(ParserErrorKind::TOMLParserErrors, None))
-            .and_then(verify_header_consistency)
-            .map(EntryHeader::from_table)
+        try!(self.move_and_relink(id, new_id.clone()));
+
+        let mut entry = try!(self.retrieve(new_id.clone()));
+        try!(entry.get_header_mut().set("imag.archived", Value::Boolean(true)));
+        self.update(entry).map(|_| new_id)
     }
 
-    pub fn verify(&self) -> Result<()> {
-        match &self.header {
-            &Value::Table(ref t) => verify_header(&t),
-            _ => Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
-        }
+    /// Move an archived entry out of `archive_module` again, the inverse of `Store::archive()`
+    ///
+    /// Fails with `EntryNotArchived` if the entry is not in `archive_module`.
+    pub fn unarchive(&self, id: StoreId, archive_module: &str) -> Result<StoreId> {
+        let id = try!(self.storify_id(id));
+        let new_id = match id.strip_prefix(&self.location.join(archive_module)) {
+            Ok(relative) => try!(self.storify_id(relative.to_path_buf())),
+            Err(_) => return Err(StoreError::new(StoreErrorKind::EntryNotArchived, None)),
+        };
+
+        try!(self.move_and_relink(id, new_id.clone()));
+
+        let mut entry = try!(self.retrieve(new_id.clone()));
+        try!(entry.get_header_mut().delete("imag.archived"));
+        self.update(entry).map(|_| new_id)
     }
 
-    /**
-     * Insert a header field by a string-spec
-     *
-     * ```ignore
-     *  insert("something.in.a.field", Boolean(true));
-     * ```
-     *
-     * If an array field was accessed which is _out of bounds_ of the array available, the element
-     * is appended to the array.
-     *
-     * Inserts a Boolean in the section "something" -> "in" -> "a" -> "field"
-     * A JSON equivalent would be
-     *
-     *  {
-     *      something: {
-     *          in: {
-     *              a: {
-     *                  field: true
-     *              }
-     *          }
-     *      }
-     *  }
-     *
-     * Returns true if header field was set, false if there is already a value
-     */
-    pub fn insert(&mut self, spec: &str, v: Value) -> Result<bool> {
-        self.insert_with_sep(spec, '.', v)
+    /// Internal method to write a borrowed entry to `new_id` (which has to be storified already)
+    /// and remove it from its old location.
+    ///
+    /// On failure, the entry is left at its old location.
+    fn rekey_borrowed<'a>(&'a self, entry: &mut FileLockEntry<'a>, new_id: StoreId) -> Result<()> {
+        let mut hsmap = try!(self.entries
+            .write()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+        let new_path = self.id_to_path(&new_id);
+        if hsmap.contains_key(&new_id) || new_path.exists() {
+            return Err(StoreError::new(StoreErrorKind::EntryAlreadyExists, None));
+        }
+
+        let old_id = entry.key.clone();
+        let mut se = try!(hsmap.remove(&old_id)
+              .ok_or(StoreError::new(StoreErrorKind::IdNotFound, None)));
+        assert!(se.is_borrowed(), "Tried to move a non borrowed entry.");
+
+        let old_path = self.id_to_path(&old_id);
+        if let Err(e) = se.check_unmodified(&old_path) {
+            hsmap.insert(old_id, se);
+            return Err(e);
+        }
+
+        let old_file = ::std::mem::replace(&mut se.file, LazyFile::Absent(new_path));
+        let old_stamp = se.stamp.take();
+        se.id = new_id.clone();
+        entry.entry.location = new_id.clone();
+
+        debug!("Moving Entry {:?} -> {:?}", old_id, new_id);
+        let written = entry.entry
+            .verify()
+            .and_then(|_| {
+                se.write_entry(&entry.entry, &self.id_to_path(&new_id), self.separate_content,
+                               self.durable_writes)
+            })
+            .and_then(|_| {
+                if old_path.exists() {
+                    remove_file(&old_path)
+                        .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))))
+                } else {
+                    // The entry was created but never written
+                    Ok(())
+                }
+            })
+            .and_then(|_| remove_body_file(&old_path));
+
+        if let Err(e) = written {
+            debug!("Moving failed, restoring {:?}", old_id);
+            if self.id_to_path(&new_id).exists() {
+                // We checked that nothing was there before
+                let _ = remove_file(self.id_to_path(&new_id));
+            }
+            let _ = remove_body_file(&self.id_to_path(&new_id));
+            se.file = old_file;
+            se.stamp = old_stamp;
+            se.id = old_id.clone();
+            entry.entry.location = old_id.clone();
+            hsmap.insert(old_id, se);
+            return Err(e);
+        }
+
+        let (old_key, new_key) = (self.index_key(&old_id), self.index_key(&new_id));
+        self.update_index(|index| {
+            index.remove(&old_key[..]);
+            index.insert(new_key, IndexEntry::new(&entry.entry));
+        });
+
+        hsmap.insert(new_id.clone(), se);
+        entry.key = new_id;
+        Ok(())
     }
 
-    pub fn insert_with_sep(&mut self, spec: &str, sep: char, v: Value) -> Result<bool> {
-        let tokens = EntryHeader::tokenize(spec, sep);
-        if tokens.is_err() { // return parser error if any
-            return tokens.map(|_| false);
+    /// Retrieve a copy of a given entry, this cannot be used to mutate
+    /// the one on disk
+    pub fn retrieve_copy(&self, id: StoreId) -> Result<Entry> {
+        let id = try!(self.storify_id(id));
+        let entries_lock = self.entries.write();
+        if entries_lock.is_err() {
+            return Err(StoreError::new(StoreErrorKind::LockPoisoned, None))
         }
-        let tokens = tokens.unwrap();
 
-        let destination = tokens.iter().last();
-        if destination.is_none() {
-            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        let mut entries = entries_lock.unwrap();
+
+        // if the entry is currently modified by the user, we cannot drop it
+        if entries.get(&id).map(|e| e.is_borrowed()).unwrap_or(false) {
+            return Err(StoreError::new(StoreErrorKind::IdLocked, None));
         }
-        let destination = destination.unwrap();
 
-        let path_to_dest = tokens[..(tokens.len() - 1)].into(); // N - 1 tokens
-        let value = EntryHeader::walk_header(&mut self.header, path_to_dest); // walk N-1 tokens
-        if value.is_err() {
-            return value.map(|_| false);
+        self.read_entry(&mut entries, &id)
+    }
+
+    /// Read an entry from disk, through the opened (and locked) file if the entry is borrowed
+    ///
+    /// The entry is read even if it is currently borrowed. The files of entries which are not
+    /// borrowed are closed again afterwards.
+    fn read_entry(&self, entries: &mut HashMap<StoreId, StoreEntry>, id: &StoreId)
+        -> Result<Entry>
+    {
+        let entry = if let Some(se) = entries.get_mut(id) {
+            let entry = se.read_entry(self.header_max_depth);
+            if !se.is_borrowed() {
+                se.file.close(self.id_to_path(id));
+            }
+            entry
+        } else {
+            let mut se = StoreEntry::new(id.clone(), self.id_to_path(id));
+            let entry = se.read_entry(self.header_max_depth);
+            self.add_metrics(&se.metrics);
+            entry
+        };
+
+        entry.and_then(|e| self.load_body(e))
+            .map(|mut e| {
+                e.header.set_max_depth(self.header_max_depth);
+                e
+            })
+    }
+
+    /// Load the content of an entry from its body file, if its header refers to one in
+    /// `imag.body`
+    ///
+    /// This works regardless of the `separate-content` setting, the reference is removed from the
+    /// header.
+    fn load_body(&self, mut entry: Entry) -> Result<Entry> {
+        use std::io::Read;
+
+        match try!(entry.header.read("imag.body")) {
+            Some(Value::String(_)) => {},
+            Some(_) => return Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
+            None => return Ok(entry),
         }
-        let mut value = value.unwrap();
 
-        // There is already an value at this place
-        if EntryHeader::extract(value, destination).is_ok() {
-            return Ok(false);
+        // The path is always derived from the id, not from the header
+        let body_path = body_path_for(&self.id_to_path(&entry.location));
+        let mut content = String::new();
+        try!(File::open(&body_path)
+            .and_then(|mut f| f.read_to_string(&mut content))
+            .map_err(|e| StoreError::new(StoreErrorKind::FileNotFound, Some(Box::new(e)))));
+
+        try!(entry.header.delete("imag.body"));
+        entry.content = content;
+        Ok(entry)
+    }
+
+    /// Delete an entry
+    pub fn delete(&self, id: StoreId) -> Result<()> {
+        let id = try!(self.storify_id(id));
+        if let Err(e) = self.execute_hooks_for_id(self.pre_delete_aspects.clone(), &id) {
+            return Err(e);
         }
 
-        match destination {
-            &Token::Key(ref s) => { // if the destination shall be an map key
-                match value {
-                    /*
-                     * Put it in there if we have a map
-                     */
-                    &mut Value::Table(ref mut t) => {
-                        t.insert(s.clone(), v);
+        let entries_lock = self.entries.write();
+        if entries_lock.is_err() {
+            return Err(StoreError::new(StoreErrorKind::LockPoisoned, None))
+        }
+
+        let mut entries = entries_lock.unwrap();
+
+        // if the entry is currently modified by the user, we cannot drop it
+        if entries.get(&id).map(|e| e.is_borrowed()).unwrap_or(false) {
+            return Err(StoreError::new(StoreErrorKind::IdLocked, None));
+        }
+
+        // remove the entry first, then the file
+        if let Some(se) = entries.remove(&id) {
+            self.add_metrics(&se.metrics);
+        }
+        if let Err(e) = remove_file(self.id_to_path(&id)) {
+            return Err(StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))));
+        }
+        try!(remove_body_file(&self.id_to_path(&id)));
+
+        let key = self.index_key(&id);
+        self.update_index(|index| index.remove(&key[..]));
+
+        self.execute_hooks_for_id(self.post_delete_aspects.clone(), &id)
+    }
+
+    /// Delete all entries whose path relative to the store matches the `glob` pattern
+    ///
+    /// Fails with `IdLocked` before deleting anything if one of the entries is borrowed. The
+    /// entries are deleted one by one with `Store::delete()`, so the delete hooks run for each of
+    /// them. If a deletion fails, the error is a `PartialDeletion` which holds the number of
+    /// entries which were deleted before. Returns the number of deleted entries.
+    pub fn delete_matching(&self, glob_pattern: &str) -> Result<usize> {
+        let pattern = self.location.join(glob_pattern);
+        let pattern = try!(pattern.to_str()
+            .ok_or(StoreError::new(StoreErrorKind::EncodingError, None)));
+        let ids : Vec<StoreId> = try!(glob(pattern)
+            .map(|paths| StoreIdIterator::with_layout(paths, self.layout))
+            .map_err(|e| StoreError::new(StoreErrorKind::GlobError, Some(Box::new(e)))))
+            .collect();
+
+        {
+            let entries = try!(self.entries
+                .read()
+                .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+            if ids.iter().any(|id| entries.get(id).map(|e| e.is_borrowed()).unwrap_or(false)) {
+                return Err(StoreError::new(StoreErrorKind::IdLocked, None));
+            }
+        }
+
+        for (deleted, id) in ids.iter().enumerate() {
+            debug!("Deleting {:?}", id);
+            if let Err(e) = self.delete(id.clone()) {
+                let cause = PartialDeletion::new(deleted, e);
+                return Err(StoreError::new(StoreErrorKind::PartialDeletion, Some(Box::new(cause))));
+            }
+        }
+
+        Ok(ids.len())
+    }
+
+    /// Get the metrics about the filesystem access of this store
+    pub fn metrics(&self) -> Result<StoreMetrics> {
+        let entries = try!(self.entries
+            .read()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+        let mut metrics = *try!(self.metrics
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+        for se in entries.values() {
+            metrics.add(&se.metrics);
+        }
+
+        Ok(metrics)
+    }
+
+    fn add_metrics(&self, m: &StoreMetrics) {
+        match self.metrics.lock() {
+            Ok(mut metrics) => metrics.add(m),
+            Err(_) => warn!("Metrics lock poisoned, metrics are lost: {:?}", m),
+        }
+    }
+
+    /// Recover the store after a thread panicked while holding one of its internal locks
+    ///
+    /// A poisoned lock makes every store operation fail with `LockPoisoned`. Calling this
+    /// function clears the poison flag of all internal locks, so the store can be used again.
+    ///
+    /// # Risks
+    ///
+    /// The poison flag exists for a reason: the panicking thread may have left the internal
+    /// state half-modified. Entries which were borrowed by the panicking thread are still marked
+    /// as borrowed and will fail with `IdLocked`, and changes to them which were not yet written
+    /// back are lost. Only call this if the caller knows what the panicking thread was doing.
+    pub fn clear_poison(&self) -> Result<()> {
+        if self.entries.is_poisoned() {
+            warn!("Clearing poisoned entry cache lock");
+            self.entries.clear_poison();
+        }
+
+        for aspects in &[&self.pre_create_aspects, &self.post_create_aspects,
+                         &self.pre_retrieve_aspects, &self.post_retrieve_aspects,
+                         &self.pre_update_aspects, &self.post_update_aspects,
+                         &self.post_write_aspects,
+                         &self.pre_delete_aspects, &self.post_delete_aspects] {
+            if aspects.is_poisoned() {
+                warn!("Clearing poisoned aspect lock");
+                aspects.clear_poison();
+            }
+        }
+
+        self.metrics.clear_poison();
+        self.aliases_lock.clear_poison();
+        if let Some(ref index) = self.index {
+            index.clear_poison();
+        }
+
+        self.entries
+            .write()
+            .map(|_| ())
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None))
+    }
+
+    /// Clean up the store
+    ///
+    /// Removes temporary files left by interrupted writes, empty directories and, if
+    /// `remove_empty_entries` is set, entries which contain nothing but the default header.
+    /// Temporary files are only removed if they belong to a store id. Empty entries are removed
+    /// with `Store::delete()`, so the delete hooks run for them. Borrowed entries and hidden
+    /// directories, like the `.git` directory of a store which is under version control, are
+    /// never touched.
+    pub fn vacuum(&self, remove_empty_entries: bool) -> Result<VacuumReport> {
+        let mut report = VacuumReport::default();
+
+        if remove_empty_entries {
+            let empty_ids : Vec<StoreId> = {
+                let mut entries = try!(self.entries
+                    .write()
+                    .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+                let mut empty_ids = vec![];
+                for id in try!(self.retrieve_all()) {
+                    if entries.get(&id).map(|e| e.is_borrowed()).unwrap_or(false) {
+                        continue;
                     }
 
-                    /*
-                     * Fail if there is no map here
-                     */
-                    _ => return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+                    let empty = self.read_entry(&mut entries, &id)
+                        .map(|e| e.is_empty())
+                        .unwrap_or(false);
+                    if empty {
+                        empty_ids.push(id);
+                    }
+                }
+                empty_ids
+            };
+
+            for id in empty_ids {
+                debug!("Removing empty entry: {:?}", id);
+                match self.delete(id.clone()) {
+                    Ok(()) => report.empty_entries.push(id),
+                    // Borrowed since we looked at it
+                    Err(ref e) if e.err_type() == StoreErrorKind::IdLocked => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        let entries = try!(self.entries
+            .read()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+        let location = self.location.clone();
+        try!(self.vacuum_dir(&location, &entries, &mut report));
+        Ok(report)
+    }
+
+    /// Remove stale temporary files and empty directories below `dir`
+    ///
+    /// Returns whether `dir` is empty afterwards.
+    fn vacuum_dir(&self,
+                  dir: &PathBuf,
+                  entries: &HashMap<StoreId, StoreEntry>,
+                  report: &mut VacuumReport)
+        -> Result<bool>
+    {
+        use std::fs::{read_dir, remove_dir};
+
+        let io_err = |e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)));
+
+        for dir_entry in try!(read_dir(dir).map_err(&io_err)) {
+            let path = try!(dir_entry.map_err(&io_err)).path();
+
+            if path.is_dir() {
+                let hidden = path.file_name()
+                    .and_then(|name| name.to_str())
+                    .map(|name| name.starts_with("."))
+                    .unwrap_or(false);
+
+                if !hidden && try!(self.vacuum_dir(&path, entries, report)) {
+                    debug!("Removing empty directory: {:?}", path);
+                    try!(remove_dir(&path).map_err(&io_err));
+                    report.directories.push(path);
                 }
+                continue;
+            }
+
+            let entry_path = match temp_file_entry_path(&path) {
+                Some(entry_path) => entry_path,
+                None => continue,
+            };
+
+            let borrowed = entries
+                .values()
+                .any(|se| se.is_borrowed() && self.id_to_path(&se.id) == entry_path);
+
+            if !borrowed {
+                debug!("Removing stale temporary file: {:?}", path);
+                try!(remove_file(&path).map_err(&io_err));
+                report.temp_files.push(path);
+            }
+        }
+
+        read_dir(dir)
+            .map(|mut content| content.next().is_none())
+            .map_err(io_err)
+    }
+
+    /// Get the index of the store, if it is enabled in the configuration
+    ///
+    /// The index is maintained by the store while entries are written or deleted and is rebuilt
+    /// when the store is opened and the index file is missing or broken. If the entries were
+    /// changed outside of the store, use `Store::rebuild_index()`.
+    pub fn index(&self) -> Result<Option<BTreeMap<StoreId, IndexEntry>>> {
+        match self.index {
+            None => Ok(None),
+            Some(ref index) => {
+                let index = try!(index
+                    .lock()
+                    .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+                Ok(Some(index.entries()
+                    .iter()
+                    .map(|(k, ie)| (self.location.join(k), ie.clone()))
+                    .collect()))
+            },
+        }
+    }
+
+    /// Regenerate the index from the entries on disk
+    ///
+    /// Does nothing if the index is not enabled in the configuration.
+    pub fn rebuild_index(&self) -> Result<()> {
+        self.rebuild_index_with_progress(|_, _| {})
+    }
+
+    /// Like `Store::rebuild_index()`, but `progress` is called after each entry was indexed, with
+    /// the number of indexed entries and the number of all entries
+    pub fn rebuild_index_with_progress<P>(&self, progress: P) -> Result<()>
+        where P: Fn(usize, Option<usize>)
+    {
+        let index = match self.index {
+            None => {
+                debug!("Store index is not enabled, not rebuilding it");
+                return Ok(());
             },
+            Some(ref index) => index,
+        };
 
-            &Token::Index(i) => { // if the destination shall be an array
-                match value {
+        debug!("Rebuilding store index");
+        let mut cache = try!(self.entries
+            .write()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+
+        let mut entries = BTreeMap::new();
+        let ids : Vec<StoreId> = try!(self.retrieve_all()).collect();
+        let total = Some(ids.len());
+        for (processed, id) in ids.into_iter().enumerate() {
+            // Read the entries from disk, so borrowed entries are indexed as they are on disk
+            match self.read_entry(&mut cache, &id) {
+                Ok(entry) => {
+                    entries.insert(self.index_key(&id), IndexEntry::new(&entry));
+                },
+                Err(e) => warn!("Could not index {:?}: {:?}", id, e),
+            }
+            progress(processed + 1, total);
+        }
 
-                    /*
-                     * Put it in there if we have an array
-                     */
-                    &mut Value::Array(ref mut a) => {
-                        a.push(v); // push to the end of the array
+        let mut index = try!(index
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockPoisoned, None)));
+        index.replace(entries);
+        index.save()
+    }
 
-                        // if the index is inside the array, we swap-remove the element at this
-                        // index
-                        if a.len() < i {
-                            a.swap_remove(i);
-                        }
-                    },
+    /// Get the key of a storified id in the index
+    fn index_key(&self, id: &StoreId) -> String {
+        id.strip_prefix(&self.location)
+            .unwrap_or(id)
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Apply `f` to the index, if enabled, and write it to disk
+    ///
+    /// Failing to write the index does not fail the operation on the entry, the index can be
+    /// rebuilt later.
+    fn update_index<F: FnOnce(&mut Index)>(&self, f: F) {
+        if let Some(ref index) = self.index {
+            match index.lock() {
+                Ok(mut index) => {
+                    f(&mut index);
+                    if let Err(e) = index.save() {
+                        warn!("Could not write store index: {:?}", e);
+                    }
+                },
+                Err(_) => warn!("Store index lock poisoned, index is out of date"),
+            }
+        }
+    }
+
+    /// Gets the path where this store is on the disk
+    pub fn path(&self) -> &PathBuf {
+        &self.location
+    }
+
+    /// Get the path on the filesystem an id maps to
+    ///
+    /// The id may be relative to the store, with or without a leading slash (like the ids
+    /// `build_entry_path()` accepts), or an id which already contains the store path. The layout
+    /// of the store is taken into account.
+    pub fn storeid_to_path(&self, id: &StoreId) -> Result<PathBuf> {
+        let id = if id.starts_with(&self.location) {
+            id.clone()
+        } else {
+            match id.strip_prefix("/") {
+                Ok(relative) => try!(self.storify_id(relative.to_path_buf())),
+                Err(_)       => try!(self.storify_id(id.clone())),
+            }
+        };
+        Ok(self.id_to_path(&id))
+    }
+
+    pub fn register_hook(&mut self,
+                         position: HookPosition,
+                         aspect_name: &String,
+                         mut h: Box<Hook>)
+        -> Result<()>
+    {
+        debug!("Registering hook: {:?}", h);
+        debug!("     in position: {:?}", position);
+        debug!("     with aspect: {:?}", aspect_name);
+
+        let guard = self.aspects_for(&position);
+        let guard = guard
+            .deref()
+            .lock()
+            .map_err(|_| StoreError::new(StoreErrorKind::LockError, None));
+
+        if guard.is_err() {
+            return Err(StoreError::new(StoreErrorKind::HookRegisterError,
+                                       Some(Box::new(guard.err().unwrap()))));
+        }
+        let mut guard  = guard.unwrap();
+        for mut aspect in guard.deref_mut() {
+            if aspect.name().clone() == aspect_name.clone() {
+                self.get_config_for_hook(h.name()).map(|config| h.set_config(config));
+                aspect.register_hook(h);
+                return Ok(());
+            }
+        }
+
+        let annfe = StoreError::new(StoreErrorKind::AspectNameNotFoundError, None);
+        return Err(StoreError::new(StoreErrorKind::HookRegisterError, Some(Box::new(annfe))));
+    }
+
+    /// Run `f` without executing any hooks
+    ///
+    /// Useful for bulk operations like imports or migrations. Hooks are only disabled for the
+    /// calling thread, other threads using the store still execute them. Calls can be nested,
+    /// hook execution is restored when the outermost call returns, even if `f` panics.
+    pub fn with_hooks_disabled<F, R>(&self, f: F) -> R
+        where F: FnOnce() -> R
+    {
+        struct Restore<'a>(&'a Mutex<HashMap<ThreadId, usize>>);
+
+        impl<'a> Drop for Restore<'a> {
+            fn drop(&mut self) {
+                let mut depths = self.0.lock().unwrap_or_else(|e| e.into_inner());
+                let id = current_thread().id();
+                let depth = depths.get(&id).cloned().unwrap_or(1);
+                if depth <= 1 {
+                    depths.remove(&id);
+                } else {
+                    depths.insert(id, depth - 1);
+                }
+            }
+        }
+
+        {
+            let mut depths = self.hooks_disabled.lock().unwrap_or_else(|e| e.into_inner());
+            *depths.entry(current_thread().id()).or_insert(0) += 1;
+        }
+        let _restore = Restore(&self.hooks_disabled);
+        f()
+    }
+
+    /// Whether hooks are disabled for the current thread, see `Store::with_hooks_disabled()`
+    fn hooks_disabled(&self) -> bool {
+        self.hooks_disabled
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(&current_thread().id())
+    }
+
+    /// Get the names of the aspects in a hook position
+    pub fn registered_aspects(&self, position: HookPosition) -> Vec<String> {
+        match self.aspects_for(&position).lock() {
+            Ok(aspects) => aspects.iter().map(|a| a.name().clone()).collect(),
+            Err(_) => {
+                warn!("Aspect lock poisoned for {:?}", position);
+                vec![]
+            },
+        }
+    }
+
+    /// Get the names of the hooks registered in an aspect, in all hook positions
+    pub fn hooks_in_aspect(&self, name: &str) -> Vec<String> {
+        let positions = [
+            HookPosition::PreCreate,
+            HookPosition::PostCreate,
+            HookPosition::PreRetrieve,
+            HookPosition::PostRetrieve,
+            HookPosition::PreUpdate,
+            HookPosition::PostUpdate,
+            HookPosition::PostWrite,
+            HookPosition::PreDelete,
+            HookPosition::PostDelete,
+        ];
+
+        let mut names = vec![];
+        for position in positions.iter() {
+            match self.aspects_for(position).lock() {
+                Ok(aspects) => {
+                    for aspect in aspects.iter().filter(|a| &a.name()[..] == name) {
+                        names.extend(aspect.hook_names());
+                    }
+                },
+                Err(_) => warn!("Aspect lock poisoned for {:?}", position),
+            }
+        }
+        names
+    }
+
+    fn aspects_for(&self, position: &HookPosition) -> Arc<Mutex<Vec<Aspect>>> {
+        match *position {
+            HookPosition::PreCreate    => self.pre_create_aspects.clone(),
+            HookPosition::PostCreate   => self.post_create_aspects.clone(),
+            HookPosition::PreRetrieve  => self.pre_retrieve_aspects.clone(),
+            HookPosition::PostRetrieve => self.post_retrieve_aspects.clone(),
+            HookPosition::PreUpdate    => self.pre_update_aspects.clone(),
+            HookPosition::PostUpdate   => self.post_update_aspects.clone(),
+            HookPosition::PostWrite    => self.post_write_aspects.clone(),
+            HookPosition::PreDelete    => self.pre_delete_aspects.clone(),
+            HookPosition::PostDelete   => self.post_delete_aspects.clone(),
+        }
+    }
+
+    fn get_config_for_hook(&self, name: &str) -> Option<&Value> {
+        match &self.configuration {
+            &Some(Value::Table(ref tabl)) => {
+                tabl.get("hooks")
+                    .map(|hook_section| {
+                        match hook_section {
+                            &Value::Table(ref tabl) => tabl.get(name),
+                            _ => None
+                        }
+                    })
+                    .unwrap_or(None)
+            },
+            _ => None,
+        }
+    }
+
+    fn execute_hooks_for_id(&self,
+                            aspects: Arc<Mutex<Vec<Aspect>>>,
+                            id: &StoreId)
+        -> Result<()>
+    {
+        if self.hooks_disabled() {
+            debug!("Hooks are disabled, not executing them");
+            return Ok(());
+        }
+
+        let guard = aspects.deref().lock();
+        if guard.is_err() { return Err(StoreError::new(StoreErrorKind::PreHookExecuteError, None)) }
+
+        guard.unwrap().deref().iter()
+            .fold(Ok(()), |acc, aspect| {
+                debug!("[Aspect][exec]: {:?}", aspect);
+                acc.and_then(|_| {
+                    (aspect as &StoreIdAccessor)
+                        .access(id)
+                        .or_else(|e| skip_if_non_fatal(aspect, e))
+                })
+            })
+            .map_err(|e| StoreError::new(StoreErrorKind::PreHookExecuteError, Some(Box::new(e))))
+    }
+
+    fn execute_hooks_for_mut_file(&self,
+                                  aspects: Arc<Mutex<Vec<Aspect>>>,
+                                  fle: &mut FileLockEntry)
+        -> Result<()>
+    {
+        if self.hooks_disabled() {
+            debug!("Hooks are disabled, not executing them");
+            return Ok(());
+        }
+
+        let guard = aspects.deref().lock();
+        if guard.is_err() { return Err(StoreError::new(StoreErrorKind::PreHookExecuteError, None)) }
+
+        guard.unwrap().deref().iter()
+            .fold(Ok(()), |acc, aspect| {
+                debug!("[Aspect][exec]: {:?}", aspect);
+                acc.and_then(|_| aspect.access_mut(fle).or_else(|e| skip_if_non_fatal(aspect, e)))
+            })
+            .map_err(|e| StoreError::new(StoreErrorKind::PreHookExecuteError, Some(Box::new(e))))
+    }
+
+    fn execute_hooks_for_file(&self,
+                              aspects: Arc<Mutex<Vec<Aspect>>>,
+                              fle: &FileLockEntry)
+        -> Result<()>
+    {
+        if self.hooks_disabled() {
+            debug!("Hooks are disabled, not executing them");
+            return Ok(());
+        }
+
+        let guard = aspects.deref().lock();
+        if guard.is_err() {
+            return Err(StoreError::new(StoreErrorKind::PostHookExecuteError, None))
+        }
+
+        guard.unwrap().deref().iter()
+            .fold(Ok(()), |acc, aspect| {
+                debug!("[Aspect][exec]: {:?}", aspect);
+                acc.and_then(|_| {
+                    NonMutableHookDataAccessor::access(aspect, fle)
+                        .or_else(|e| skip_if_non_fatal(aspect, e))
+                })
+            })
+            .map_err(|e| StoreError::new(StoreErrorKind::PostHookExecuteError, Some(Box::new(e))))
+    }
+
+}
+
+/// Turn the error of a hook in a non-fatal aspect into a warning
+fn skip_if_non_fatal(aspect: &Aspect, e: HookError) -> HookResult<()> {
+    if aspect.is_fatal() {
+        Err(e)
+    } else {
+        warn!("Hook in non-fatal aspect '{}' failed, ignoring: {:?}", aspect.name(), e);
+        Ok(())
+    }
+}
+
+impl Debug for Store {
+
+    fn fmt(&self, fmt: &mut Formatter) -> RResult<(), FMTError> {
+        try!(write!(fmt, " --- Store ---\n"));
+        try!(write!(fmt, "\n"));
+        try!(write!(fmt, " - location               : {:?}\n", self.location));
+        try!(write!(fmt, " - configuration          : {:?}\n", self.configuration));
+        try!(write!(fmt, " - pre_create_aspects     : {:?}\n", self.pre_create_aspects    ));
+        try!(write!(fmt, " - post_create_aspects    : {:?}\n", self.post_create_aspects   ));
+        try!(write!(fmt, " - pre_retrieve_aspects   : {:?}\n", self.pre_retrieve_aspects  ));
+        try!(write!(fmt, " - post_retrieve_aspects  : {:?}\n", self.post_retrieve_aspects ));
+        try!(write!(fmt, " - pre_update_aspects     : {:?}\n", self.pre_update_aspects    ));
+        try!(write!(fmt, " - post_update_aspects    : {:?}\n", self.post_update_aspects   ));
+        try!(write!(fmt, " - post_write_aspects     : {:?}\n", self.post_write_aspects    ));
+        try!(write!(fmt, " - pre_delete_aspects     : {:?}\n", self.pre_delete_aspects    ));
+        try!(write!(fmt, " - post_delete_aspects    : {:?}\n", self.post_delete_aspects   ));
+        try!(write!(fmt, "\n"));
+        try!(write!(fmt, "Entries:\n"));
+        try!(write!(fmt, "{:?}", self.entries));
+        try!(write!(fmt, "\n"));
+        Ok(())
+    }
+
+}
+
+impl Drop for Store {
+
+    /**
+     * Unlock all files on drop
+     */
+    fn drop(&mut self) {
+        debug!("Dropping store");
+        match self.entries.read() {
+            Ok(entries) => for se in entries.values() {
+                if let Err(e) = se.file.unlock() {
+                    warn!("Could not unlock {:?}: {:?}", se.id, e);
+                }
+            },
+            // The files are unlocked anyways when they are closed
+            Err(_) => warn!("Entry cache lock poisoned, not unlocking files explicitely"),
+        }
+    }
+
+}
+
+/// Name of the file in the store root which contains the version the store was created with
+const STORE_VERSION_FILE: &'static str = ".imag_version";
+
+/// Check the version of the store at `location` against the running version
+///
+/// If there is no version file yet, it is created. Versions are compatible by semver rules.
+fn check_store_version(location: &PathBuf, fail_on_mismatch: bool) -> Result<()> {
+    use std::fs::OpenOptions;
+    use std::io::Read;
+    use semver::Version;
+
+    let path = location.join(STORE_VERSION_FILE);
+    if !path.exists() {
+        debug!("Creating store version file: {:?}", path);
+        return OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(&path)
+            .and_then(|mut f| f.write_all(version!().as_bytes()))
+            .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))));
+    }
+
+    let mut store_version = String::new();
+    try!(File::open(&path)
+         .and_then(|mut f| f.read_to_string(&mut store_version))
+         .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))));
+    let store_version = store_version.trim();
+
+    let compatible = match (Version::parse(store_version), Version::parse(version!())) {
+        (Ok(store), Ok(running)) => {
+            store.major == running.major && (store.major != 0 || store.minor == running.minor)
+        },
+        _ => false,
+    };
+
+    if compatible {
+        Ok(())
+    } else if fail_on_mismatch {
+        error!("Store version '{}' is incompatible with imag version '{}'",
+               store_version, version!());
+        Err(StoreError::new(StoreErrorKind::StoreVersionMismatch, None))
+    } else {
+        warn!("Store version '{}' is incompatible with imag version '{}'",
+              store_version, version!());
+        Ok(())
+    }
+}
+
+/// A struct that allows you to borrow an Entry
+pub struct FileLockEntry<'a> {
+    store: &'a Store,
+    entry: Entry,
+    key: StoreId,
+}
+
+impl<'a> FileLockEntry<'a, > {
+    fn new(store: &'a Store, mut entry: Entry, key: StoreId) -> FileLockEntry<'a> {
+        entry.header.set_max_depth(store.header_max_depth);
+        FileLockEntry {
+            store: store,
+            entry: entry,
+            key: key,
+        }
+    }
+}
+
+impl<'a> ::std::ops::Deref for FileLockEntry<'a> {
+    type Target = Entry;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entry
+    }
+}
+
+impl<'a> ::std::ops::DerefMut for FileLockEntry<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.entry
+    }
+}
+
+impl<'a> Drop for FileLockEntry<'a> {
+    /// This will silently ignore errors, use `Store::update` if you want to catch the errors
+    fn drop(&mut self) {
+        let _ = self.store._update(self);
+    }
+}
+
+/// An iterator which retrieves the entries for a `StoreIdIterator` lazily
+///
+/// See `Store::entries_for_module()`.
+pub struct FileLockEntryIterator<'a> {
+    store: &'a Store,
+    ids: StoreIdIterator,
+}
+
+impl<'a> FileLockEntryIterator<'a> {
+    pub fn new(store: &'a Store, ids: StoreIdIterator) -> FileLockEntryIterator<'a> {
+        FileLockEntryIterator {
+            store: store,
+            ids: ids,
+        }
+    }
+}
+
+impl<'a> Iterator for FileLockEntryIterator<'a> {
+    type Item = Result<FileLockEntry<'a>>;
+
+    fn next(&mut self) -> Option<Result<FileLockEntry<'a>>> {
+        self.ids.next().map(|id| self.store.retrieve(id))
+    }
+}
+
+/**
+ * EntryContent type
+ */
+pub type EntryContent = String;
+
+/**
+ * EntryHeader
+ *
+ * This is basically a wrapper around toml::Table which provides convenience to the user of the
+ * librray.
+ */
+#[derive(Debug, Clone)]
+pub struct EntryHeader {
+    header: Value,
+    max_depth: usize,
+}
+
+/// The default maximum nesting depth of entry headers, see `EntryHeader::set_max_depth()`
+pub const DEFAULT_HEADER_MAX_DEPTH: usize = 64;
+
+/// Header fields which the store changes on its own, they are not part of
+/// `Entry::header_hash()`
+pub const VOLATILE_HEADER_FIELDS: &'static [&'static str] = &["imag.last_modified", "imag.history"];
+
+pub type EntryResult<V> = RResult<V, ParserError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Key(String),
+    Index(usize),
+}
+
+enum FlatLevel<'a> {
+    Table(::std::collections::btree_map::Iter<'a, String, Value>),
+    Array(::std::iter::Enumerate<::std::slice::Iter<'a, Value>>),
+}
+
+impl<'a> Iterator for FlatLevel<'a> {
+    type Item = (String, &'a Value);
+
+    fn next(&mut self) -> Option<(String, &'a Value)> {
+        match *self {
+            FlatLevel::Table(ref mut i) => i.next().map(|(k, v)| (k.clone(), v)),
+            FlatLevel::Array(ref mut i) => i.next().map(|(n, v)| (n.to_string(), v)),
+        }
+    }
+}
+
+/**
+ * Iterator over the (path, value) pairs of an EntryHeader, see `EntryHeader::iter_flat()`
+ */
+pub struct HeaderFlatIter<'a> {
+    stack: Vec<(String, FlatLevel<'a>)>,
+}
+
+impl<'a> Iterator for HeaderFlatIter<'a> {
+    type Item = (String, &'a Value);
+
+    fn next(&mut self) -> Option<(String, &'a Value)> {
+        loop {
+            let next = match self.stack.last_mut() {
+                None => return None,
+                Some(&mut (ref prefix, ref mut level)) => level.next().map(|(k, v)| {
+                    if prefix.is_empty() {
+                        (k, v)
+                    } else {
+                        (format!("{}.{}", prefix, k), v)
+                    }
+                }),
+            };
+
+            match next {
+                None => {
+                    self.stack.pop();
+                },
+                Some((path, &Value::Table(ref t))) => {
+                    self.stack.push((path, FlatLevel::Table(t.iter())));
+                },
+                Some((path, &Value::Array(ref a))) => {
+                    self.stack.push((path, FlatLevel::Array(a.iter().enumerate())));
+                },
+                Some(leaf) => return Some(leaf),
+            }
+        }
+    }
+}
+
+/**
+ * Wrapper type around file header (TOML) object
+ */
+impl EntryHeader {
+
+    pub fn new() -> EntryHeader {
+        EntryHeader {
+            header: build_default_header(),
+            max_depth: DEFAULT_HEADER_MAX_DEPTH,
+        }
+    }
+
+    pub fn header(&self) -> &Value {
+        &self.header
+    }
+
+    /// Iterate over all leaf values of the header, together with their path
+    ///
+    /// Paths are joined with '.' and contain array indices, so they can be passed to
+    /// `EntryHeader::read()`. Values are yielded lazily, in key order.
+    pub fn iter_flat<'a>(&'a self) -> HeaderFlatIter<'a> {
+        let stack = match self.header {
+            Value::Table(ref t) => vec![(String::new(), FlatLevel::Table(t.iter()))],
+            _ => vec![],
+        };
+
+        HeaderFlatIter { stack: stack }
+    }
+
+    fn from_table(t: Table) -> EntryHeader {
+        EntryHeader {
+            header: Value::Table(t),
+            max_depth: DEFAULT_HEADER_MAX_DEPTH,
+        }
+    }
+
+    pub fn parse(s: &str) -> EntryResult<EntryHeader> {
+        EntryHeader::parse_with_max_depth(s, DEFAULT_HEADER_MAX_DEPTH)
+    }
+
+    /// Parse a header like `EntryHeader::parse()`, with a maximum nesting depth of `max_depth`
+    ///
+    /// Headers which are nested deeper fail with a `HeaderInconsistency` error, caused by a
+    /// `HeaderPathSyntaxError`.
+    pub fn parse_with_max_depth(s: &str, max_depth: usize) -> EntryResult<EntryHeader> {
+        use toml::Parser;
+
+        let mut parser = Parser::new(s);
+        parser.parse()
+            .ok_or(ParserError::new(ParserErrorKind::TOMLParserErrors, None))
+            .and_then(verify_header_consistency)
+            .map(EntryHeader::from_table)
+            .and_then(|mut header| {
+                if header.depth() > max_depth {
+                    debug!("Header is nested deeper than {} levels", max_depth);
+                    let e = StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None);
+                    return Err(ParserError::new(ParserErrorKind::HeaderInconsistency,
+                                                Some(Box::new(e))));
+                }
+                header.set_max_depth(max_depth);
+                Ok(header)
+            })
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        match &self.header {
+            &Value::Table(ref t) => {
+                if self.depth() > self.max_depth {
+                    debug!("Header is nested deeper than {} levels", self.max_depth);
+                    return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+                }
+                verify_header(&t)
+            },
+            _ => Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
+        }
+    }
+
+    /// Set the maximum nesting depth of this header
+    ///
+    /// Header paths with more components than this fail with `HeaderPathSyntaxError`, and so do
+    /// setting or inserting values which would nest the header deeper and `EntryHeader::verify()`
+    /// if the header is nested deeper, so such an entry fails cleanly instead of overflowing the
+    /// stack later. Reading a header which is nested deeper fails as well, see
+    /// `EntryHeader::parse_with_max_depth()`. Entries of a store get the `header-max-depth` of the
+    /// store configuration, `DEFAULT_HEADER_MAX_DEPTH` otherwise.
+    pub fn set_max_depth(&mut self, depth: usize) {
+        self.max_depth = depth;
+    }
+
+    /// Get the maximum nesting depth of this header, see `EntryHeader::set_max_depth()`
+    pub fn max_depth(&self) -> usize {
+        self.max_depth
+    }
+
+    /// Get the nesting depth of the header, which is the number of components of the longest
+    /// path to a value in it
+    ///
+    /// This does not recurse, so it is safe to call on arbitrarily deep headers.
+    pub fn depth(&self) -> usize {
+        value_depth(&self.header)
+    }
+
+    /// Fail with `HeaderPathSyntaxError` if putting `v` at a path with `len` components would
+    /// nest the header deeper than its maximum depth
+    fn check_depth(&self, len: usize, v: &Value) -> Result<()> {
+        if len + value_depth(v) > self.max_depth {
+            debug!("Value would be nested deeper than {} levels", self.max_depth);
+            Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None))
+        } else {
+            Ok(())
+        }
+    }
+
+    /**
+     * Insert a header field by a string-spec
+     *
+     * ```ignore
+     *  insert("something.in.a.field", Boolean(true));
+     * ```
+     *
+     * If an array field was accessed which is _out of bounds_ of the array available, the element
+     * is appended to the array. So inserting at any index `>= len` puts the element at index
+     * `len`, not at the index which was passed. An index inside the array is occupied already,
+     * hence nothing is inserted in this case.
+     *
+     * Inserts a Boolean in the section "something" -> "in" -> "a" -> "field"
+     * A JSON equivalent would be
+     *
+     *  {
+     *      something: {
+     *          in: {
+     *              a: {
+     *                  field: true
+     *              }
+     *          }
+     *      }
+     *  }
+     *
+     * Returns true if header field was set, false if there is already a value
+     */
+    pub fn insert(&mut self, spec: &str, v: Value) -> Result<bool> {
+        self.insert_with_sep(spec, '.', v)
+    }
+
+    pub fn insert_with_sep(&mut self, spec: &str, sep: char, v: Value) -> Result<bool> {
+        let tokens = EntryHeader::tokenize(spec, sep, self.max_depth);
+        if tokens.is_err() { // return parser error if any
+            return tokens.map(|_| false);
+        }
+        let tokens = tokens.unwrap();
+        try!(self.check_depth(tokens.len(), &v));
+
+        let destination = tokens.iter().last();
+        if destination.is_none() {
+            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        }
+        let destination = destination.unwrap();
+
+        let path_to_dest = tokens[..(tokens.len() - 1)].into(); // N - 1 tokens
+        let value = EntryHeader::walk_header(&mut self.header, path_to_dest); // walk N-1 tokens
+        if value.is_err() {
+            return value.map(|_| false);
+        }
+        let mut value = value.unwrap();
+
+        // There is already an value at this place
+        if EntryHeader::extract(value, destination).is_ok() {
+            return Ok(false);
+        }
+
+        match destination {
+            &Token::Key(ref s) => { // if the destination shall be an map key
+                match value {
+                    /*
+                     * Put it in there if we have a map
+                     */
+                    &mut Value::Table(ref mut t) => {
+                        t.insert(s.clone(), v);
+                    }
+
+                    /*
+                     * Fail if there is no map here
+                     */
+                    _ => return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+                }
+            },
+
+            &Token::Index(i) => { // if the destination shall be an array
+                match value {
+
+                    /*
+                     * Put it in there if we have an array
+                     */
+                    &mut Value::Array(ref mut a) => {
+                        // The index cannot be inside the array here, as we checked for an
+                        // existing value above, so we append
+                        debug!("Appending to Array {:?} for index {:?}", a, i);
+                        a.push(v);
+                    },
+
+                    /*
+                     * Fail if there is no array here
+                     */
+                    _ => return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+                }
+            },
+        }
+
+        Ok(true)
+    }
+
+    /**
+     * Set a header field by a string-spec
+     *
+     * ```ignore
+     *  set("something.in.a.field", Boolean(true));
+     * ```
+     *
+     * Sets a Boolean in the section "something" -> "in" -> "a" -> "field"
+     * A JSON equivalent would be
+     *
+     *  {
+     *      something: {
+     *          in: {
+     *              a: {
+     *                  field: true
+     *              }
+     *          }
+     *      }
+     *  }
+     *
+     * If there is already a value at this place, this value will be overridden and the old value
+     * will be returned
+     */
+    pub fn set(&mut self, spec: &str, v: Value) -> Result<Option<Value>> {
+        self.set_with_sep(spec, '.', v)
+    }
+
+    pub fn set_with_sep(&mut self, spec: &str, sep: char, v: Value) -> Result<Option<Value>> {
+        let tokens = EntryHeader::tokenize(spec, sep, self.max_depth);
+        if tokens.is_err() { // return parser error if any
+            return Err(tokens.err().unwrap());
+        }
+        let tokens = tokens.unwrap();
+        try!(self.check_depth(tokens.len(), &v));
+        debug!("tokens = {:?}", tokens);
+
+        let destination = tokens.iter().last();
+        if destination.is_none() {
+            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        }
+        let destination = destination.unwrap();
+        debug!("destination = {:?}", destination);
+
+        let path_to_dest = tokens[..(tokens.len() - 1)].into(); // N - 1 tokens
+        let value = EntryHeader::walk_header(&mut self.header, path_to_dest); // walk N-1 tokens
+        if value.is_err() {
+            return Err(value.err().unwrap());
+        }
+        let mut value = value.unwrap();
+        debug!("walked value = {:?}", value);
+
+        match destination {
+            &Token::Key(ref s) => { // if the destination shall be an map key->value
+                match value {
+                    /*
+                     * Put it in there if we have a map
+                     */
+                    &mut Value::Table(ref mut t) => {
+                        debug!("Matched Key->Table");
+                        return Ok(t.insert(s.clone(), v));
+                    }
+
+                    /*
+                     * Fail if there is no map here
+                     */
+                    _ => {
+                        debug!("Matched Key->NON-Table");
+                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
+                    }
+                }
+            },
+
+            &Token::Index(i) => { // if the destination shall be an array
+                match value {
+
+                    /*
+                     * Put it in there if we have an array
+                     */
+                    &mut Value::Array(ref mut a) => {
+                        debug!("Matched Index->Array");
+
+                        // if the index is inside the array, we replace the element at this
+                        // index by pushing and swap-removing it
+                        if i < a.len() {
+                            a.push(v);
+                            debug!("Swap-Removing in Array {:?}[{:?}] <- {:?}", a, i, a[a.len()-1]);
+                            return Ok(Some(a.swap_remove(i)));
+                        }
+
+                        a.push(v); // push to the end of the array
+
+                        debug!("Appended");
+                        return Ok(None);
+                    },
+
+                    /*
+                     * Fail if there is no array here
+                     */
+                    _ => {
+                        debug!("Matched Index->NON-Array");
+                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
+                    },
+                }
+            },
+        }
+
+        Ok(None)
+    }
+
+    /**
+     * Read a header field by a string-spec
+     *
+     * ```ignore
+     *  let value = read("something.in.a.field");
+     * ```
+     *
+     * Reads a Value in the section "something" -> "in" -> "a" -> "field"
+     * A JSON equivalent would be
+     *
+     *  {
+     *      something: {
+     *          in: {
+     *              a: {
+     *                  field: true
+     *              }
+     *          }
+     *      }
+     *  }
+     *
+     * If there is no a value at this place, None will be returned. This also holds true for Arrays
+     * which are accessed at an index which is not yet there, even if the accessed index is much
+     * larger than the array length.
+     */
+    pub fn read(&self, spec: &str) -> Result<Option<Value>> {
+        self.read_with_sep(spec, '.')
+    }
+
+    pub fn read_with_sep(&self, spec: &str, splitchr: char) -> Result<Option<Value>> {
+        let tokens = EntryHeader::tokenize(spec, splitchr, self.max_depth);
+        if tokens.is_err() { // return parser error if any
+            return Err(tokens.err().unwrap());
+        }
+        let tokens = tokens.unwrap();
+
+        let mut header_clone = self.header.clone(); // we clone as READing is simpler this way
+        let value = EntryHeader::walk_header(&mut header_clone, tokens); // walk N-1 tokens
+        if value.is_err() {
+            let e = value.err().unwrap();
+            return match e.err_type() {
+                // We cannot find the header key, as there is no path to it
+                StoreErrorKind::HeaderKeyNotFound => Ok(None),
+                _ => Err(e),
+            };
+        }
+        Ok(Some(value.unwrap().clone()))
+    }
+
+    /// Read a String header field
+    ///
+    /// Returns `Ok(None)` if the field does not exist and a `HeaderPathTypeFailure` error if it is
+    /// not a String.
+    pub fn read_string(&self, spec: &str) -> Result<Option<String>> {
+        match try!(self.read(spec)) {
+            Some(Value::String(s)) => Ok(Some(s)),
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Ok(None),
+        }
+    }
+
+    /// Read an Integer header field, see `EntryHeader::read_string()`
+    pub fn read_integer(&self, spec: &str) -> Result<Option<i64>> {
+        match try!(self.read(spec)) {
+            Some(Value::Integer(i)) => Ok(Some(i)),
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Ok(None),
+        }
+    }
+
+    /// Read a Float header field, see `EntryHeader::read_string()`
+    pub fn read_float(&self, spec: &str) -> Result<Option<f64>> {
+        match try!(self.read(spec)) {
+            Some(Value::Float(f)) => Ok(Some(f)),
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Ok(None),
+        }
+    }
+
+    /// Read a Boolean header field, see `EntryHeader::read_string()`
+    pub fn read_boolean(&self, spec: &str) -> Result<Option<bool>> {
+        match try!(self.read(spec)) {
+            Some(Value::Boolean(b)) => Ok(Some(b)),
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Ok(None),
+        }
+    }
+
+    /// Read an Array header field, see `EntryHeader::read_string()`
+    pub fn read_array(&self, spec: &str) -> Result<Option<Vec<Value>>> {
+        match try!(self.read(spec)) {
+            Some(Value::Array(a)) => Ok(Some(a)),
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Ok(None),
+        }
+    }
+
+    /// Read a datetime header field, see `EntryHeader::read_string()`
+    ///
+    /// The field can either be a TOML datetime or a String with an RFC 3339 timestamp, which is
+    /// converted to UTC. Other strings result in a `HeaderPathTypeFailure` error as well.
+    pub fn read_datetime(&self, spec: &str) -> Result<Option<DateTime<Utc>>> {
+        match try!(self.read(spec)) {
+            Some(Value::Datetime(s)) | Some(Value::String(s)) => {
+                DateTime::parse_from_rfc3339(&s[..])
+                    .map(|d| Some(d.with_timezone(&Utc)))
+                    .map_err(|e| {
+                        StoreError::new(StoreErrorKind::HeaderPathTypeFailure, Some(Box::new(e)))
+                    })
+            },
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Ok(None),
+        }
+    }
+
+    /// Append `v` to the array at `spec`
+    ///
+    /// If there is no value at `spec`, an empty array is created there first, together with the
+    /// tables on the way to it. Fails with `HeaderPathTypeFailure` if the value at `spec` is not an
+    /// array. The header is not changed if this fails.
+    pub fn append(&mut self, spec: &str, v: Value) -> Result<()> {
+        match try!(self.read_array(spec)) {
+            Some(mut array) => {
+                array.push(v);
+                self.set(spec, Value::Array(array)).map(|_| ())
+            },
+            None => {
+                let mut header = self.clone();
+                try!(header.create_parent_tables(spec));
+                try!(header.set(spec, Value::Array(vec![v])));
+                *self = header;
+                Ok(())
+            },
+        }
+    }
+
+    /// Append `v` to the array at `spec` like `EntryHeader::append()`, unless it is already in it
+    ///
+    /// Returns whether `v` was appended.
+    pub fn append_unique(&mut self, spec: &str, v: Value) -> Result<bool> {
+        if try!(self.read_array(spec)).map(|a| a.contains(&v)).unwrap_or(false) {
+            return Ok(false);
+        }
+        self.append(spec, v).map(|_| true)
+    }
+
+    /// Move the value at `from` to `to`
+    ///
+    /// Tables on the way to `to` are created if they do not exist. If there is a value at `to`
+    /// already, it is replaced if `overwrite` is set and this fails with `HeaderKeyExists`
+    /// otherwise. Moving a value into itself, like from `a` to `a.b`, fails with
+    /// `HeaderPathSyntaxError`. The header is not changed if this fails.
+    ///
+    /// Returns whether there was a value at `from`.
+    pub fn rename_field(&mut self, from: &str, to: &str, overwrite: bool) -> Result<bool> {
+        let value = match try!(self.read(from)) {
+            Some(value) => value,
+            None => return Ok(false),
+        };
+
+        if from == to {
+            return Ok(true);
+        }
+        if to.starts_with(&format!("{}.", from)[..]) || from.starts_with(&format!("{}.", to)[..]) {
+            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        }
+        if !overwrite && try!(self.read(to)).is_some() {
+            return Err(StoreError::new(StoreErrorKind::HeaderKeyExists, None));
+        }
+
+        let mut header = self.clone();
+        try!(header.delete(from));
+
+        try!(header.create_parent_tables(to));
+        try!(header.set(to, value));
+
+        *self = header;
+        Ok(true)
+    }
+
+    /// Create the tables on the way to `spec` which do not exist
+    fn create_parent_tables(&mut self, spec: &str) -> Result<()> {
+        let parents : Vec<&str> = spec.split('.').collect();
+        for i in 1..parents.len() {
+            let parent = parents[..i].join(".");
+            if try!(self.read(&parent[..])).is_none() {
+                try!(self.set(&parent[..], Value::Table(BTreeMap::new())));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the keys of the table at `spec`, where an empty `spec` denotes the top level
+    ///
+    /// Fails with `HeaderPathTypeFailure` if the value at `spec` is not a table and with
+    /// `HeaderKeyNotFound` if there is no value at `spec`.
+    pub fn keys(&self, spec: &str) -> Result<Vec<String>> {
+        self.entries(spec).map(|entries| entries.into_iter().map(|(k, _)| k).collect())
+    }
+
+    /// Get the keys and values of the table at `spec`, see `EntryHeader::keys()`
+    pub fn entries(&self, spec: &str) -> Result<Vec<(String, Value)>> {
+        let value = if spec.is_empty() {
+            Some(self.header.clone())
+        } else {
+            try!(self.read(spec))
+        };
+
+        match value {
+            Some(Value::Table(t)) => Ok(t.into_iter().collect()),
+            Some(_) => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+            None    => Err(StoreError::new(StoreErrorKind::HeaderKeyNotFound, None)),
+        }
+    }
+
+    pub fn delete(&mut self, spec: &str) -> Result<Option<Value>> {
+        let tokens = EntryHeader::tokenize(spec, '.', self.max_depth);
+        if tokens.is_err() { // return parser error if any
+            return Err(tokens.err().unwrap());
+        }
+        let tokens = tokens.unwrap();
+
+        let destination = tokens.iter().last();
+        if destination.is_none() {
+            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        }
+        let destination = destination.unwrap();
+        debug!("destination = {:?}", destination);
+
+        let path_to_dest = tokens[..(tokens.len() - 1)].into(); // N - 1 tokens
+        let value = EntryHeader::walk_header(&mut self.header, path_to_dest); // walk N-1 tokens
+        if value.is_err() {
+            return Err(value.err().unwrap());
+        }
+        let mut value = value.unwrap();
+        debug!("walked value = {:?}", value);
+
+        match destination {
+            &Token::Key(ref s) => { // if the destination shall be an map key->value
+                match value {
+                    &mut Value::Table(ref mut t) => {
+                        debug!("Matched Key->Table, removing {:?}", s);
+                        return Ok(t.remove(s));
+                    },
+                    _ => {
+                        debug!("Matched Key->NON-Table");
+                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
+                    }
+                }
+            },
+
+            &Token::Index(i) => { // if the destination shall be an array
+                match value {
+                    &mut Value::Array(ref mut a) => {
+                        // if the index is inside the array, we swap-remove the element at this
+                        // index
+                        if a.len() > i {
+                            debug!("Removing in Array {:?}[{:?}]", a, i);
+                            return Ok(Some(a.remove(i)));
+                        } else {
+                            return Ok(None);
+                        }
+                    },
+                    _ => {
+                        debug!("Matched Index->NON-Array");
+                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
+                    },
+                }
+            },
+        }
+
+        Ok(None)
+    }
+
+    fn tokenize(spec: &str, splitchr: char, max_depth: usize) -> Result<Vec<Token>> {
+        use std::str::FromStr;
+
+        if spec.split(splitchr).count() > max_depth {
+            debug!("Header path '{}' is longer than {} components", spec, max_depth);
+            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        }
+
+        spec.split(splitchr)
+            .map(|s| {
+                usize::from_str(s)
+                    .map(Token::Index)
+                    .or_else(|_| Ok(Token::Key(String::from(s))))
+            })
+            .collect()
+    }
+
+    fn walk_header(v: &mut Value, tokens: Vec<Token>) -> Result<&mut Value> {
+        use std::vec::IntoIter;
+
+        fn walk_iter<'a>(v: Result<&'a mut Value>, i: &mut IntoIter<Token>) -> Result<&'a mut Value> {
+            let next = i.next();
+            v.and_then(move |value| {
+                if let Some(token) = next {
+                    walk_iter(EntryHeader::extract(value, &token), i)
+                } else {
+                    Ok(value)
+                }
+            })
+        }
+
+        walk_iter(Ok(v), &mut tokens.into_iter())
+    }
+
+    fn extract_from_table<'a>(v: &'a mut Value, s: &String) -> Result<&'a mut Value> {
+        match v {
+            &mut Value::Table(ref mut t) => {
+                t.get_mut(&s[..])
+                    .ok_or(StoreError::new(StoreErrorKind::HeaderKeyNotFound, None))
+            },
+            _ => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+        }
+    }
+
+    fn extract_from_array(v: &mut Value, i: usize) -> Result<&mut Value> {
+        match v {
+            &mut Value::Array(ref mut a) => {
+                if a.len() <= i {
+                    Err(StoreError::new(StoreErrorKind::HeaderKeyNotFound, None))
+                } else {
+                    Ok(&mut a[i])
+                }
+            },
+            _ => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
+        }
+    }
+
+    fn extract<'a>(v: &'a mut Value, token: &Token) -> Result<&'a mut Value> {
+        match token {
+            &Token::Key(ref s)  => EntryHeader::extract_from_table(v, s),
+            &Token::Index(i)    => EntryHeader::extract_from_array(v, i),
+        }
+    }
+
+}
+
+impl Into<Table> for EntryHeader {
+
+    fn into(self) -> Table {
+        match self.header {
+            Value::Table(t) => t,
+            _ => panic!("EntryHeader is not a table!"),
+        }
+    }
+
+}
+
+impl From<Table> for EntryHeader {
+
+    fn from(t: Table) -> EntryHeader {
+        EntryHeader::from_table(t)
+    }
+
+}
+
+fn build_default_header() -> Value { // BTreeMap<String, Value>
+    let mut m = BTreeMap::new();
+
+    m.insert(String::from("imag"), {
+        let mut imag_map = BTreeMap::<String, Value>::new();
+
+        imag_map.insert(String::from("version"), Value::String(String::from(version!())));
+        imag_map.insert(String::from("links"), Value::Array(vec![]));
+
+        Value::Table(imag_map)
+    });
+
+    Value::Table(m)
+}
+/// Get the nesting depth of `v`, which is 0 for values which are no tables or arrays
+///
+/// This does not recurse, so it is safe to call on arbitrarily deep values.
+fn value_depth(v: &Value) -> usize {
+    let mut max = 0;
+    let mut stack = vec![(v, 0)];
+
+    while let Some((value, depth)) = stack.pop() {
+        if depth > max {
+            max = depth;
+        }
+        match value {
+            &Value::Table(ref t) => stack.extend(t.values().map(|v| (v, depth + 1))),
+            &Value::Array(ref a) => stack.extend(a.iter().map(|v| (v, depth + 1))),
+            _ => {},
+        }
+    }
+    max
+}
+
+fn verify_header(t: &Table) -> Result<()> {
+    if !has_main_section(t) {
+        Err(StoreError::from(ParserError::new(ParserErrorKind::MissingMainSection, None)))
+    } else if !has_imag_version_in_main_section(t) {
+        Err(StoreError::from(ParserError::new(ParserErrorKind::MissingVersionInfo, None)))
+    } else if !has_only_tables(t) {
+        debug!("Could not verify that it only has tables in its base table");
+        Err(StoreError::from(ParserError::new(ParserErrorKind::NonTableInBaseTable, None)))
+    } else {
+        Ok(())
+    }
+}
+
+fn verify_header_consistency(t: Table) -> EntryResult<Table> {
+    if let Err(e) = verify_header(&t) {
+        Err(ParserError::new(ParserErrorKind::HeaderInconsistency, Some(Box::new(e))))
+    } else {
+        Ok(t)
+    }
+}
+
+fn has_only_tables(t: &Table) -> bool {
+    debug!("Verifying that table has only tables");
+    t.iter().all(|(_, x)| if let &Value::Table(_) = x { true } else { false })
+}
+
+fn has_main_section(t: &Table) -> bool {
+    t.contains_key("imag") &&
+        match t.get("imag") {
+            Some(&Value::Table(_)) => true,
+            Some(_)                => false,
+            None                   => false,
+        }
+}
+
+fn has_imag_version_in_main_section(t: &Table) -> bool {
+    use semver::Version;
+
+    match t.get("imag").unwrap() {
+        &Value::Table(ref sec) => {
+            sec.get("version")
+                .and_then(|v| {
+                    match v {
+                        &Value::String(ref s) => {
+                            Some(Version::parse(&s[..]).is_ok())
+                        },
+                        _                 => Some(false),
+                    }
+                })
+            .unwrap_or(false)
+        }
+        _                  => false,
+    }
+}
+
+/**
+ * An Entry of the store
+ *
+ * Contains location, header and content part.
+ */
+#[derive(Debug, Clone)]
+pub struct Entry {
+    location: StoreId,
+    header: EntryHeader,
+    content: EntryContent,
+}
+
+impl Entry {
+
+    pub fn new(loc: StoreId) -> Entry {
+        Entry {
+            location: loc,
+            header: EntryHeader::new(),
+            content: EntryContent::new()
+        }
+    }
+
+    pub fn from_file(loc: StoreId, file: &mut File) -> Result<Entry> {
+        Self::from_reader(loc, file)
+    }
+
+    /// Read an entry from any source, for example stdin or an in-memory buffer
+    pub fn from_reader<R: ::std::io::Read>(loc: StoreId, r: &mut R) -> Result<Entry> {
+        let text = {
+            let mut s = String::new();
+            try!(r.read_to_string(&mut s));
+            s
+        };
+        Self::from_str(loc, &text[..])
+    }
+
+    pub fn from_str(loc: StoreId, s: &str) -> Result<Entry> {
+        Self::from_str_with_max_depth(loc, s, DEFAULT_HEADER_MAX_DEPTH)
+    }
+
+    /// Build an entry from a string like `Entry::from_str()`, see
+    /// `EntryHeader::parse_with_max_depth()`
+    pub fn from_str_with_max_depth(loc: StoreId, s: &str, max_depth: usize) -> Result<Entry> {
+        debug!("Building entry from string");
+        lazy_static! {
+            // The delimiters may end with \r\n, for files which were written on Windows
+            static ref RE: Regex = Regex::new(r"(?smx)
+                ^---\r?$
+                (?P<header>.*) # Header
+                ^---\r?$\n
+                (?P<content>.*) # Content
+            ").unwrap();
+        }
+
+        // Some editors prepend a byte order mark or blank lines, which are not part of the entry
+        let s = s.trim_left_matches('\u{feff}').trim_left();
+
+        let matches = RE.captures(s);
+
+        if matches.is_none() {
+            return Err(StoreError::new(StoreErrorKind::MalformedEntry, None));
+        }
+
+        let matches = matches.unwrap();
+
+        let header = matches.name("header");
+        let content = matches.name("content").unwrap_or("");
+
+        if header.is_none() {
+            return Err(StoreError::new(StoreErrorKind::MalformedEntry, None));
+        }
+
+        debug!("Header and content found. Yay! Building Entry object now");
+        Ok(Entry {
+            location: loc,
+            header: try!(EntryHeader::parse_with_max_depth(header.unwrap(), max_depth)),
+            content: content.into(),
+        })
+    }
+
+    pub fn to_str(&self) -> String {
+        format!("---{header}---\n{content}",
+                header  = ::toml::encode_str(&self.header.header),
+                content = self.content)
+    }
+
+    pub fn get_location(&self) -> &StoreId {
+        &self.location
+    }
+
+    pub fn get_header(&self) -> &EntryHeader {
+        &self.header
+    }
+
+    pub fn get_header_mut(&mut self) -> &mut EntryHeader {
+        &mut self.header
+    }
+
+    pub fn get_content(&self) -> &EntryContent {
+        &self.content
+    }
+
+    pub fn get_content_mut(&mut self) -> &mut EntryContent {
+        &mut self.content
+    }
+
+    pub fn verify(&self) -> Result<()> {
+        self.header.verify()
+    }
+
+    /// Check whether the entry has no content and no header data besides the imag version
+    pub fn is_empty(&self) -> bool {
+        if !self.content.trim().is_empty() {
+            return false;
+        }
+
+        match self.header.header {
+            Value::Table(ref t) => t.iter().all(|(k, v)| {
+                k == "imag" && match v {
+                    &Value::Table(ref imag) => imag.iter().all(|(k, v)| match (&k[..], v) {
+                        ("version", _) => true,
+                        ("links", &Value::Array(ref a)) => a.is_empty(),
+                        _ => false,
+                    }),
+                    _ => false,
+                }
+            }),
+            _ => false,
+        }
+    }
+
+    /// Get the entry as JSON object with the keys `id`, `header` and `content`
+    pub fn to_json(&self) -> Json {
+        let mut obj = Object::new();
+        obj.insert(String::from("id"), Json::String(self.location.to_string_lossy().into_owned()));
+        obj.insert(String::from("header"), toml_to_json(&self.header.header));
+        obj.insert(String::from("content"), Json::String(self.content.clone()));
+        Json::Object(obj)
+    }
+
+    /// Get a hash of the content of this entry
+    ///
+    /// The hash only changes when the content changes, so it can be used to detect changes
+    /// without comparing the content itself.
+    pub fn content_hash(&self) -> String {
+        hash_str(&self.content[..])
+    }
+
+    /// Get a hash of the header of this entry
+    ///
+    /// The hash is computed over the serialized header without the `VOLATILE_HEADER_FIELDS`, so
+    /// for example an update which only touches `imag.last_modified` does not change it.
+    pub fn header_hash(&self) -> String {
+        let mut header = self.header.clone();
+        for field in VOLATILE_HEADER_FIELDS {
+            let _ = header.delete(field);
+        }
+        hash_str(&::toml::encode_str(&header.header)[..])
+    }
+
+    /// Get the internal links of this entry from `imag.links`, sorted and without duplicates
+    pub fn internal_links(&self) -> Result<Vec<StoreId>> {
+        let links = match try!(self.header.read("imag.links")) {
+            Some(Value::Array(links)) => links,
+            None => return Ok(vec![]),
+            Some(_) => return Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
+        };
+
+        let mut ids = vec![];
+        for link in links {
+            match link {
+                Value::String(s) => ids.push(StoreId::from(s)),
+                _ => return Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
+            }
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    /// Set the internal links of this entry in `imag.links`
+    ///
+    /// The links are written sorted and without duplicates. Fails with `EncodingError` if a link
+    /// is not valid UTF-8, in which case the header is not touched.
+    pub fn set_internal_links(&mut self, links: &[StoreId]) -> Result<()> {
+        let mut links = links.to_vec();
+        links.sort();
+        links.dedup();
+
+        let mut values = vec![];
+        for link in links {
+            match link.to_str() {
+                Some(s) => values.push(Value::String(String::from(s))),
+                None => return Err(StoreError::new(StoreErrorKind::EncodingError, None)),
+            }
+        }
+
+        self.header.set("imag.links", Value::Array(values)).map(|_| ())
+    }
+
+}
+
+/// Get the local path of a `file://` URL, with percent-encoded characters decoded
+///
+/// Returns `None` if the URL has a host other than `localhost`, or if it contains an invalid
+/// escape sequence.
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let rest = &url["file://".len()..];
+    let rest = if rest.starts_with("localhost/") { &rest["localhost".len()..] } else { rest };
+    if !rest.starts_with("/") {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(rest.len());
+    let mut iter = rest.bytes();
+    while let Some(b) = iter.next() {
+        if b != b'%' {
+            bytes.push(b);
+            continue;
+        }
+
+        let hex : Vec<u8> = iter.by_ref().take(2).collect();
+        if hex.len() != 2 {
+            return None;
+        }
+        match String::from_utf8(hex).ok().and_then(|hex| u8::from_str_radix(&hex[..], 16).ok()) {
+            Some(b) => bytes.push(b),
+            None    => return None,
+        }
+    }
+
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+/// Name for the copy of `file` in an exported bundle which is none of the `taken` names
+///
+/// This is the name of `file` or, if that is taken, the name with a number appended to the file
+/// stem, like `picture-1.png`.
+fn bundle_file_name(file: &Path, taken: &[&str]) -> Option<String> {
+    let name = match file.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name,
+        None => return None,
+    };
+    if !taken.contains(&name) {
+        return Some(String::from(name));
+    }
+
+    let (stem, extension) = match (file.file_stem().and_then(|s| s.to_str()),
+                                   file.extension().and_then(|e| e.to_str())) {
+        (Some(stem), Some(extension)) => (stem, format!(".{}", extension)),
+        _ => (name, String::new()),
+    };
+
+    (1..).map(|n| format!("{}-{}{}", stem, n, extension))
+        .find(|candidate| !taken.contains(&&candidate[..]))
+}
+
+/// Whether the file name of `path` is the one of an entry: not hidden and ending in `~<version>`
+fn is_entry_path(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if !name.starts_with(".") => {
+            match name.rfind('~') {
+                Some(pos) => Version::parse(&name[(pos + 1)..]).is_ok(),
+                None      => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+/// Path of the entry a temporary file at `path` was written for, if it is one
+///
+/// These are `notes/.a~0.1.0.tmp` for the entry `notes/a~0.1.0` and `notes/.a~0.1.0.body.tmp` for
+/// its body file, see `LazyFile::replace_with()` and `write_body_file()`.
+fn temp_file_entry_path(path: &Path) -> Option<PathBuf> {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.starts_with(".") && name.ends_with(".tmp") => name,
+        _ => return None,
+    };
+
+    let name = &name[1..(name.len() - ".tmp".len())];
+    let name = if name.ends_with(".body") { &name[..(name.len() - ".body".len())] } else { name };
+    let entry_path = path.with_file_name(name);
+    if is_entry_path(&entry_path) { Some(entry_path) } else { None }
+}
+
+/// Path of the file the content of the entry at `path` is written to if `separate-content` is
+/// enabled
+///
+/// The file is hidden, so it is never mistaken for an entry: the content of `notes/a~0.1.0` is in
+/// `notes/.a~0.1.0.body`.
+fn body_path_for(path: &Path) -> PathBuf {
+    let name = path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or(String::new());
+    path.with_file_name(format!(".{}.body", name))
+}
+
+/// Append an item with the current time and `note` to `imag.history`, keeping at most `length`
+/// items
+fn append_history(header: &mut EntryHeader, note: Option<&str>, length: usize) -> Result<()> {
+    use std::time::UNIX_EPOCH;
+
+    let mut history = match try!(header.read("imag.history")) {
+        Some(Value::Array(items)) => items,
+        None => vec![],
+        Some(_) => return Err(StoreError::new(StoreErrorKind::HeaderTypeFailure, None)),
+    };
+
+    let mut item = BTreeMap::new();
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    item.insert(String::from("timestamp"), Value::Integer(timestamp));
+    if let Some(note) = note {
+        item.insert(String::from("note"), Value::String(String::from(note)));
+    }
+    history.push(Value::Table(item));
+
+    if history.len() > length {
+        let excess = history.len() - length;
+        history.drain(..excess);
+    }
+
+    header.set("imag.history", Value::Array(history)).map(|_| ())
+}
+
+/// Write `content` to the body file at `body_path`, through a temporary file which is renamed
+fn write_body_file(body_path: &Path, content: &str, durable: bool) -> Result<()> {
+    use std::fs::rename;
+
+    let temp_path = body_path.with_extension("body.tmp");
+    File::create(&temp_path)
+        .and_then(|mut f| {
+            try!(f.write_all(content.as_bytes()));
+            if durable { f.sync_all() } else { Ok(()) }
+        })
+        .and_then(|_| rename(&temp_path, body_path))
+        .map_err(|e| {
+            let _ = remove_file(&temp_path);
+            StoreError::new(StoreErrorKind::FileError, Some(Box::new(e)))
+        })
+}
+
+/// Remove the body file of the entry at `path`, if there is one
+fn remove_body_file(path: &Path) -> Result<()> {
+    let body_path = body_path_for(path);
+    if body_path.exists() {
+        remove_file(&body_path)
+            .map_err(|e| StoreError::new(StoreErrorKind::FileError, Some(Box::new(e))))
+    } else {
+        Ok(())
+    }
+}
+
+/// Get month and day out of a date string like `2016-01-31` or `2016-01-31T10:00:00Z`
+fn month_and_day(s: &str) -> Option<(u32, u32)> {
+    use std::str::FromStr;
+
+    let mut parts = s.splitn(3, '-').skip(1);
+    let month = parts.next().and_then(|m| u32::from_str(m).ok());
+    let day = parts.next()
+        .and_then(|d| d.get(..2))
+        .and_then(|d| u32::from_str(d).ok());
+
+    match (month, day) {
+        (Some(m), Some(d)) if m >= 1 && m <= 12 && d >= 1 && d <= 31 => Some((m, d)),
+        _ => None,
+    }
+}
+
+fn toml_to_json(v: &Value) -> Json {
+    match v {
+        &Value::String(ref s)   => Json::String(s.clone()),
+        &Value::Integer(i)      => Json::I64(i),
+        &Value::Float(f)        => Json::F64(f),
+        &Value::Boolean(b)      => Json::Boolean(b),
+        &Value::Datetime(ref s) => Json::String(s.clone()),
+        &Value::Array(ref a)    => Json::Array(a.iter().map(toml_to_json).collect()),
+        &Value::Table(ref t)    => {
+            Json::Object(t.iter().map(|(k, v)| (k.clone(), toml_to_json(v))).collect())
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    extern crate env_logger;
+
+    use std::collections::BTreeMap;
+    use std::path::{Path, PathBuf};
+    use super::EntryHeader;
+    use super::DEFAULT_HEADER_MAX_DEPTH;
+    use super::Store;
+    use super::StoreMetrics;
+    use super::Token;
+    use storeid::StoreId;
+
+    use tempdir::TempDir;
+    use toml::Value;
+
+    #[test]
+    fn test_imag_section() {
+        use super::has_main_section;
+
+        let mut map = BTreeMap::new();
+        map.insert("imag".into(), Value::Table(BTreeMap::new()));
+
+        assert!(has_main_section(&map));
+    }
+
+    #[test]
+    fn test_imag_invalid_section_type() {
+        use super::has_main_section;
+
+        let mut map = BTreeMap::new();
+        map.insert("imag".into(), Value::Boolean(false));
+
+        assert!(!has_main_section(&map));
+    }
+
+    #[test]
+    fn test_imag_abscent_main_section() {
+        use super::has_main_section;
+
+        let mut map = BTreeMap::new();
+        map.insert("not_imag".into(), Value::Boolean(false));
+
+        assert!(!has_main_section(&map));
+    }
+
+    #[test]
+    fn test_main_section_without_version() {
+        use super::has_imag_version_in_main_section;
+
+        let mut map = BTreeMap::new();
+        map.insert("imag".into(), Value::Table(BTreeMap::new()));
+
+        assert!(!has_imag_version_in_main_section(&map));
+    }
+
+    #[test]
+    fn test_main_section_with_version() {
+        use super::has_imag_version_in_main_section;
+
+        let mut map = BTreeMap::new();
+        let mut sub = BTreeMap::new();
+        sub.insert("version".into(), Value::String("0.0.0".into()));
+        map.insert("imag".into(), Value::Table(sub));
+
+        assert!(has_imag_version_in_main_section(&map));
+    }
+
+    #[test]
+    fn test_main_section_with_version_in_wrong_type() {
+        use super::has_imag_version_in_main_section;
+
+        let mut map = BTreeMap::new();
+        let mut sub = BTreeMap::new();
+        sub.insert("version".into(), Value::Boolean(false));
+        map.insert("imag".into(), Value::Table(sub));
+
+        assert!(!has_imag_version_in_main_section(&map));
+    }
+
+    #[test]
+    fn test_verification_good() {
+        use super::verify_header_consistency;
+
+        let mut header = BTreeMap::new();
+        let sub = {
+            let mut sub = BTreeMap::new();
+            sub.insert("version".into(), Value::String(String::from("0.0.0")));
+
+            Value::Table(sub)
+        };
+
+        header.insert("imag".into(), sub);
+
+        assert!(verify_header_consistency(header).is_ok());
+    }
+
+    #[test]
+    fn test_verification_invalid_versionstring() {
+        use super::verify_header_consistency;
+
+        let mut header = BTreeMap::new();
+        let sub = {
+            let mut sub = BTreeMap::new();
+            sub.insert("version".into(), Value::String(String::from("000")));
+
+            Value::Table(sub)
+        };
+
+        header.insert("imag".into(), sub);
+
+        assert!(!verify_header_consistency(header).is_ok());
+    }
+
+
+    #[test]
+    fn test_verification_current_version() {
+        use super::verify_header_consistency;
+
+        let mut header = BTreeMap::new();
+        let sub = {
+            let mut sub = BTreeMap::new();
+            sub.insert("version".into(), Value::String(String::from(version!())));
+
+            Value::Table(sub)
+        };
+
+        header.insert("imag".into(), sub);
+
+        assert!(verify_header_consistency(header).is_ok());
+    }
+
+    static TEST_ENTRY : &'static str = "---
+[imag]
+version = \"0.0.3\"
+---
+Hai";
+
+    #[test]
+    fn test_entry_from_str() {
+        use super::Entry;
+        use std::path::PathBuf;
+        println!("{}", TEST_ENTRY);
+        let entry = Entry::from_str(PathBuf::from("/test/foo~1.3"),
+                                    TEST_ENTRY).unwrap();
+
+        assert_eq!(entry.content, "Hai");
+    }
+
+    #[test]
+    fn test_entry_from_reader() {
+        use super::Entry;
+        use std::io::Cursor;
+        use std::path::PathBuf;
+        let mut cursor = Cursor::new(Vec::from(TEST_ENTRY.as_bytes()));
+        let entry = Entry::from_reader(PathBuf::from("/test/foo~1.3"), &mut cursor).unwrap();
+
+        assert_eq!(entry.content, "Hai");
+        assert_eq!(entry.to_str(), TEST_ENTRY);
+    }
+
+    #[test]
+    fn test_entry_from_str_with_bom() {
+        use super::Entry;
+        use std::path::PathBuf;
+        let text = format!("\u{feff}{}", TEST_ENTRY);
+        let entry = Entry::from_str(PathBuf::from("/test/foo~1.3"), &text[..]).unwrap();
+
+        assert_eq!(entry.content, "Hai");
+    }
+
+    #[test]
+    fn test_entry_from_str_with_leading_blank_lines() {
+        use super::Entry;
+        use std::path::PathBuf;
+        let text = format!("\n  \n\t\n{}", TEST_ENTRY);
+        let entry = Entry::from_str(PathBuf::from("/test/foo~1.3"), &text[..]).unwrap();
+
+        assert_eq!(entry.content, "Hai");
+        assert_eq!(entry.to_str(), TEST_ENTRY);
+    }
+
+    #[test]
+    fn test_entry_to_str() {
+        use super::Entry;
+        use std::path::PathBuf;
+        println!("{}", TEST_ENTRY);
+        let entry = Entry::from_str(PathBuf::from("/test/foo~1.3"),
+                                    TEST_ENTRY).unwrap();
+        let string = entry.to_str();
+
+        assert_eq!(TEST_ENTRY, string);
+    }
+
+    #[test]
+    fn test_walk_header_simple() {
+        let tokens = EntryHeader::tokenize("a", '.', DEFAULT_HEADER_MAX_DEPTH).unwrap();
+        assert!(tokens.len() == 1, "1 token was expected, {} were parsed", tokens.len());
+        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from("a")),
+                "'a' token was expected, {:?} was parsed", tokens.iter().next());
+
+        let mut header = BTreeMap::new();
+        header.insert(String::from("a"), Value::Integer(1));
+
+        let mut v_header = Value::Table(header);
+        let res = EntryHeader::walk_header(&mut v_header, tokens);
+        assert_eq!(&mut Value::Integer(1), res.unwrap());
+    }
+
+    #[test]
+    fn test_walk_header_with_array() {
+        let tokens = EntryHeader::tokenize("a.0", '.', DEFAULT_HEADER_MAX_DEPTH).unwrap();
+        assert!(tokens.len() == 2, "2 token was expected, {} were parsed", tokens.len());
+        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from("a")),
+                "'a' token was expected, {:?} was parsed", tokens.iter().next());
+
+        let mut header = BTreeMap::new();
+        let ary = Value::Array(vec![Value::Integer(1)]);
+        header.insert(String::from("a"), ary);
+
+
+        let mut v_header = Value::Table(header);
+        let res = EntryHeader::walk_header(&mut v_header, tokens);
+        assert_eq!(&mut Value::Integer(1), res.unwrap());
+    }
+
+    #[test]
+    fn test_walk_header_extract_array() {
+        let tokens = EntryHeader::tokenize("a", '.', DEFAULT_HEADER_MAX_DEPTH).unwrap();
+        assert!(tokens.len() == 1, "1 token was expected, {} were parsed", tokens.len());
+        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from("a")),
+                "'a' token was expected, {:?} was parsed", tokens.iter().next());
+
+        let mut header = BTreeMap::new();
+        let ary = Value::Array(vec![Value::Integer(1)]);
+        header.insert(String::from("a"), ary);
+
+        let mut v_header = Value::Table(header);
+        let res = EntryHeader::walk_header(&mut v_header, tokens);
+        assert_eq!(&mut Value::Array(vec![Value::Integer(1)]), res.unwrap());
+    }
+
+    /**
+     * Creates a big testing header.
+     *
+     * JSON equivalent:
+     *
+     * ```json
+     * {
+     *      "a": {
+     *          "array": [ 0, 1, 2, 3, 4, 5, 6, 7, 8, 9 ]
+     *      },
+     *      "b": {
+     *          "array": [ "string1", "string2", "string3", "string4" ]
+     *      },
+     *      "c": {
+     *          "array": [ 1, "string2", 3, "string4" ]
+     *      },
+     *      "d": {
+     *          "array": [
+     *              {
+     *                  "d1": 1
+     *              },
+     *              {
+     *                  "d2": 2
+     *              },
+     *              {
+     *                  "d3": 3
+     *              },
+     *          ],
+     *
+     *          "something": "else",
+     *
+     *          "and": {
+     *              "something": {
+     *                  "totally": "different"
+     *              }
+     *          }
+     *      }
+     * }
+     * ```
+     *
+     * The sections "a", "b", "c", "d" are created in the respective helper functions
+     * create_header_section_a, create_header_section_b, create_header_section_c and
+     * create_header_section_d.
+     *
+     * These functions can also be used for testing.
+     *
+     */
+    fn create_header() -> Value {
+        let a = create_header_section_a();
+        let b = create_header_section_b();
+        let c = create_header_section_c();
+        let d = create_header_section_d();
+
+        let mut header = BTreeMap::new();
+        header.insert(String::from("a"), a);
+        header.insert(String::from("b"), b);
+        header.insert(String::from("c"), c);
+        header.insert(String::from("d"), d);
+
+        Value::Table(header)
+    }
+
+    fn create_header_section_a() -> Value {
+        // 0..10 is exclusive 10
+        let a_ary = Value::Array((0..10).map(|x| Value::Integer(x)).collect());
+
+        let mut a_obj = BTreeMap::new();
+        a_obj.insert(String::from("array"), a_ary);
+        Value::Table(a_obj)
+    }
+
+    fn create_header_section_b() -> Value {
+        let b_ary = Value::Array((0..9)
+                                 .map(|x| Value::String(format!("string{}", x)))
+                                 .collect());
+
+        let mut b_obj = BTreeMap::new();
+        b_obj.insert(String::from("array"), b_ary);
+        Value::Table(b_obj)
+    }
+
+    fn create_header_section_c() -> Value {
+        let c_ary = Value::Array(
+            vec![
+                Value::Integer(1),
+                Value::String(String::from("string2")),
+                Value::Integer(3),
+                Value::String(String::from("string4"))
+            ]);
+
+        let mut c_obj = BTreeMap::new();
+        c_obj.insert(String::from("array"), c_ary);
+        Value::Table(c_obj)
+    }
+
+    fn create_header_section_d() -> Value {
+        let d_ary = Value::Array(
+            vec![
+                {
+                    let mut tab = BTreeMap::new();
+                    tab.insert(String::from("d1"), Value::Integer(1));
+                    tab
+                },
+                {
+                    let mut tab = BTreeMap::new();
+                    tab.insert(String::from("d2"), Value::Integer(2));
+                    tab
+                },
+                {
+                    let mut tab = BTreeMap::new();
+                    tab.insert(String::from("d3"), Value::Integer(3));
+                    tab
+                },
+            ].into_iter().map(Value::Table).collect());
+
+        let and_obj = Value::Table({
+            let mut tab = BTreeMap::new();
+            let something_tab = Value::Table({
+                let mut tab = BTreeMap::new();
+                tab.insert(String::from("totally"), Value::String(String::from("different")));
+                tab
+            });
+            tab.insert(String::from("something"), something_tab);
+            tab
+        });
+
+        let mut d_obj = BTreeMap::new();
+        d_obj.insert(String::from("array"), d_ary);
+        d_obj.insert(String::from("something"), Value::String(String::from("else")));
+        d_obj.insert(String::from("and"), and_obj);
+        Value::Table(d_obj)
+    }
+
+    #[test]
+    fn test_header_iter_flat() {
+        let header = EntryHeader::from(match create_header() {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        });
+
+        let flat : Vec<(String, &Value)> = header.iter_flat().collect();
+        assert_eq!(flat.len(), 10 + 9 + 4 + 5);
+
+        assert_eq!(flat[0], (String::from("a.array.0"), &Value::Integer(0)));
+        assert_eq!(flat[10], (String::from("b.array.0"), &Value::String(String::from("string0"))));
+
+        let d : Vec<&str> = flat.iter()
+            .map(|&(ref k, _)| &k[..])
+            .filter(|k| k.starts_with("d."))
+            .collect();
+        assert_eq!(d, vec![
+            "d.and.something.totally",
+            "d.array.0.d1",
+            "d.array.1.d2",
+            "d.array.2.d3",
+            "d.something",
+        ]);
+
+        for (path, value) in header.iter_flat() {
+            assert_eq!(header.read(&path[..]).unwrap().as_ref(), Some(value));
+        }
+    }
+
+    fn header_with_array(a: Vec<i64>) -> EntryHeader {
+        let mut t = BTreeMap::new();
+        t.insert(String::from("a"), Value::Array(a.into_iter().map(Value::Integer).collect()));
+        EntryHeader::from(t)
+    }
+
+    fn array_of(h: &EntryHeader) -> Vec<i64> {
+        match h.read("a").unwrap() {
+            Some(Value::Array(a)) => a.into_iter().map(|v| match v {
+                Value::Integer(i) => i,
+                _ => panic!("Non-Integer in array: {:?}", v),
+            }).collect(),
+            other => panic!("Expected array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_typed_read() {
+        let h = EntryHeader::from(match create_header() {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        });
+
+        assert_eq!(h.read_string("d.something").unwrap(), Some(String::from("else")));
+        assert_eq!(h.read_integer("a.array.3").unwrap(), Some(3));
+        assert_eq!(h.read_array("c.array").unwrap().map(|a| a.len()), Some(4));
+        assert_eq!(h.read_string("d.nothing").unwrap(), None);
+        assert_eq!(h.read_boolean("d.nothing").unwrap(), None);
+        assert_eq!(h.read_float("d.nothing").unwrap(), None);
+    }
+
+    #[test]
+    fn test_header_append() {
+        use error::StoreErrorKind;
+
+        let mut h = EntryHeader::from(match create_header() {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        });
+
+        let len = h.read_array("a.array").unwrap().unwrap().len();
+        h.append("a.array", Value::Integer(42)).unwrap();
+        let array = h.read_array("a.array").unwrap().unwrap();
+        assert_eq!(array.len(), len + 1);
+        assert_eq!(array.last(), Some(&Value::Integer(42)));
+
+        // A missing array is created
+        h.append("d.new", Value::String(String::from("first"))).unwrap();
+        assert_eq!(h.read_array("d.new").unwrap(),
+                   Some(vec![Value::String(String::from("first"))]));
+
+        assert!(!h.append_unique("d.new", Value::String(String::from("first"))).unwrap());
+        assert!(h.append_unique("d.new", Value::String(String::from("second"))).unwrap());
+        assert_eq!(h.read_array("d.new").unwrap().map(|a| a.len()), Some(2));
+
+        // So are missing tables on the way to it
+        h.append("links.internal", Value::String(String::from("test/a~0.1.0"))).unwrap();
+        assert_eq!(h.read_array("links.internal").unwrap(),
+                   Some(vec![Value::String(String::from("test/a~0.1.0"))]));
+        h.append("e.f.g", Value::Integer(1)).unwrap();
+        assert_eq!(h.read_array("e.f.g").unwrap(), Some(vec![Value::Integer(1)]));
+
+        // Nothing is created if the array cannot be appended to
+        let mut deep = Value::Boolean(true);
+        for _ in 0..DEFAULT_HEADER_MAX_DEPTH {
+            let mut t = BTreeMap::new();
+            t.insert(String::from("a"), deep);
+            deep = Value::Table(t);
+        }
+        let before = h.header().clone();
+        assert_eq!(h.append("x.y", deep).unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
+        assert_eq!(h.header(), &before);
+
+        assert_eq!(h.append("d.something", Value::Integer(1)).unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(h.append_unique("d.something", Value::Integer(1)).unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+    }
+
+    #[test]
+    fn test_header_keys() {
+        use error::StoreErrorKind;
+
+        let h = EntryHeader::from(match create_header() {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        });
+
+        assert_eq!(h.keys("").unwrap(), vec!["a", "b", "c", "d"]);
+        assert_eq!(h.keys("d").unwrap(), vec!["and", "array", "something"]);
+        assert_eq!(h.entries("d.and.something").unwrap(),
+                   vec![(String::from("totally"), Value::String(String::from("different")))]);
+
+        assert_eq!(h.keys("d.something").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(h.entries("d.nothing").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderKeyNotFound);
+    }
+
+    #[test]
+    fn test_typed_read_wrong_type() {
+        use error::StoreErrorKind;
+
+        let h = EntryHeader::from(match create_header() {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        });
+
+        assert_eq!(h.read_integer("d.something").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(h.read_string("a.array.0").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(h.read_boolean("a.array").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(h.read_float("a.array.0").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(h.read_array("d.something").unwrap_err().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+    }
+
+    #[test]
+    fn test_insert_array_index_zero_of_empty_array() {
+        let mut h = header_with_array(vec![]);
+        assert!(h.insert("a.0", Value::Integer(1)).unwrap());
+        assert_eq!(array_of(&h), vec![1]);
+    }
+
+    #[test]
+    fn test_insert_array_index_at_length() {
+        let mut h = header_with_array(vec![1, 2]);
+        assert!(h.insert("a.2", Value::Integer(3)).unwrap());
+        assert_eq!(array_of(&h), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_insert_array_index_past_length() {
+        let mut h = header_with_array(vec![1, 2]);
+        assert!(h.insert("a.10", Value::Integer(3)).unwrap());
+        assert_eq!(array_of(&h), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_insert_array_index_inside_array() {
+        let mut h = header_with_array(vec![1, 2]);
+        assert!(!h.insert("a.1", Value::Integer(3)).unwrap());
+        assert_eq!(array_of(&h), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_set_array_index() {
+        let mut h = header_with_array(vec![1, 2]);
+        assert_eq!(h.set("a.0", Value::Integer(5)).unwrap(), Some(Value::Integer(1)));
+        assert_eq!(array_of(&h), vec![5, 2]);
+
+        assert_eq!(h.set("a.2", Value::Integer(3)).unwrap(), None);
+        assert_eq!(array_of(&h), vec![5, 2, 3]);
+
+        assert_eq!(h.set("a.10", Value::Integer(4)).unwrap(), None);
+        assert_eq!(array_of(&h), vec![5, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_walk_header_big_a() {
+        test_walk_header_extract_section("a", &create_header_section_a());
+    }
+
+    #[test]
+    fn test_walk_header_big_b() {
+        test_walk_header_extract_section("b", &create_header_section_b());
+    }
+
+    #[test]
+    fn test_walk_header_big_c() {
+        test_walk_header_extract_section("c", &create_header_section_c());
+    }
+
+    #[test]
+    fn test_walk_header_big_d() {
+        test_walk_header_extract_section("d", &create_header_section_d());
+    }
+
+    fn test_walk_header_extract_section(secname: &str, expected: &Value) {
+        let tokens = EntryHeader::tokenize(secname, '.', DEFAULT_HEADER_MAX_DEPTH).unwrap();
+        assert!(tokens.len() == 1, "1 token was expected, {} were parsed", tokens.len());
+        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from(secname)),
+                "'{}' token was expected, {:?} was parsed", secname, tokens.iter().next());
+
+        let mut header = create_header();
+        let res = EntryHeader::walk_header(&mut header, tokens);
+        assert_eq!(expected, res.unwrap());
+    }
+
+    #[test]
+    fn test_walk_header_extract_numbers() {
+        test_extract_number("a", 0, 0);
+        test_extract_number("a", 1, 1);
+        test_extract_number("a", 2, 2);
+        test_extract_number("a", 3, 3);
+        test_extract_number("a", 4, 4);
+        test_extract_number("a", 5, 5);
+        test_extract_number("a", 6, 6);
+        test_extract_number("a", 7, 7);
+        test_extract_number("a", 8, 8);
+        test_extract_number("a", 9, 9);
+
+        test_extract_number("c", 0, 1);
+        test_extract_number("c", 2, 3);
+    }
+
+    fn test_extract_number(sec: &str, idx: usize, exp: i64) {
+        let spec = format!("{}.array.{}", sec, idx);
+        let tokens = EntryHeader::tokenize(&spec[..], '.', DEFAULT_HEADER_MAX_DEPTH).unwrap();
+        assert!(tokens.len() == 3, "3 token was expected, {} were parsed", tokens.len());
+        {
+            let mut iter = tokens.iter();
+
+            let tok = iter.next().unwrap();
+            let exp = Token::Key(String::from(sec));
+            assert!(tok == &exp, "'{}' token was expected, {:?} was parsed", sec, tok);
+
+            let tok = iter.next().unwrap();
+            let exp = Token::Key(String::from("array"));
+            assert!(tok == &exp, "'array' token was expected, {:?} was parsed", tok);
+
+            let tok = iter.next().unwrap();
+            let exp = Token::Index(idx);
+            assert!(tok == &exp, "'{}' token was expected, {:?} was parsed", idx, tok);
+        }
+
+        let mut header = create_header();
+        let res = EntryHeader::walk_header(&mut header, tokens);
+        assert_eq!(&mut Value::Integer(exp), res.unwrap());
+    }
+
+    #[test]
+    fn test_header_read() {
+        let v = create_header();
+        let h = match v {
+            Value::Table(t) => EntryHeader::from_table(t),
+            _ => panic!("create_header() doesn't return a table!"),
+        };
+
+        assert!(if let Ok(Some(Value::Table(_)))  = h.read("a") { true } else { false });
+        assert!(if let Ok(Some(Value::Array(_)))   = h.read("a.array") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.1") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.9") { true } else { false });
+
+        assert!(if let Ok(Some(Value::Table(_))) = h.read("c") { true } else { false });
+        assert!(if let Ok(Some(Value::Array(_)))  = h.read("c.array") { true } else { false });
+        assert!(if let Ok(Some(Value::String(_))) = h.read("c.array.1") { true } else { false });
+        assert!(if let Ok(None) = h.read("c.array.9") { true } else { false });
+
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("d.array.0.d1") { true } else { false });
+        assert!(if let Ok(None) = h.read("d.array.0.d2") { true } else { false });
+        assert!(if let Ok(None) = h.read("d.array.0.d3") { true } else { false });
+
+        assert!(if let Ok(None) = h.read("d.array.1.d1") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("d.array.1.d2") { true } else { false });
+        assert!(if let Ok(None) = h.read("d.array.1.d3") { true } else { false });
+
+        assert!(if let Ok(None) = h.read("d.array.2.d1") { true } else { false });
+        assert!(if let Ok(None) = h.read("d.array.2.d2") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("d.array.2.d3") { true } else { false });
+
+        assert!(if let Ok(Some(Value::String(_))) = h.read("d.something") { true } else { false });
+        assert!(if let Ok(Some(Value::Table(_))) = h.read("d.and") { true } else { false });
+        assert!(if let Ok(Some(Value::Table(_))) = h.read("d.and.something") { true } else { false });
+        assert!(if let Ok(Some(Value::String(_))) = h.read("d.and.something.totally") { true } else { false });
+    }
+
+    #[test]
+    fn test_header_set_override() {
+        let _ = env_logger::init();
+        let v = create_header();
+        let mut h = match v {
+            Value::Table(t) => EntryHeader::from_table(t),
+            _ => panic!("create_header() doesn't return a table!"),
+        };
+
+        println!("Testing index 0");
+        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(0));
+
+        println!("Altering index 0");
+        assert_eq!(h.set("a.array.0", Value::Integer(42)).unwrap().unwrap(), Value::Integer(0));
+
+        println!("Values now: {:?}", h);
+
+        println!("Testing all indexes");
+        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(42));
+        assert_eq!(h.read("a.array.1").unwrap().unwrap(), Value::Integer(1));
+        assert_eq!(h.read("a.array.2").unwrap().unwrap(), Value::Integer(2));
+        assert_eq!(h.read("a.array.3").unwrap().unwrap(), Value::Integer(3));
+        assert_eq!(h.read("a.array.4").unwrap().unwrap(), Value::Integer(4));
+        assert_eq!(h.read("a.array.5").unwrap().unwrap(), Value::Integer(5));
+        assert_eq!(h.read("a.array.6").unwrap().unwrap(), Value::Integer(6));
+        assert_eq!(h.read("a.array.7").unwrap().unwrap(), Value::Integer(7));
+        assert_eq!(h.read("a.array.8").unwrap().unwrap(), Value::Integer(8));
+        assert_eq!(h.read("a.array.9").unwrap().unwrap(), Value::Integer(9));
+    }
+
+    #[test]
+    fn test_header_set_new() {
+        let _ = env_logger::init();
+        let v = create_header();
+        let mut h = match v {
+            Value::Table(t) => EntryHeader::from_table(t),
+            _ => panic!("create_header() doesn't return a table!"),
+        };
+
+        assert!(h.read("a.foo").is_ok());
+        assert!(h.read("a.foo").unwrap().is_none());
+
+        {
+            let v = h.set("a.foo", Value::Integer(42));
+            assert!(v.is_ok());
+            assert!(v.unwrap().is_none());
+
+            assert!(if let Ok(Some(Value::Table(_))) = h.read("a") { true } else { false });
+            assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.foo") { true } else { false });
+        }
+
+        {
+            let v = h.set("new", Value::Table(BTreeMap::new()));
+            assert!(v.is_ok());
+            assert!(v.unwrap().is_none());
+
+            let v = h.set("new.subset", Value::Table(BTreeMap::new()));
+            assert!(v.is_ok());
+            assert!(v.unwrap().is_none());
+
+            let v = h.set("new.subset.dest", Value::Integer(1337));
+            assert!(v.is_ok());
+            assert!(v.unwrap().is_none());
+
+            assert!(if let Ok(Some(Value::Table(_))) = h.read("new") { true } else { false });
+            assert!(if let Ok(Some(Value::Table(_))) = h.read("new.subset") { true } else { false });
+            assert!(if let Ok(Some(Value::Integer(_))) = h.read("new.subset.dest") { true } else { false });
+        }
+    }
+
+
+    #[test]
+    fn test_header_insert_override() {
+        let _ = env_logger::init();
+        let v = create_header();
+        let mut h = match v {
+            Value::Table(t) => EntryHeader::from_table(t),
+            _ => panic!("create_header() doesn't return a table!"),
+        };
+
+        println!("Testing index 0");
+        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(0));
+
+        println!("Altering index 0");
+        assert_eq!(h.insert("a.array.0", Value::Integer(42)).unwrap(), false);
+        println!("...should have failed");
+
+        println!("Testing all indexes");
+        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(0));
+        assert_eq!(h.read("a.array.1").unwrap().unwrap(), Value::Integer(1));
+        assert_eq!(h.read("a.array.2").unwrap().unwrap(), Value::Integer(2));
+        assert_eq!(h.read("a.array.3").unwrap().unwrap(), Value::Integer(3));
+        assert_eq!(h.read("a.array.4").unwrap().unwrap(), Value::Integer(4));
+        assert_eq!(h.read("a.array.5").unwrap().unwrap(), Value::Integer(5));
+        assert_eq!(h.read("a.array.6").unwrap().unwrap(), Value::Integer(6));
+        assert_eq!(h.read("a.array.7").unwrap().unwrap(), Value::Integer(7));
+        assert_eq!(h.read("a.array.8").unwrap().unwrap(), Value::Integer(8));
+        assert_eq!(h.read("a.array.9").unwrap().unwrap(), Value::Integer(9));
+    }
+
+    #[test]
+    fn test_header_insert_new() {
+        let _ = env_logger::init();
+        let v = create_header();
+        let mut h = match v {
+            Value::Table(t) => EntryHeader::from_table(t),
+            _ => panic!("create_header() doesn't return a table!"),
+        };
+
+        assert!(h.read("a.foo").is_ok());
+        assert!(h.read("a.foo").unwrap().is_none());
+
+        {
+            let v = h.insert("a.foo", Value::Integer(42));
+            assert!(v.is_ok());
+            assert_eq!(v.unwrap(), true);
+
+            assert!(if let Ok(Some(Value::Table(_))) = h.read("a") { true } else { false });
+            assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.foo") { true } else { false });
+        }
+
+        {
+            let v = h.insert("new", Value::Table(BTreeMap::new()));
+            assert!(v.is_ok());
+            assert_eq!(v.unwrap(), true);
+
+            let v = h.insert("new.subset", Value::Table(BTreeMap::new()));
+            assert!(v.is_ok());
+            assert_eq!(v.unwrap(), true);
+
+            let v = h.insert("new.subset.dest", Value::Integer(1337));
+            assert!(v.is_ok());
+            assert_eq!(v.unwrap(), true);
+
+            assert!(if let Ok(Some(Value::Table(_))) = h.read("new") { true } else { false });
+            assert!(if let Ok(Some(Value::Table(_))) = h.read("new.subset") { true } else { false });
+            assert!(if let Ok(Some(Value::Integer(_))) = h.read("new.subset.dest") { true } else { false });
+        }
+    }
+
+    #[test]
+    fn test_header_delete() {
+        let _ = env_logger::init();
+        let v = create_header();
+        let mut h = match v {
+            Value::Table(t) => EntryHeader::from_table(t),
+            _ => panic!("create_header() doesn't return a table!"),
+        };
+
+        assert!(if let Ok(Some(Value::Table(_)))   = h.read("a") { true } else { false });
+        assert!(if let Ok(Some(Value::Array(_)))   = h.read("a.array") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.1") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.9") { true } else { false });
+
+        assert!(if let Ok(Some(Value::Integer(1))) = h.delete("a.array.1") { true } else { false });
+        assert!(if let Ok(Some(Value::Integer(9))) = h.delete("a.array.8") { true } else { false });
+        assert!(if let Ok(Some(Value::Array(_)))   = h.delete("a.array") { true } else { false });
+        assert!(if let Ok(Some(Value::Table(_)))   = h.delete("a") { true } else { false });
+
+    }
+
+    fn get_store_config(extra: &str) -> Option<Value> {
+        use toml::Parser;
+
+        let cfg = format!("
+        pre-read-hook-aspects      = []
+        post-read-hook-aspects     = []
+        pre-create-hook-aspects    = []
+        post-create-hook-aspects   = []
+        pre-retrieve-hook-aspects  = []
+        post-retrieve-hook-aspects = []
+        pre-update-hook-aspects    = []
+        post-update-hook-aspects   = []
+        pre-delete-hook-aspects    = []
+        post-delete-hook-aspects   = []
+        {}
+
+        [hooks]
+        [aspects]
+        ", extra);
+
+        Parser::new(&cfg[..]).parse().map(Value::Table)
+    }
+
+    fn set_hook_aspects(cfg: &mut Option<Value>, key: &str, aspects: &[&str]) {
+        if let &mut Some(Value::Table(ref mut t)) = cfg {
+            let aspects = aspects.iter().map(|a| Value::String(String::from(*a))).collect();
+            t.insert(String::from(key), Value::Array(aspects));
+        }
+    }
+
+    fn get_store_with_config(cfg: Option<Value>) -> (TempDir, Store) {
+        let dir = TempDir::new("imag-store-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), cfg).unwrap();
+        (dir, store)
+    }
+
+    fn read_file(path: &Path) -> String {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut s = String::new();
+        File::open(path).unwrap().read_to_string(&mut s).unwrap();
+        s
+    }
+
+    #[test]
+    fn test_flat_layout_roundtrip() {
+        let (dir, store) = get_store_with_config(get_store_config("layout = \"flat\""));
+        let id = PathBuf::from("diary/2016/01/02-10:30~0.1.0");
+
+        {
+            let mut entry = store.create(id.clone()).unwrap();
+            *entry.get_content_mut() = String::from("flat content");
+        }
+
+        let mut on_disk = PathBuf::from(dir.path());
+        on_disk.push("diary");
+        on_disk.push("2016%2F01%2F02-10%3A30~0.1.0");
+        assert!(on_disk.is_file());
+
+        let ids : Vec<StoreId> = store.retrieve_for_module("diary").unwrap().collect();
+        let mut expected = PathBuf::from(dir.path());
+        expected.push(&id);
+        assert_eq!(ids, vec![expected]);
+
+        let entry = store.retrieve_copy(id).unwrap();
+        assert_eq!(entry.get_content(), "flat content");
+    }
+
+    #[test]
+    fn test_storeid_to_path() {
+        let (dir, store) = get_store_with_config(None);
+        let expected = dir.path().join("test/a~0.1.0");
+        for id in ["test/a~0.1.0", "/test/a~0.1.0"].iter() {
+            assert_eq!(store.storeid_to_path(&PathBuf::from(id)).unwrap(), expected);
+        }
+        assert_eq!(store.storeid_to_path(&expected).unwrap(), expected);
+
+        let (dir, store) = get_store_with_config(get_store_config("layout = \"flat\""));
+        assert_eq!(store.storeid_to_path(&PathBuf::from("diary/2016/01~0.1.0")).unwrap(),
+                   dir.path().join("diary/2016%2F01~0.1.0"));
+    }
+
+    #[test]
+    fn test_header_max_depth() {
+        use error::StoreErrorKind;
+
+        let mut header = EntryHeader::new();
+        let mut deep = Value::Boolean(true);
+        for _ in 0..(2 * DEFAULT_HEADER_MAX_DEPTH) {
+            let mut t = BTreeMap::new();
+            t.insert(String::from("a"), deep);
+            deep = Value::Table(t);
+        }
+        assert_eq!(header.set("deep", deep.clone()).err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
+        assert_eq!(header.insert("deep", deep.clone()).err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
+        assert_eq!(header.read("deep").unwrap(), None);
+
+        let mut table = match header.header().clone() {
+            Value::Table(t) => t,
+            _ => panic!("Header is no table"),
+        };
+        table.insert(String::from("deep"), deep);
+        let mut header = EntryHeader::from_table(table);
+        assert!(header.depth() > DEFAULT_HEADER_MAX_DEPTH);
+        assert_eq!(header.verify().err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
+
+        let spec = vec!["a"; 2 * DEFAULT_HEADER_MAX_DEPTH].join(".");
+        assert_eq!(header.read(&spec[..]).err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
+        assert_eq!(header.set(&spec[..], Value::Boolean(false)).err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
+    }
+
+    #[test]
+    fn test_header_max_depth_from_config() {
+        use error::StoreErrorKind;
+
+        let (_dir, store) = get_store_with_config(get_store_config("header-max-depth = 3"));
+        let mut entry = store.create(PathBuf::from("test/deep~0.1.0")).unwrap();
+        assert_eq!(entry.get_header().max_depth(), 3);
+
+        assert!(entry.get_header_mut().insert("test", Value::Table(BTreeMap::new())).is_ok());
+        assert!(entry.get_header_mut().insert("test.a", Value::Table(BTreeMap::new())).is_ok());
+        assert!(entry.get_header_mut().insert("test.a.b", Value::Boolean(true)).is_ok());
+        assert!(entry.get_header().verify().is_ok());
+        assert_eq!(entry.get_header_mut().insert("test.a.b.c", Value::Boolean(true))
+                   .err().unwrap().err_type(), StoreErrorKind::HeaderPathSyntaxError);
+
+        let mut t = BTreeMap::new();
+        t.insert(String::from("c"), Value::Boolean(true));
+        assert_eq!(entry.get_header_mut().set("test.a.b", Value::Table(t)).err().unwrap()
+                   .err_type(), StoreErrorKind::HeaderPathSyntaxError);
+        assert!(store.update(entry).is_ok());
+    }
+
+    #[test]
+    fn test_header_max_depth_read_from_disk() {
+        use std::fs::File;
+        use std::io::Write;
+
+        use error::StoreErrorKind;
+        use super::Entry;
+
+        let deep = vec!["a"; 2 * DEFAULT_HEADER_MAX_DEPTH].join(".");
+        let text = format!("---\n[imag]\nversion = \"0.1.0\"\n[{}]\nb = true\n---\n", deep);
+        assert_eq!(Entry::from_str(PathBuf::from("test/deep~0.1.0"), &text[..]).err().unwrap()
+                   .err_type(), StoreErrorKind::MalformedEntry);
+
+        let (dir, store) = get_store_with_config(None);
+        let _ = store.create(PathBuf::from("test/deep~0.1.0")).unwrap();
+        write!(File::create(dir.path().join("test/deep~0.1.0")).unwrap(), "{}", text).unwrap();
+        assert_eq!(store.retrieve(PathBuf::from("test/deep~0.1.0")).err().unwrap().err_type(),
+                   StoreErrorKind::MalformedEntry);
+        assert_eq!(store.retrieve_copy(PathBuf::from("test/deep~0.1.0")).err().unwrap()
+                   .err_type(), StoreErrorKind::MalformedEntry);
+
+        // The depth of the store configuration is used
+        let text = "---\n[imag]\nversion = \"0.1.0\"\n[test.a]\nb = true\n---\n";
+        write!(File::create(dir.path().join("test/deep~0.1.0")).unwrap(), "{}", text).unwrap();
+        assert!(store.retrieve_copy(PathBuf::from("test/deep~0.1.0")).is_ok());
+        let config = get_store_config("header-max-depth = 2");
+        let store = Store::new(PathBuf::from(dir.path()), config).unwrap();
+        assert_eq!(store.retrieve_copy(PathBuf::from("test/deep~0.1.0")).err().unwrap()
+                   .err_type(), StoreErrorKind::MalformedEntry);
+    }
 
-                    /*
-                     * Fail if there is no array here
-                     */
-                    _ => return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
-                }
-            },
+    #[test]
+    fn test_entries_on_day() {
+        let (dir, store) = get_store_with_config(None);
+        let created = [
+            ("test/a~0.1.0", Some(Value::Datetime(String::from("2014-03-05T10:00:00Z")))),
+            ("test/b~0.1.0", Some(Value::String(String::from("2015-03-05")))),
+            ("test/c~0.1.0", Some(Value::Datetime(String::from("2016-03-05T23:59:59Z")))),
+            ("test/d~0.1.0", Some(Value::Datetime(String::from("2016-03-06T00:00:00Z")))),
+            ("test/e~0.1.0", Some(Value::String(String::from("not a date")))),
+            ("test/f~0.1.0", None),
+        ];
+
+        for &(id, ref date) in created.iter() {
+            let mut entry = store.create(PathBuf::from(id)).unwrap();
+            if let &Some(ref date) = date {
+                assert!(entry.get_header_mut().set("imag.created", date.clone()).is_ok());
+            }
         }
 
-        Ok(true)
+        assert_eq!(store.entries_on_day(3, 5).unwrap(),
+                   vec![dir.path().join("test/a~0.1.0"),
+                        dir.path().join("test/b~0.1.0"),
+                        dir.path().join("test/c~0.1.0")]);
+        assert_eq!(store.entries_on_day(3, 6).unwrap(), vec![dir.path().join("test/d~0.1.0")]);
+        assert!(store.entries_on_day(12, 24).unwrap().is_empty());
     }
 
-    /**
-     * Set a header field by a string-spec
-     *
-     * ```ignore
-     *  set("something.in.a.field", Boolean(true));
-     * ```
-     *
-     * Sets a Boolean in the section "something" -> "in" -> "a" -> "field"
-     * A JSON equivalent would be
-     *
-     *  {
-     *      something: {
-     *          in: {
-     *              a: {
-     *                  field: true
-     *              }
-     *          }
-     *      }
-     *  }
-     *
-     * If there is already a value at this place, this value will be overridden and the old value
-     * will be returned
-     */
-    pub fn set(&mut self, spec: &str, v: Value) -> Result<Option<Value>> {
-        self.set_with_sep(spec, '.', v)
+    #[test]
+    fn test_wrap_content_on_update() {
+        let (dir, store) = get_store_with_config(get_store_config("wrap-content-at = 20"));
+        let id = PathBuf::from("test/wrapped~0.1.0");
+        let path = dir.path().join("test/wrapped~0.1.0");
+
+        let mut entry = store.create(id.clone()).unwrap();
+        *entry.get_content_mut() = String::from("# Heading\n\n\
+                                                 This is a rather long paragraph which has to \
+                                                 be wrapped.\n");
+        store.update(entry).unwrap();
+
+        let written = read_file(&path);
+        assert!(written.ends_with("# Heading\n\nThis is a rather\nlong paragraph which\n\
+                                   has to be wrapped.\n"));
+
+        let entry = store.retrieve(id).unwrap();
+        store.update(entry).unwrap();
+        assert_eq!(read_file(&path), written);
     }
 
-    pub fn set_with_sep(&mut self, spec: &str, sep: char, v: Value) -> Result<Option<Value>> {
-        let tokens = EntryHeader::tokenize(spec, sep);
-        if tokens.is_err() { // return parser error if any
-            return Err(tokens.err().unwrap());
-        }
-        let tokens = tokens.unwrap();
-        debug!("tokens = {:?}", tokens);
+    #[test]
+    fn test_external_modification_is_detected() {
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use error::StoreErrorKind;
+        use super::Entry;
 
-        let destination = tokens.iter().last();
-        if destination.is_none() {
-            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
-        }
-        let destination = destination.unwrap();
-        debug!("destination = {:?}", destination);
+        let (dir, store) = get_store_with_config(None);
+        let id = PathBuf::from("test/external~0.1.0");
+        let path = dir.path().join("test/external~0.1.0");
 
-        let path_to_dest = tokens[..(tokens.len() - 1)].into(); // N - 1 tokens
-        let value = EntryHeader::walk_header(&mut self.header, path_to_dest); // walk N-1 tokens
-        if value.is_err() {
-            return Err(value.err().unwrap());
+        {
+            let mut entry = store.create(id.clone()).unwrap();
+            *entry.get_content_mut() = String::from("written by imag");
         }
-        let mut value = value.unwrap();
-        debug!("walked value = {:?}", value);
 
-        match destination {
-            &Token::Key(ref s) => { // if the destination shall be an map key->value
-                match value {
-                    /*
-                     * Put it in there if we have a map
-                     */
-                    &mut Value::Table(ref mut t) => {
-                        debug!("Matched Key->Table");
-                        return Ok(t.insert(s.clone(), v));
-                    }
+        let mut entry = store.retrieve(id.clone()).unwrap();
+        let external = {
+            let mut e = Entry::new(path.clone());
+            *e.get_content_mut() = String::from("written by another program");
+            e.to_str()
+        };
+        File::create(&path).unwrap().write_all(external.as_bytes()).unwrap();
 
-                    /*
-                     * Fail if there is no map here
-                     */
-                    _ => {
-                        debug!("Matched Key->NON-Table");
-                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
-                    }
-                }
-            },
+        *entry.get_content_mut() = String::from("written by imag again");
+        assert_eq!(store.update(entry).err().unwrap().err_type(),
+                   StoreErrorKind::ConcurrentModification);
 
-            &Token::Index(i) => { // if the destination shall be an array
-                match value {
+        let mut s = String::new();
+        File::open(&path).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, external);
 
-                    /*
-                     * Put it in there if we have an array
-                     */
-                    &mut Value::Array(ref mut a) => {
-                        debug!("Matched Index->Array");
-                        a.push(v); // push to the end of the array
+        // Entries which were not modified externally are still written
+        let mut entry = store.retrieve(PathBuf::from("test/other~0.1.0")).unwrap();
+        *entry.get_content_mut() = String::from("not modified");
+        assert!(store.update(entry).is_ok());
+    }
 
-                        // if the index is inside the array, we swap-remove the element at this
-                        // index
-                        if a.len() > i {
-                            debug!("Swap-Removing in Array {:?}[{:?}] <- {:?}", a, i, a[a.len()-1]);
-                            return Ok(Some(a.swap_remove(i)));
-                        }
+    #[test]
+    fn test_external_modification_is_detected_after_passive_read() {
+        use std::fs::File;
+        use std::io::Write;
+        use error::StoreErrorKind;
+        use super::Entry;
 
-                        debug!("Appended");
-                        return Ok(None);
-                    },
+        let (dir, store) = get_store_with_config(get_store_config("index = true"));
+        let id = PathBuf::from("test/external~0.1.0");
+        let path = dir.path().join("test/external~0.1.0");
 
-                    /*
-                     * Fail if there is no array here
-                     */
-                    _ => {
-                        debug!("Matched Index->NON-Array");
-                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
-                    },
-                }
-            },
+        {
+            let mut entry = store.create(id.clone()).unwrap();
+            *entry.get_content_mut() = String::from("written by imag");
         }
 
-        Ok(None)
+        let mut entry = store.retrieve(id.clone()).unwrap();
+        let mut external = Entry::new(path.clone());
+        *external.get_content_mut() = String::from("written by another program");
+        File::create(&path).unwrap().write_all(external.to_str().as_bytes()).unwrap();
+
+        // Reading the borrowed entry from disk must not hide the external change
+        store.rebuild_index().unwrap();
+
+        *entry.get_content_mut() = String::from("written by imag again");
+        assert_eq!(store.update(entry).err().unwrap().err_type(),
+                   StoreErrorKind::ConcurrentModification);
     }
 
-    /**
-     * Read a header field by a string-spec
-     *
-     * ```ignore
-     *  let value = read("something.in.a.field");
-     * ```
-     *
-     * Reads a Value in the section "something" -> "in" -> "a" -> "field"
-     * A JSON equivalent would be
-     *
-     *  {
-     *      something: {
-     *          in: {
-     *              a: {
-     *                  field: true
-     *              }
-     *          }
-     *      }
-     *  }
-     *
-     * If there is no a value at this place, None will be returned. This also holds true for Arrays
-     * which are accessed at an index which is not yet there, even if the accessed index is much
-     * larger than the array length.
-     */
-    pub fn read(&self, spec: &str) -> Result<Option<Value>> {
-        self.read_with_sep(spec, '.')
+    #[test]
+    fn test_aliases() {
+        let (dir, store) = get_store_with_config(get_store_config("
+            [aliases]
+            configured = \"test/configured~0.1.0\"
+        "));
+        let id = dir.path().join("test/aliased~0.1.0");
+        let _ = store.create(id.clone()).unwrap();
+
+        assert_eq!(store.resolve_alias("a"), None);
+
+        store.set_alias("a", PathBuf::from("test/aliased~0.1.0")).unwrap();
+        assert_eq!(store.resolve_alias("a"), Some(id.clone()));
+        assert_eq!(store.resolve("@a").unwrap(), vec![id.clone()]);
+
+        assert_eq!(store.resolve_alias("configured"),
+                   Some(dir.path().join("test/configured~0.1.0")));
+        store.set_alias("configured", id.clone()).unwrap();
+        assert_eq!(store.resolve_alias("configured"), Some(id.clone()));
+
+        assert!(store.remove_alias("a").unwrap());
+        assert!(!store.remove_alias("a").unwrap());
+        assert_eq!(store.resolve_alias("a"), None);
+        assert!(store.resolve("@a").unwrap().is_empty());
+
+        // The aliases are no entry
+        assert!(dir.path().join(".imag_aliases").is_file());
+        let ids : Vec<StoreId> = store.retrieve_all().unwrap().collect();
+        assert_eq!(ids, vec![id]);
+        assert!(store.resolve("a").unwrap().is_empty());
     }
 
-    pub fn read_with_sep(&self, spec: &str, splitchr: char) -> Result<Option<Value>> {
-        let tokens = EntryHeader::tokenize(spec, splitchr);
-        if tokens.is_err() { // return parser error if any
-            return Err(tokens.err().unwrap());
-        }
-        let tokens = tokens.unwrap();
+    #[test]
+    fn test_progress_callbacks() {
+        use std::cell::RefCell;
 
-        let mut header_clone = self.header.clone(); // we clone as READing is simpler this way
-        let value = EntryHeader::walk_header(&mut header_clone, tokens); // walk N-1 tokens
-        if value.is_err() {
-            let e = value.err().unwrap();
-            return match e.err_type() {
-                // We cannot find the header key, as there is no path to it
-                StoreErrorKind::HeaderKeyNotFound => Ok(None),
-                _ => Err(e),
-            };
+        let (_dir, store) = get_store_with_config(get_store_config("index = true"));
+        for i in 0..5 {
+            let _ = store.create(PathBuf::from(format!("test/{}~0.1.0", i))).unwrap();
         }
-        Ok(Some(value.unwrap().clone()))
+
+        let calls = RefCell::new(vec![]);
+        let changed = store.update_matching_with_progress(|e| e.get_location().ends_with("2~0.1.0"),
+            |e| *e.get_content_mut() = String::from("changed"),
+            |processed, total| calls.borrow_mut().push((processed, total)));
+        assert_eq!(changed.unwrap(), 1);
+        let expected : Vec<(usize, Option<usize>)> = (1..6).map(|i| (i, Some(5))).collect();
+        assert_eq!(*calls.borrow(), expected);
+
+        calls.borrow_mut().clear();
+        store.rebuild_index_with_progress(|processed, total| {
+            calls.borrow_mut().push((processed, total))
+        }).unwrap();
+        assert_eq!(*calls.borrow(), expected);
     }
 
-    pub fn delete(&mut self, spec: &str) -> Result<Option<Value>> {
-        let tokens = EntryHeader::tokenize(spec, '.');
-        if tokens.is_err() { // return parser error if any
-            return Err(tokens.err().unwrap());
-        }
-        let tokens = tokens.unwrap();
+    #[test]
+    fn test_separate_content() {
+        let (dir, store) = get_store_with_config(get_store_config("separate-content = true"));
+        let id = PathBuf::from("test/split~0.1.0");
+        let path = dir.path().join("test/split~0.1.0");
+        let body_path = dir.path().join("test/.split~0.1.0.body");
 
-        let destination = tokens.iter().last();
-        if destination.is_none() {
-            return Err(StoreError::new(StoreErrorKind::HeaderPathSyntaxError, None));
+        {
+            let mut entry = store.create(id.clone()).unwrap();
+            *entry.get_content_mut() = String::from("The body of the entry\n");
         }
-        let destination = destination.unwrap();
-        debug!("destination = {:?}", destination);
 
-        let path_to_dest = tokens[..(tokens.len() - 1)].into(); // N - 1 tokens
-        let value = EntryHeader::walk_header(&mut self.header, path_to_dest); // walk N-1 tokens
-        if value.is_err() {
-            return Err(value.err().unwrap());
-        }
-        let mut value = value.unwrap();
-        debug!("walked value = {:?}", value);
+        assert!(!read_file(&path).contains("The body of the entry"));
+        assert!(read_file(&path).contains("body = \".split~0.1.0.body\""));
+        assert_eq!(read_file(&body_path), "The body of the entry\n");
 
-        match destination {
-            &Token::Key(ref s) => { // if the destination shall be an map key->value
-                match value {
-                    &mut Value::Table(ref mut t) => {
-                        debug!("Matched Key->Table, removing {:?}", s);
-                        return Ok(t.remove(s));
-                    },
-                    _ => {
-                        debug!("Matched Key->NON-Table");
-                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
-                    }
-                }
-            },
+        let entry = store.retrieve_copy(id.clone()).unwrap();
+        assert_eq!(entry.get_content(), "The body of the entry\n");
+        assert_eq!(entry.get_header().read("imag.body").unwrap(), None);
+        assert_eq!(store.retrieve_all().unwrap().collect::<Vec<_>>(), vec![path.clone()]);
 
-            &Token::Index(i) => { // if the destination shall be an array
-                match value {
-                    &mut Value::Array(ref mut a) => {
-                        // if the index is inside the array, we swap-remove the element at this
-                        // index
-                        if a.len() > i {
-                            debug!("Removing in Array {:?}[{:?}]", a, i);
-                            return Ok(Some(a.remove(i)));
-                        } else {
-                            return Ok(None);
-                        }
-                    },
-                    _ => {
-                        debug!("Matched Index->NON-Array");
-                        return Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None));
-                    },
-                }
-            },
+        // A store which does not separate the content reads the entry and writes it combined
+        drop(store);
+        let store = Store::new(PathBuf::from(dir.path()), get_store_config("")).unwrap();
+        {
+            let entry = store.retrieve(id.clone()).unwrap();
+            assert_eq!(entry.get_content(), "The body of the entry\n");
         }
+        assert!(read_file(&path).ends_with("---\nThe body of the entry\n"));
+        assert!(!body_path.exists());
 
-        Ok(None)
+        let entry = store.retrieve_copy(id.clone()).unwrap();
+        assert_eq!(entry.get_content(), "The body of the entry\n");
     }
 
-    fn tokenize(spec: &str, splitchr: char) -> Result<Vec<Token>> {
-        use std::str::FromStr;
+    #[test]
+    fn test_separate_content_move_and_delete() {
+        let (dir, store) = get_store_with_config(get_store_config("separate-content = true"));
+        {
+            let mut entry = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("content");
+        }
 
-        spec.split(splitchr)
-            .map(|s| {
-                usize::from_str(s)
-                    .map(Token::Index)
-                    .or_else(|_| Ok(Token::Key(String::from(s))))
-            })
-            .collect()
+        store.move_by_id(PathBuf::from("test/a~0.1.0"), PathBuf::from("test/b~0.1.0")).unwrap();
+        assert!(!dir.path().join("test/.a~0.1.0.body").exists());
+        assert!(dir.path().join("test/.b~0.1.0.body").exists());
+        let entry = store.retrieve_copy(PathBuf::from("test/b~0.1.0")).unwrap();
+        assert_eq!(entry.get_content(), "content");
+
+        store.delete(PathBuf::from("test/b~0.1.0")).unwrap();
+        assert!(!dir.path().join("test/.b~0.1.0.body").exists());
     }
 
-    fn walk_header(v: &mut Value, tokens: Vec<Token>) -> Result<&mut Value> {
-        use std::vec::IntoIter;
+    #[test]
+    fn test_create_fails_for_entry_on_disk() {
+        use std::fs::{File, create_dir_all};
+        use std::io::{Read, Write};
+        use error::StoreErrorKind;
+
+        let (dir, store) = get_store_with_config(None);
+        let path = dir.path().join("test/on-disk~0.1.0");
+        create_dir_all(path.parent().unwrap()).unwrap();
+        File::create(&path).unwrap().write_all(b"created by another process").unwrap();
+
+        let res = store.create(PathBuf::from("test/on-disk~0.1.0"));
+        assert_eq!(res.err().unwrap().err_type(), StoreErrorKind::EntryAlreadyExists);
+
+        let mut s = String::new();
+        File::open(&path).unwrap().read_to_string(&mut s).unwrap();
+        assert_eq!(s, "created by another process");
+
+        // The failed creation did not leave anything behind
+        assert!(store.create(PathBuf::from("test/on-disk~0.1.0")).is_err());
+        assert!(store.create(PathBuf::from("test/not-on-disk~0.1.0")).is_ok());
+    }
 
-        fn walk_iter<'a>(v: Result<&'a mut Value>, i: &mut IntoIter<Token>) -> Result<&'a mut Value> {
-            let next = i.next();
-            v.and_then(move |value| {
-                if let Some(token) = next {
-                    walk_iter(EntryHeader::extract(value, &token), i)
-                } else {
-                    Ok(value)
-                }
-            })
-        }
+    #[test]
+    fn test_rename_field() {
+        use error::StoreErrorKind;
 
-        walk_iter(Ok(v), &mut tokens.into_iter())
-    }
+        let mut header = EntryHeader::from(match create_header() {
+            Value::Table(t) => t,
+            _ => unreachable!(),
+        });
+        let moved = header.read("a").unwrap();
+        assert!(header.rename_field("a", "imag.content.moved", false).unwrap());
+        assert_eq!(header.read("a").unwrap(), None);
+        assert_eq!(header.read("imag.content.moved").unwrap(), moved);
 
-    fn extract_from_table<'a>(v: &'a mut Value, s: &String) -> Result<&'a mut Value> {
-        match v {
-            &mut Value::Table(ref mut t) => {
-                t.get_mut(&s[..])
-                    .ok_or(StoreError::new(StoreErrorKind::HeaderKeyNotFound, None))
-            },
-            _ => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
-        }
-    }
+        assert!(!header.rename_field("a", "b", false).unwrap());
 
-    fn extract_from_array(v: &mut Value, i: usize) -> Result<&mut Value> {
-        match v {
-            &mut Value::Array(ref mut a) => {
-                if a.len() < i {
-                    Err(StoreError::new(StoreErrorKind::HeaderKeyNotFound, None))
-                } else {
-                    Ok(&mut a[i])
-                }
-            },
-            _ => Err(StoreError::new(StoreErrorKind::HeaderPathTypeFailure, None)),
-        }
+        let b = header.read("b").unwrap();
+        assert_eq!(header.rename_field("c", "b", false).err().unwrap().err_type(),
+                   StoreErrorKind::HeaderKeyExists);
+        assert_eq!(header.read("b").unwrap(), b);
+        assert!(header.read("c").unwrap().is_some());
+
+        let c = header.read("c").unwrap();
+        assert!(header.rename_field("c", "b", true).unwrap());
+        assert_eq!(header.read("b").unwrap(), c);
+
+        assert_eq!(header.rename_field("d", "d.inner", false).err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathSyntaxError);
     }
 
-    fn extract<'a>(v: &'a mut Value, token: &Token) -> Result<&'a mut Value> {
-        match token {
-            &Token::Key(ref s)  => EntryHeader::extract_from_table(v, s),
-            &Token::Index(i)    => EntryHeader::extract_from_array(v, i),
+    #[test]
+    fn test_rename_header_field() {
+        let (_dir, store) = get_store_with_config(None);
+        for (i, url) in [Some("http://a"), None, Some("http://c")].iter().enumerate() {
+            let mut entry = store.create(PathBuf::from(format!("test/{}~0.1.0", i))).unwrap();
+            if let &Some(url) = url {
+                entry.get_header_mut().insert("test", Value::Table(BTreeMap::new())).unwrap();
+                entry.get_header_mut().set("test.url", Value::String(String::from(url))).unwrap();
+            }
         }
-    }
 
-}
+        assert_eq!(store.rename_header_field("test.url", "imag.content.url", false).unwrap(), 2);
+        for (i, url) in [Some("http://a"), None, Some("http://c")].iter().enumerate() {
+            let entry = store.retrieve_copy(PathBuf::from(format!("test/{}~0.1.0", i))).unwrap();
+            assert_eq!(entry.get_header().read("test.url").unwrap(), None);
+            assert_eq!(entry.get_header().read_string("imag.content.url").unwrap(),
+                       url.map(String::from));
+        }
 
-impl Into<Table> for EntryHeader {
+        assert_eq!(store.rename_header_field("test.url", "imag.content.url", false).unwrap(), 0);
+    }
 
-    fn into(self) -> Table {
-        match self.header {
-            Value::Table(t) => t,
-            _ => panic!("EntryHeader is not a table!"),
-        }
+    #[test]
+    fn test_retrieve_for_unused_module() {
+        let (_dir, store) = get_store_with_config(None);
+        let _ = store.create(PathBuf::from("used/a~0.1.0")).unwrap();
+
+        assert_eq!(store.retrieve_for_module("unused").unwrap().count(), 0);
+        assert_eq!(store.retrieve_for_module("[unused").unwrap().count(), 0);
+        assert_eq!(store.entries_for_module("unused").unwrap().count(), 0);
+        assert_eq!(store.retrieve_for_module("used").unwrap().count(), 1);
     }
 
-}
+    #[test]
+    fn test_entry_hashes() {
+        use super::Entry;
+        use storeid::StoreId;
 
-impl From<Table> for EntryHeader {
+        let (_dir, store) = get_store_with_config(None);
+        let id = PathBuf::from("test/hashed~0.1.0");
 
-    fn from(t: Table) -> EntryHeader {
-        EntryHeader { header: Value::Table(t) }
+        let mut entry = store.create(id.clone()).unwrap();
+        *entry.get_content_mut() = String::from("content");
+        let content_hash = entry.content_hash();
+        let header_hash = entry.header_hash();
+        store.update(entry).unwrap();
+
+        let mut entry = store.retrieve(id.clone()).unwrap();
+        assert_eq!(entry.content_hash(), content_hash);
+        assert_eq!(entry.header_hash(), header_hash);
+
+        entry.get_header_mut().set("imag.last_modified", Value::String(String::from("now")))
+            .unwrap();
+        assert_eq!(entry.header_hash(), header_hash);
+
+        entry.get_header_mut().set("imag.version", Value::String(String::from("0.2.0")))
+            .unwrap();
+        assert!(entry.header_hash() != header_hash);
+
+        *entry.get_content_mut() = String::from("changed content");
+        assert!(entry.content_hash() != content_hash);
+
+        let other = Entry::new(StoreId::from("test/other~0.1.0"));
+        assert_eq!(other.content_hash(), Entry::new(id).content_hash());
     }
 
-}
+    #[test]
+    fn test_retrieve_with_source() {
+        use std::fs::File;
+        use std::io::Write;
 
-fn build_default_header() -> Value { // BTreeMap<String, Value>
-    let mut m = BTreeMap::new();
+        use super::EntrySource;
 
-    m.insert(String::from("imag"), {
-        let mut imag_map = BTreeMap::<String, Value>::new();
+        let (dir, store) = get_store_with_config(None);
 
-        imag_map.insert(String::from("version"), Value::String(String::from(version!())));
-        imag_map.insert(String::from("links"), Value::Array(vec![]));
+        let id = PathBuf::from("test/source~0.1.0");
+        let (entry, source) = store.retrieve_with_source(id.clone()).unwrap();
+        assert_eq!(source, EntrySource::New);
+        store.update(entry).unwrap();
 
-        Value::Table(imag_map)
-    });
+        let (entry, source) = store.retrieve_with_source(id.clone()).unwrap();
+        assert_eq!(source, EntrySource::Cache);
+        drop(entry);
 
-    Value::Table(m)
-}
-fn verify_header(t: &Table) -> Result<()> {
-    if !has_main_section(t) {
-        Err(StoreError::from(ParserError::new(ParserErrorKind::MissingMainSection, None)))
-    } else if !has_imag_version_in_main_section(t) {
-        Err(StoreError::from(ParserError::new(ParserErrorKind::MissingVersionInfo, None)))
-    } else if !has_only_tables(t) {
-        debug!("Could not verify that it only has tables in its base table");
-        Err(StoreError::from(ParserError::new(ParserErrorKind::NonTableInBaseTable, None)))
-    } else {
-        Ok(())
+        let (_, source) = store.retrieve_with_source(id).unwrap();
+        assert_eq!(source, EntrySource::Cache);
+
+        let mut file = File::create(dir.path().join("test/disk~0.1.0")).unwrap();
+        file.write_all(b"---\n[imag]\nversion = \"0.1.0\"\n---\ncontent").unwrap();
+        let (entry, source) = store.retrieve_with_source(PathBuf::from("test/disk~0.1.0")).unwrap();
+        assert_eq!(source, EntrySource::Disk);
+        assert_eq!(entry.get_content(), "content");
     }
-}
 
-fn verify_header_consistency(t: Table) -> EntryResult<Table> {
-    if let Err(e) = verify_header(&t) {
-        Err(ParserError::new(ParserErrorKind::HeaderInconsistency, Some(Box::new(e))))
-    } else {
-        Ok(t)
+    #[test]
+    fn test_history() {
+        let (_dir, store) = get_store_with_config(get_store_config("history-length = 3"));
+        let id = PathBuf::from("test/history~0.1.0");
+
+        let entry = store.create(id.clone()).unwrap();
+        store.update(entry).unwrap();
+        for i in 0..4 {
+            let entry = store.retrieve(id.clone()).unwrap();
+            store.update_with_note(entry, &format!("edit {}", i)).unwrap();
+        }
+
+        let entry = store.retrieve(id.clone()).unwrap();
+        let history = entry.get_header().read_array("imag.history").unwrap().unwrap();
+        let notes : Vec<Option<Value>> = history.iter()
+            .map(|item| match item {
+                &Value::Table(ref t) => {
+                    assert!(t.contains_key("timestamp"));
+                    t.get("note").cloned()
+                },
+                _ => panic!("History item is no table"),
+            })
+            .collect();
+        assert_eq!(notes, vec![Some(Value::String(String::from("edit 1"))),
+                               Some(Value::String(String::from("edit 2"))),
+                               Some(Value::String(String::from("edit 3")))]);
+        drop(entry);
+
+        let (_dir, store) = get_store_with_config(None);
+        let entry = store.create(id.clone()).unwrap();
+        store.update_with_note(entry, "edit").unwrap();
+        let entry = store.retrieve(id).unwrap();
+        assert_eq!(entry.get_header().read("imag.history").unwrap(), None);
     }
-}
 
-fn has_only_tables(t: &Table) -> bool {
-    debug!("Verifying that table has only tables");
-    t.iter().all(|(_, x)| if let &Value::Table(_) = x { true } else { false })
-}
+    #[test]
+    fn test_read_datetime() {
+        use chrono::{DateTime, Utc};
 
-fn has_main_section(t: &Table) -> bool {
-    t.contains_key("imag") &&
-        match t.get("imag") {
-            Some(&Value::Table(_)) => true,
-            Some(_)                => false,
-            None                   => false,
+        use super::Entry;
+        use error::StoreErrorKind;
+
+        let text = "---\n[imag]\nversion = \"0.1.0\"\n\
+                    [test]\nnative = 2016-05-01T12:00:00Z\n\
+                    string = \"2016-05-01T14:00:00+02:00\"\n\
+                    invalid = \"yesterday\"\nint = 1\n---\n";
+        let entry = Entry::from_str(PathBuf::from("test~0.1.0"), text).unwrap();
+        let header = entry.get_header();
+        let expected = DateTime::parse_from_rfc3339("2016-05-01T12:00:00Z").ok()
+            .map(|d| d.with_timezone(&Utc));
+
+        assert_eq!(header.read_datetime("test.native").unwrap(), expected);
+        assert_eq!(header.read_datetime("test.string").unwrap(), expected);
+        assert_eq!(header.read_datetime("test.missing").unwrap(), None);
+        assert_eq!(header.read_datetime("test.invalid").err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+        assert_eq!(header.read_datetime("test.int").err().unwrap().err_type(),
+                   StoreErrorKind::HeaderPathTypeFailure);
+    }
+
+    #[test]
+    fn test_resolve() {
+        let (dir, store) = get_store_with_config(None);
+        for id in ["notes/shopping~0.1.0", "notes/shop-hours~0.1.0", "notes/todo~0.1.0"].iter() {
+            let _ = store.create(PathBuf::from(id)).unwrap();
         }
-}
 
-fn has_imag_version_in_main_section(t: &Table) -> bool {
-    use semver::Version;
+        assert_eq!(store.resolve("notes/todo").unwrap(),
+                   vec![dir.path().join("notes/todo~0.1.0")]);
+        assert_eq!(store.resolve("/notes/to").unwrap(),
+                   vec![dir.path().join("notes/todo~0.1.0")]);
+        assert_eq!(store.resolve("notes/shopping").unwrap(),
+                   vec![dir.path().join("notes/shopping~0.1.0")]);
 
-    match t.get("imag").unwrap() {
-        &Value::Table(ref sec) => {
-            sec.get("version")
-                .and_then(|v| {
-                    match v {
-                        &Value::String(ref s) => {
-                            Some(Version::parse(&s[..]).is_ok())
-                        },
-                        _                 => Some(false),
-                    }
-                })
-            .unwrap_or(false)
-        }
-        _                  => false,
+        assert_eq!(store.resolve("notes/shop").unwrap(),
+                   vec![dir.path().join("notes/shop-hours~0.1.0"),
+                        dir.path().join("notes/shopping~0.1.0")]);
+
+        assert!(store.resolve("notes/nothing").unwrap().is_empty());
     }
-}
 
-/**
- * An Entry of the store
- *
- * Contains location, header and content part.
- */
-#[derive(Debug, Clone)]
-pub struct Entry {
-    location: StoreId,
-    header: EntryHeader,
-    content: EntryContent,
-}
+    #[test]
+    fn test_ids_escaping_the_store_are_rejected() {
+        use error::StoreErrorKind;
 
-impl Entry {
+        let (dir, store) = get_store_with_config(None);
 
-    pub fn new(loc: StoreId) -> Entry {
-        Entry {
-            location: loc,
-            header: EntryHeader::new(),
-            content: EntryContent::new()
+        for id in ["../outside~0.1.0", "test/../../outside~0.1.0", "/etc/passwd"].iter() {
+            let res = store.create(PathBuf::from(id));
+            assert_eq!(res.err().unwrap().err_type(), StoreErrorKind::InvalidStoreId);
+            assert!(!store.exists(PathBuf::from(id)));
         }
-    }
+        assert!(!dir.path().parent().unwrap().join("outside~0.1.0").exists());
 
-    pub fn from_file(loc: StoreId, file: &mut File) -> Result<Entry> {
-        let text = {
-            use std::io::Read;
-            let mut s = String::new();
-            try!(file.read_to_string(&mut s));
-            s
-        };
-        Self::from_str(loc, &text[..])
+        {
+            let entry = store.create(PathBuf::from("test/nested/inside~0.1.0")).unwrap();
+            assert_eq!(entry.get_location(), &dir.path().join("test/nested/inside~0.1.0"));
+        }
+        assert!(store.exists(PathBuf::from("test/nested/inside~0.1.0")));
+        assert!(store.exists(dir.path().join("test/nested/inside~0.1.0")));
     }
 
-    pub fn from_str(loc: StoreId, s: &str) -> Result<Entry> {
-        debug!("Building entry from string");
-        lazy_static! {
-            static ref RE: Regex = Regex::new(r"(?smx)
-                ^---$
-                (?P<header>.*) # Header
-                ^---$\n
-                (?P<content>.*) # Content
-            ").unwrap();
+    #[test]
+    fn test_hierarchical_layout_is_default() {
+        let (dir, store) = get_store_with_config(get_store_config(""));
+        let id = PathBuf::from("diary/2016/01/02-10:30~0.1.0");
+
+        {
+            let _ = store.create(id.clone()).unwrap();
         }
 
-        let matches = RE.captures(s);
+        let mut on_disk = PathBuf::from(dir.path());
+        on_disk.push(&id);
+        assert!(on_disk.is_file());
+    }
 
-        if matches.is_none() {
-            return Err(StoreError::new(StoreErrorKind::MalformedEntry, None));
+    #[test]
+    fn test_update_matching() {
+        let (_dir, store) = get_store_with_config(None);
+
+        for &(name, content) in [("a", "archive me"), ("b", "keep me"), ("c", "archive me too")].iter() {
+            let mut entry = store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            *entry.get_content_mut() = String::from(content);
+            store.update(entry).unwrap();
         }
 
-        let matches = matches.unwrap();
+        let count = store.update_matching(|e| e.get_content().starts_with("archive"), |e| {
+            e.get_header_mut().set("imag.archived", Value::Boolean(true)).unwrap();
+        });
+        assert_eq!(count.unwrap(), 2);
 
-        let header = matches.name("header");
-        let content = matches.name("content").unwrap_or("");
+        for &(name, archived) in [("a", true), ("b", false), ("c", true)].iter() {
+            let entry = store.retrieve_copy(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            let flag = entry.get_header().read("imag.archived").unwrap();
+            assert_eq!(flag.is_some(), archived);
+        }
+    }
 
-        if header.is_none() {
-            return Err(StoreError::new(StoreErrorKind::MalformedEntry, None));
+    #[test]
+    fn test_update_matching_skips_borrowed_entries() {
+        let (_dir, store) = get_store_with_config(None);
+
+        for name in ["a", "b"].iter() {
+            let entry = store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            store.update(entry).unwrap();
         }
 
-        debug!("Header and content found. Yay! Building Entry object now");
-        Ok(Entry {
-            location: loc,
-            header: try!(EntryHeader::parse(header.unwrap())),
-            content: content.into(),
-        })
+        let borrowed = store.retrieve(PathBuf::from("test/a~0.1.0")).unwrap();
+        let count = store.update_matching(|_| true, |e| {
+            e.get_header_mut().set("imag.archived", Value::Boolean(true)).unwrap();
+        });
+        assert_eq!(count.unwrap(), 1);
+        assert!(borrowed.get_header().read("imag.archived").unwrap().is_none());
     }
 
-    pub fn to_str(&self) -> String {
-        format!("---{header}---\n{content}",
-                header  = ::toml::encode_str(&self.header.header),
-                content = self.content)
-    }
+    #[test]
+    fn test_update_id() {
+        let (dir, store) = get_store_with_config(None);
 
-    pub fn get_location(&self) -> &StoreId {
-        &self.location
-    }
+        {
+            let mut entry = store.create(PathBuf::from("test/old~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("moved content");
+            store.update(entry).unwrap();
+        }
 
-    pub fn get_header(&self) -> &EntryHeader {
-        &self.header
-    }
+        {
+            let entry = store.retrieve(PathBuf::from("test/old~0.1.0")).unwrap();
+            let entry = store.update_id(entry, PathBuf::from("test/new~0.1.0")).unwrap();
+            assert_eq!(entry.get_location(), &dir.path().join("test/new~0.1.0"));
+            assert_eq!(entry.get_content(), "moved content");
+        }
 
-    pub fn get_header_mut(&mut self) -> &mut EntryHeader {
-        &mut self.header
-    }
+        assert!(!dir.path().join("test/old~0.1.0").exists());
+        assert!(dir.path().join("test/new~0.1.0").is_file());
 
-    pub fn get_content(&self) -> &EntryContent {
-        &self.content
+        let entry = store.retrieve_copy(PathBuf::from("test/new~0.1.0")).unwrap();
+        assert_eq!(entry.get_content(), "moved content");
     }
 
-    pub fn get_content_mut(&mut self) -> &mut EntryContent {
-        &mut self.content
-    }
+    #[test]
+    fn test_update_id_to_existing_entry_fails() {
+        let (dir, store) = get_store_with_config(None);
 
-    pub fn verify(&self) -> Result<()> {
-        self.header.verify()
+        for name in ["a", "b"].iter() {
+            let entry = store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            store.update(entry).unwrap();
+        }
+
+        let entry = store.retrieve(PathBuf::from("test/a~0.1.0")).unwrap();
+        assert!(store.update_id(entry, PathBuf::from("test/b~0.1.0")).is_err());
+        assert!(dir.path().join("test/a~0.1.0").is_file());
     }
 
-}
+    mod counting_hook {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
+        use toml::Value;
 
-#[cfg(test)]
-mod test {
-    extern crate env_logger;
+        use hook::Hook;
+        use hook::accessor::{HookDataAccessor, HookDataAccessorProvider, StoreIdAccessor};
+        use hook::error::{HookError, HookErrorKind};
+        use hook::result::HookResult;
+        use storeid::StoreId;
 
-    use std::collections::BTreeMap;
-    use super::EntryHeader;
-    use super::Token;
+        /// Hook for StoreId positions which counts how often it was executed
+        #[derive(Debug)]
+        pub struct CountingHook(pub Arc<AtomicUsize>);
 
-    use toml::Value;
+        impl Hook for CountingHook {
+            fn name(&self) -> &'static str {
+                "counting"
+            }
 
-    #[test]
-    fn test_imag_section() {
-        use super::has_main_section;
+            fn set_config(&mut self, _: &Value) { }
+        }
 
-        let mut map = BTreeMap::new();
-        map.insert("imag".into(), Value::Table(BTreeMap::new()));
+        impl HookDataAccessorProvider for CountingHook {
+            fn accessor(&self) -> HookDataAccessor {
+                HookDataAccessor::StoreIdAccess(self)
+            }
+        }
 
-        assert!(has_main_section(&map));
-    }
+        impl StoreIdAccessor for CountingHook {
+            fn access(&self, _: &StoreId) -> HookResult<()> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
 
-    #[test]
-    fn test_imag_invalid_section_type() {
-        use super::has_main_section;
+        /// Hook for StoreId positions which fails for ids ending with the contained string
+        #[derive(Debug)]
+        pub struct FailingHook(pub &'static str);
 
-        let mut map = BTreeMap::new();
-        map.insert("imag".into(), Value::Boolean(false));
+        impl Hook for FailingHook {
+            fn name(&self) -> &'static str {
+                "failing"
+            }
 
-        assert!(!has_main_section(&map));
+            fn set_config(&mut self, _: &Value) { }
+        }
+
+        impl HookDataAccessorProvider for FailingHook {
+            fn accessor(&self) -> HookDataAccessor {
+                HookDataAccessor::StoreIdAccess(self)
+            }
+        }
+
+        impl StoreIdAccessor for FailingHook {
+            fn access(&self, id: &StoreId) -> HookResult<()> {
+                if id.to_str().map(|s| s.ends_with(self.0)).unwrap_or(false) {
+                    Err(HookError::new(HookErrorKind::HookExecutionError, None))
+                } else {
+                    Ok(())
+                }
+            }
+        }
     }
 
-    #[test]
-    fn test_imag_abscent_main_section() {
-        use super::has_main_section;
+    mod file_reading_hook {
+        use std::fs::File;
+        use std::io::Read;
+        use std::path::PathBuf;
+        use std::sync::{Arc, Mutex};
 
-        let mut map = BTreeMap::new();
-        map.insert("not_imag".into(), Value::Boolean(false));
+        use toml::Value;
 
-        assert!(!has_main_section(&map));
-    }
+        use hook::Hook;
+        use hook::accessor::{HookDataAccessor, HookDataAccessorProvider};
+        use hook::accessor::NonMutableHookDataAccessor;
+        use hook::result::HookResult;
+        use store::FileLockEntry;
 
-    #[test]
-    fn test_main_section_without_version() {
-        use super::has_imag_version_in_main_section;
+        /// Hook for file positions which reads the file at the contained path
+        #[derive(Debug)]
+        pub struct FileReadingHook(pub PathBuf, pub Arc<Mutex<String>>);
 
-        let mut map = BTreeMap::new();
-        map.insert("imag".into(), Value::Table(BTreeMap::new()));
+        impl Hook for FileReadingHook {
+            fn name(&self) -> &'static str {
+                "file_reading"
+            }
 
-        assert!(!has_imag_version_in_main_section(&map));
+            fn set_config(&mut self, _: &Value) { }
+        }
+
+        impl HookDataAccessorProvider for FileReadingHook {
+            fn accessor(&self) -> HookDataAccessor {
+                HookDataAccessor::NonMutableAccess(self)
+            }
+        }
+
+        impl NonMutableHookDataAccessor for FileReadingHook {
+            fn access(&self, _: &FileLockEntry) -> HookResult<()> {
+                let mut s = self.1.lock().unwrap();
+                s.clear();
+                File::open(&self.0).unwrap().read_to_string(&mut s).unwrap();
+                Ok(())
+            }
+        }
     }
 
     #[test]
-    fn test_main_section_with_version() {
-        use super::has_imag_version_in_main_section;
+    fn test_post_write_hook_sees_written_file() {
+        use std::sync::{Arc, Mutex};
+
+        use hook::position::HookPosition;
+        use self::file_reading_hook::FileReadingHook;
+
+        let mut cfg = get_store_config("");
+        set_hook_aspects(&mut cfg, "post-write-hook-aspects", &["vcs"]);
+        let (dir, mut store) = get_store_with_config(cfg);
+
+        let written = Arc::new(Mutex::new(String::new()));
+        let hook = FileReadingHook(dir.path().join("test/written~0.1.0"), written.clone());
+        store.register_hook(HookPosition::PostWrite, &String::from("vcs"), Box::new(hook))
+            .unwrap();
+
+        let mut entry = store.create(PathBuf::from("test/written~0.1.0")).unwrap();
+        *entry.get_content_mut() = String::from("new content");
+        store.update(entry).unwrap();
+        assert!(written.lock().unwrap().ends_with("---\nnew content"));
+
+        let mut entry = store.retrieve(PathBuf::from("test/written~0.1.0")).unwrap();
+        *entry.get_content_mut() = String::from("changed content");
+        store.update(entry).unwrap();
+        assert!(written.lock().unwrap().ends_with("---\nchanged content"));
+    }
 
-        let mut map = BTreeMap::new();
-        let mut sub = BTreeMap::new();
-        sub.insert("version".into(), Value::String("0.0.0".into()));
-        map.insert("imag".into(), Value::Table(sub));
+    #[test]
+    fn test_with_hooks_disabled() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use hook::position::HookPosition;
+        use self::counting_hook::CountingHook;
+
+        let mut cfg = get_store_config("");
+        set_hook_aspects(&mut cfg, "pre-create-hook-aspects", &["test"]);
+        let (_dir, mut store) = get_store_with_config(cfg);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let hook  = Box::new(CountingHook(count.clone()));
+        store.register_hook(HookPosition::PreCreate, &String::from("test"), hook).unwrap();
+
+        store.with_hooks_disabled(|| {
+            let _ = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 0);
 
-        assert!(has_imag_version_in_main_section(&map));
-    }
+        let _ = store.create(PathBuf::from("test/b~0.1.0")).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 1);
 
-    #[test]
-    fn test_main_section_with_version_in_wrong_type() {
-        use super::has_imag_version_in_main_section;
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            store.with_hooks_disabled(|| panic!("failing bulk operation"))
+        }));
+        assert!(res.is_err());
 
-        let mut map = BTreeMap::new();
-        let mut sub = BTreeMap::new();
-        sub.insert("version".into(), Value::Boolean(false));
-        map.insert("imag".into(), Value::Table(sub));
+        let _ = store.create(PathBuf::from("test/c~0.1.0")).unwrap();
+        assert_eq!(count.load(Ordering::SeqCst), 2);
 
-        assert!(!has_imag_version_in_main_section(&map));
+        // Nested calls restore hook execution when the outermost one returns
+        store.with_hooks_disabled(|| {
+            store.with_hooks_disabled(|| {});
+            let _ = store.create(PathBuf::from("test/d~0.1.0")).unwrap();
+        });
+        assert_eq!(count.load(Ordering::SeqCst), 2);
     }
 
     #[test]
-    fn test_verification_good() {
-        use super::verify_header_consistency;
-
-        let mut header = BTreeMap::new();
-        let sub = {
-            let mut sub = BTreeMap::new();
-            sub.insert("version".into(), Value::String(String::from("0.0.0")));
-
-            Value::Table(sub)
-        };
+    fn test_with_hooks_disabled_is_per_thread() {
+        use std::sync::{Arc, Barrier};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::thread;
+
+        use hook::position::HookPosition;
+        use self::counting_hook::CountingHook;
+
+        let mut cfg = get_store_config("");
+        set_hook_aspects(&mut cfg, "pre-create-hook-aspects", &["test"]);
+        let (_dir, mut store) = get_store_with_config(cfg);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let hook  = Box::new(CountingHook(count.clone()));
+        store.register_hook(HookPosition::PreCreate, &String::from("test"), hook).unwrap();
+
+        let store = &store;
+        let (disabled, created) = (Barrier::new(2), Barrier::new(2));
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                store.with_hooks_disabled(|| {
+                    disabled.wait();
+                    // The other thread creates its entry while hooks are disabled here
+                    created.wait();
+                    let _ = store.create(PathBuf::from("test/disabled~0.1.0")).unwrap();
+                });
+            });
 
-        header.insert("imag".into(), sub);
+            disabled.wait();
+            let _ = store.create(PathBuf::from("test/enabled~0.1.0")).unwrap();
+            created.wait();
+        });
 
-        assert!(verify_header_consistency(header).is_ok());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
     }
 
     #[test]
-    fn test_verification_invalid_versionstring() {
-        use super::verify_header_consistency;
+    fn test_durable_writes() {
+        for &(config, fsyncs) in &[("", 2), ("durable-writes = false", 0)] {
+            let (_dir, store) = get_store_with_config(get_store_config(config));
+            for _ in 0..2 {
+                let entry = store.retrieve(PathBuf::from("test/durable~0.1.0")).unwrap();
+                store.update(entry).unwrap();
+            }
+            assert_eq!(store.metrics().unwrap().fsyncs, fsyncs);
+        }
+    }
 
-        let mut header = BTreeMap::new();
-        let sub = {
-            let mut sub = BTreeMap::new();
-            sub.insert("version".into(), Value::String(String::from("000")));
+    #[test]
+    fn test_metrics_file_is_opened_per_borrow() {
+        let (dir, store) = get_store_with_config(None);
+        {
+            let mut entry = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("some content");
+        }
+        let len = dir.path().join("test/a~0.1.0").metadata().unwrap().len() as usize;
 
-            Value::Table(sub)
-        };
+        drop(store);
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        assert_eq!(store.metrics().unwrap(), StoreMetrics::default());
 
-        header.insert("imag".into(), sub);
+        // The file is closed when the entry is released
+        for _ in 0..2 {
+            let _ = store.retrieve(PathBuf::from("test/a~0.1.0")).unwrap();
+        }
+        let metrics = store.metrics().unwrap();
+        assert_eq!(metrics.files_opened, 2);
+        assert_eq!(metrics.bytes_read, 2 * len);
 
-        assert!(!verify_header_consistency(header).is_ok());
+        // retrieve_copy() does not keep the file open either
+        let _ = store.retrieve_copy(PathBuf::from("test/a~0.1.0")).unwrap();
+        assert_eq!(store.metrics().unwrap().files_opened, 3);
     }
 
-
     #[test]
-    fn test_verification_current_version() {
-        use super::verify_header_consistency;
-
-        let mut header = BTreeMap::new();
-        let sub = {
-            let mut sub = BTreeMap::new();
-            sub.insert("version".into(), Value::String(String::from(version!())));
+    fn test_rewrite_content() {
+        fn header_of(s: &str) -> String {
+            s.split("---\n").take(2).collect::<Vec<_>>().join("---\n")
+        }
 
-            Value::Table(sub)
-        };
+        let (dir, store) = get_store_with_config(None);
+        {
+            let mut entry = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            entry.get_header_mut().set("imag.custom", Value::String(String::from("value"))).unwrap();
+            *entry.get_content_mut() = String::from("some content\n");
+            store.update(entry).unwrap();
+        }
+        let before = read_file(&dir.path().join("test/a~0.1.0"));
 
-        header.insert("imag".into(), sub);
+        store.rewrite_content(PathBuf::from("test/a~0.1.0"), |c| c.to_uppercase()).unwrap();
+        let after = read_file(&dir.path().join("test/a~0.1.0"));
 
-        assert!(verify_header_consistency(header).is_ok());
+        assert_eq!(header_of(&before[..]), header_of(&after[..]));
+        assert!(after.ends_with("---\nSOME CONTENT\n"));
     }
 
-    static TEST_ENTRY : &'static str = "---
-[imag]
-version = \"0.0.3\"
----
-Hai";
-
-    #[test]
-    fn test_entry_from_str() {
-        use super::Entry;
-        use std::path::PathBuf;
-        println!("{}", TEST_ENTRY);
-        let entry = Entry::from_str(PathBuf::from("/test/foo~1.3"),
-                                    TEST_ENTRY).unwrap();
+    fn write_store_version(dir: &TempDir, version: &str) {
+        use std::fs::File;
+        use std::io::Write;
 
-        assert_eq!(entry.content, "Hai");
+        let mut f = File::create(dir.path().join(".imag_version")).unwrap();
+        f.write_all(version.as_bytes()).unwrap();
     }
 
     #[test]
-    fn test_entry_to_str() {
-        use super::Entry;
-        use std::path::PathBuf;
-        println!("{}", TEST_ENTRY);
-        let entry = Entry::from_str(PathBuf::from("/test/foo~1.3"),
-                                    TEST_ENTRY).unwrap();
-        let string = entry.to_str();
+    fn test_store_version_file_is_created() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let (dir, _store) = get_store_with_config(None);
+        let mut version = String::new();
+        File::open(dir.path().join(".imag_version")).unwrap()
+            .read_to_string(&mut version).unwrap();
+        assert_eq!(version, version!());
+
+        // matching version, reopening works
+        assert!(Store::new(PathBuf::from(dir.path()), None).is_ok());
+        let cfg = get_store_config("version-mismatch = \"error\"");
+        assert!(Store::new(PathBuf::from(dir.path()), cfg).is_ok());
+    }
 
-        assert_eq!(TEST_ENTRY, string);
+    #[test]
+    fn test_store_version_too_old_warns_by_default() {
+        let dir = TempDir::new("imag-store-test").unwrap();
+        write_store_version(&dir, "0.0.1");
+        assert!(Store::new(PathBuf::from(dir.path()), None).is_ok());
     }
 
     #[test]
-    fn test_walk_header_simple() {
-        let tokens = EntryHeader::tokenize("a", '.').unwrap();
-        assert!(tokens.len() == 1, "1 token was expected, {} were parsed", tokens.len());
-        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from("a")),
-                "'a' token was expected, {:?} was parsed", tokens.iter().next());
+    fn test_store_version_too_old_errors_if_configured() {
+        use error::StoreErrorKind;
 
-        let mut header = BTreeMap::new();
-        header.insert(String::from("a"), Value::Integer(1));
+        let dir = TempDir::new("imag-store-test").unwrap();
+        write_store_version(&dir, "0.0.1");
 
-        let mut v_header = Value::Table(header);
-        let res = EntryHeader::walk_header(&mut v_header, tokens);
-        assert_eq!(&mut Value::Integer(1), res.unwrap());
+        let cfg = get_store_config("version-mismatch = \"error\"");
+        match Store::new(PathBuf::from(dir.path()), cfg) {
+            Err(e) => assert_eq!(e.err_type(), StoreErrorKind::StoreVersionMismatch),
+            Ok(_)  => panic!("Store with incompatible version was opened"),
+        }
     }
 
     #[test]
-    fn test_walk_header_with_array() {
-        let tokens = EntryHeader::tokenize("a.0", '.').unwrap();
-        assert!(tokens.len() == 2, "2 token was expected, {} were parsed", tokens.len());
-        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from("a")),
-                "'a' token was expected, {:?} was parsed", tokens.iter().next());
+    fn test_index_disabled_by_default() {
+        let (dir, store) = get_store_with_config(None);
+        let _ = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
 
-        let mut header = BTreeMap::new();
-        let ary = Value::Array(vec![Value::Integer(1)]);
-        header.insert(String::from("a"), ary);
-
-
-        let mut v_header = Value::Table(header);
-        let res = EntryHeader::walk_header(&mut v_header, tokens);
-        assert_eq!(&mut Value::Integer(1), res.unwrap());
+        assert!(store.index().unwrap().is_none());
+        assert!(!dir.path().join(".imag_index").exists());
     }
 
     #[test]
-    fn test_walk_header_extract_array() {
-        let tokens = EntryHeader::tokenize("a", '.').unwrap();
-        assert!(tokens.len() == 1, "1 token was expected, {} were parsed", tokens.len());
-        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from("a")),
-                "'a' token was expected, {:?} was parsed", tokens.iter().next());
+    fn test_index_tracks_changes() {
+        use index::content_hash;
 
-        let mut header = BTreeMap::new();
-        let ary = Value::Array(vec![Value::Integer(1)]);
-        header.insert(String::from("a"), ary);
+        let (dir, store) = get_store_with_config(get_store_config("index = true"));
+        let a = dir.path().join("test/a~0.1.0");
+        let b = dir.path().join("test/b~0.1.0");
 
-        let mut v_header = Value::Table(header);
-        let res = EntryHeader::walk_header(&mut v_header, tokens);
-        assert_eq!(&mut Value::Array(vec![Value::Integer(1)]), res.unwrap());
-    }
+        {
+            let mut entry = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("first");
+            store.update(entry).unwrap();
+        }
+        let index = store.index().unwrap().unwrap();
+        assert_eq!(index.len(), 1);
+        assert_eq!(index[&a].content_hash(), &content_hash("first")[..]);
 
-    /**
-     * Creates a big testing header.
-     *
-     * JSON equivalent:
-     *
-     * ```json
-     * {
-     *      "a": {
-     *          "array": [ 0, 1, 2, 3, 4, 5, 6, 7, 8, 9 ]
-     *      },
-     *      "b": {
-     *          "array": [ "string1", "string2", "string3", "string4" ]
-     *      },
-     *      "c": {
-     *          "array": [ 1, "string2", 3, "string4" ]
-     *      },
-     *      "d": {
-     *          "array": [
-     *              {
-     *                  "d1": 1
-     *              },
-     *              {
-     *                  "d2": 2
-     *              },
-     *              {
-     *                  "d3": 3
-     *              },
-     *          ],
-     *
-     *          "something": "else",
-     *
-     *          "and": {
-     *              "something": {
-     *                  "totally": "different"
-     *              }
-     *          }
-     *      }
-     * }
-     * ```
-     *
-     * The sections "a", "b", "c", "d" are created in the respective helper functions
-     * create_header_section_a, create_header_section_b, create_header_section_c and
-     * create_header_section_d.
-     *
-     * These functions can also be used for testing.
-     *
-     */
-    fn create_header() -> Value {
-        let a = create_header_section_a();
-        let b = create_header_section_b();
-        let c = create_header_section_c();
-        let d = create_header_section_d();
+        store.rewrite_content(PathBuf::from("test/a~0.1.0"), |_| String::from("second")).unwrap();
+        let index = store.index().unwrap().unwrap();
+        assert_eq!(index[&a].content_hash(), &content_hash("second")[..]);
 
-        let mut header = BTreeMap::new();
-        header.insert(String::from("a"), a);
-        header.insert(String::from("b"), b);
-        header.insert(String::from("c"), c);
-        header.insert(String::from("d"), d);
+        {
+            let entry = store.create(PathBuf::from("test/b~0.1.0")).unwrap();
+            store.update(entry).unwrap();
+        }
+        store.delete(PathBuf::from("test/a~0.1.0")).unwrap();
+        let index = store.index().unwrap().unwrap();
+        assert_eq!(index.keys().collect::<Vec<_>>(), vec![&b]);
 
-        Value::Table(header)
+        // The index is persisted
+        let store = Store::new(PathBuf::from(dir.path()), get_store_config("index = true")).unwrap();
+        assert_eq!(store.index().unwrap().unwrap(), index);
     }
 
-    fn create_header_section_a() -> Value {
-        // 0..10 is exclusive 10
-        let a_ary = Value::Array((0..10).map(|x| Value::Integer(x)).collect());
+    #[test]
+    fn test_index_rebuild_matches_scan() {
+        use std::fs::remove_file;
 
-        let mut a_obj = BTreeMap::new();
-        a_obj.insert(String::from("array"), a_ary);
-        Value::Table(a_obj)
-    }
+        let (dir, store) = get_store_with_config(get_store_config("index = true"));
+        for name in ["a", "b", "c"].iter() {
+            let mut entry = store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            *entry.get_content_mut() = format!("content of {}", name);
+        }
+        let index = store.index().unwrap().unwrap();
+        assert_eq!(index.len(), 3);
 
-    fn create_header_section_b() -> Value {
-        let b_ary = Value::Array((0..9)
-                                 .map(|x| Value::String(format!("string{}", x)))
-                                 .collect());
+        store.rebuild_index().unwrap();
+        assert_eq!(store.index().unwrap().unwrap(), index);
 
-        let mut b_obj = BTreeMap::new();
-        b_obj.insert(String::from("array"), b_ary);
-        Value::Table(b_obj)
+        // A missing index is rebuilt when the store is opened
+        remove_file(dir.path().join(".imag_index")).unwrap();
+        drop(store);
+        let store = Store::new(PathBuf::from(dir.path()), get_store_config("index = true")).unwrap();
+        assert_eq!(store.index().unwrap().unwrap(), index);
     }
 
-    fn create_header_section_c() -> Value {
-        let c_ary = Value::Array(
-            vec![
-                Value::Integer(1),
-                Value::String(String::from("string2")),
-                Value::Integer(3),
-                Value::String(String::from("string4"))
-            ]);
+    #[test]
+    fn test_registered_aspects_and_hooks() {
+        use std::sync::Arc;
+        use std::sync::atomic::AtomicUsize;
 
-        let mut c_obj = BTreeMap::new();
-        c_obj.insert(String::from("array"), c_ary);
-        Value::Table(c_obj)
-    }
+        use hook::position::HookPosition;
+        use self::counting_hook::CountingHook;
 
-    fn create_header_section_d() -> Value {
-        let d_ary = Value::Array(
-            vec![
-                {
-                    let mut tab = BTreeMap::new();
-                    tab.insert(String::from("d1"), Value::Integer(1));
-                    tab
-                },
-                {
-                    let mut tab = BTreeMap::new();
-                    tab.insert(String::from("d2"), Value::Integer(2));
-                    tab
-                },
-                {
-                    let mut tab = BTreeMap::new();
-                    tab.insert(String::from("d3"), Value::Integer(3));
-                    tab
-                },
-            ].into_iter().map(Value::Table).collect());
+        let mut cfg = get_store_config("");
+        set_hook_aspects(&mut cfg, "pre-delete-hook-aspects", &["cleanup", "audit"]);
+        let (_dir, mut store) = get_store_with_config(cfg);
 
-        let and_obj = Value::Table({
-            let mut tab = BTreeMap::new();
-            let something_tab = Value::Table({
-                let mut tab = BTreeMap::new();
-                tab.insert(String::from("totally"), Value::String(String::from("different")));
-                tab
-            });
-            tab.insert(String::from("something"), something_tab);
-            tab
-        });
+        let hook = Box::new(CountingHook(Arc::new(AtomicUsize::new(0))));
+        store.register_hook(HookPosition::PreDelete, &String::from("audit"), hook).unwrap();
 
-        let mut d_obj = BTreeMap::new();
-        d_obj.insert(String::from("array"), d_ary);
-        d_obj.insert(String::from("something"), Value::String(String::from("else")));
-        d_obj.insert(String::from("and"), and_obj);
-        Value::Table(d_obj)
-    }
+        assert_eq!(store.registered_aspects(HookPosition::PreDelete),
+                   vec![String::from("cleanup"), String::from("audit")]);
+        assert!(store.registered_aspects(HookPosition::PreCreate).is_empty());
 
-    #[test]
-    fn test_walk_header_big_a() {
-        test_walk_header_extract_section("a", &create_header_section_a());
+        assert_eq!(store.hooks_in_aspect("audit"), vec![String::from("counting")]);
+        assert!(store.hooks_in_aspect("cleanup").is_empty());
+        assert!(store.hooks_in_aspect("nonexistent").is_empty());
     }
 
     #[test]
-    fn test_walk_header_big_b() {
-        test_walk_header_extract_section("b", &create_header_section_b());
-    }
+    fn test_vacuum() {
+        use std::fs::{File, create_dir_all};
 
-    #[test]
-    fn test_walk_header_big_c() {
-        test_walk_header_extract_section("c", &create_header_section_c());
+        let (dir, store) = get_store_with_config(None);
+        {
+            let mut entry = store.create(PathBuf::from("test/real~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("real content");
+        }
+        {
+            let mut entry = store.create(PathBuf::from("test/header~0.1.0")).unwrap();
+            entry.get_header_mut().set("imag.custom", Value::Boolean(true)).unwrap();
+        }
+        {
+            let _ = store.create(PathBuf::from("test/empty~0.1.0")).unwrap();
+        }
+        let borrowed = store.create(PathBuf::from("test/borrowed~0.1.0")).unwrap();
+
+        // Left by interrupted writes
+        File::create(dir.path().join("test/.a~0.1.0.tmp")).unwrap();
+        File::create(dir.path().join("test/.a~0.1.0.body.tmp")).unwrap();
+        File::create(dir.path().join("test/.borrowed~0.1.0.tmp")).unwrap();
+        File::create(dir.path().join("test/.user-file.tmp")).unwrap();
+        File::create(dir.path().join("test/user-file~0.1.0.tmp")).unwrap();
+        create_dir_all(dir.path().join("test/empty-dir/nested")).unwrap();
+        create_dir_all(dir.path().join(".hidden/empty")).unwrap();
+
+        // Empty entries stay unless requested
+        let report = store.vacuum(false).unwrap();
+        assert!(report.empty_entries.is_empty());
+        let mut temp_files = report.temp_files.clone();
+        temp_files.sort();
+        assert_eq!(temp_files, vec![
+            dir.path().join("test/.a~0.1.0.body.tmp"),
+            dir.path().join("test/.a~0.1.0.tmp"),
+        ]);
+        assert_eq!(report.directories, vec![
+            dir.path().join("test/empty-dir/nested"),
+            dir.path().join("test/empty-dir"),
+        ]);
+        assert!(dir.path().join("test/empty~0.1.0").is_file());
+
+        let report = store.vacuum(true).unwrap();
+        assert_eq!(report.empty_entries, vec![dir.path().join("test/empty~0.1.0")]);
+        assert!(report.temp_files.is_empty());
+        assert!(report.directories.is_empty());
+
+        assert!(!dir.path().join("test/empty~0.1.0").exists());
+        assert!(dir.path().join("test/real~0.1.0").is_file());
+        assert!(dir.path().join("test/header~0.1.0").is_file());
+        assert!(!dir.path().join("test/.a~0.1.0.tmp").exists());
+        assert!(dir.path().join("test/.borrowed~0.1.0.tmp").is_file());
+        assert!(dir.path().join("test/.user-file.tmp").is_file());
+        assert!(dir.path().join("test/user-file~0.1.0.tmp").is_file());
+        assert!(dir.path().join(".hidden/empty").is_dir());
+        drop(borrowed);
+        assert!(dir.path().join("test/borrowed~0.1.0").is_file());
     }
 
     #[test]
-    fn test_walk_header_big_d() {
-        test_walk_header_extract_section("d", &create_header_section_d());
-    }
+    fn test_vacuum_deletes_empty_entries_with_delete() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
 
-    fn test_walk_header_extract_section(secname: &str, expected: &Value) {
-        let tokens = EntryHeader::tokenize(secname, '.').unwrap();
-        assert!(tokens.len() == 1, "1 token was expected, {} were parsed", tokens.len());
-        assert!(tokens.iter().next().unwrap() == &Token::Key(String::from(secname)),
-                "'{}' token was expected, {:?} was parsed", secname, tokens.iter().next());
+        use hook::position::HookPosition;
+        use self::counting_hook::CountingHook;
 
-        let mut header = create_header();
-        let res = EntryHeader::walk_header(&mut header, tokens);
-        assert_eq!(expected, res.unwrap());
+        let mut cfg = get_store_config("separate-content = true");
+        set_hook_aspects(&mut cfg, "post-delete-hook-aspects", &["count"]);
+        let (dir, mut store) = get_store_with_config(cfg);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let hook = Box::new(CountingHook(count.clone()));
+        store.register_hook(HookPosition::PostDelete, &String::from("count"), hook).unwrap();
+
+        {
+            let _ = store.create(PathBuf::from("test/empty~0.1.0")).unwrap();
+        }
+        assert!(dir.path().join("test/.empty~0.1.0.body").is_file());
+
+        let report = store.vacuum(true).unwrap();
+        assert_eq!(report.empty_entries, vec![dir.path().join("test/empty~0.1.0")]);
+        assert!(!dir.path().join("test/empty~0.1.0").exists());
+        assert!(!dir.path().join("test/.empty~0.1.0.body").exists());
+        assert_eq!(count.load(Ordering::SeqCst), 1);
     }
 
     #[test]
-    fn test_walk_header_extract_numbers() {
-        test_extract_number("a", 0, 0);
-        test_extract_number("a", 1, 1);
-        test_extract_number("a", 2, 2);
-        test_extract_number("a", 3, 3);
-        test_extract_number("a", 4, 4);
-        test_extract_number("a", 5, 5);
-        test_extract_number("a", 6, 6);
-        test_extract_number("a", 7, 7);
-        test_extract_number("a", 8, 8);
-        test_extract_number("a", 9, 9);
+    fn test_save_as() {
+        use error::StoreErrorKind;
 
-        test_extract_number("c", 0, 1);
-        test_extract_number("c", 2, 3);
-    }
+        let (dir, store) = get_store_with_config(None);
+        let mut entry = store.create(PathBuf::from("test/old~0.1.0")).unwrap();
+        *entry.get_content_mut() = String::from("saved content");
+        store.save_as(entry, PathBuf::from("test/new~0.1.0")).unwrap();
 
-    fn test_extract_number(sec: &str, idx: usize, exp: i64) {
-        let tokens = EntryHeader::tokenize(&format!("{}.array.{}", sec, idx)[..], '.').unwrap();
-        assert!(tokens.len() == 3, "3 token was expected, {} were parsed", tokens.len());
+        assert!(!dir.path().join("test/old~0.1.0").exists());
         {
-            let mut iter = tokens.iter();
+            let entry = store.retrieve(PathBuf::from("test/new~0.1.0")).unwrap();
+            assert_eq!(entry.get_content(), "saved content");
+            store.update(entry).unwrap();
+        }
+        assert!(!dir.path().join("test/old~0.1.0").exists());
 
-            let tok = iter.next().unwrap();
-            let exp = Token::Key(String::from(sec));
-            assert!(tok == &exp, "'{}' token was expected, {:?} was parsed", sec, tok);
+        let other = store.create(PathBuf::from("test/other~0.1.0")).unwrap();
+        match store.save_as(other, PathBuf::from("test/new~0.1.0")) {
+            Err(e) => assert_eq!(e.err_type(), StoreErrorKind::EntryAlreadyExists),
+            Ok(_)  => panic!("save_as() overwrote an existing entry"),
+        }
+    }
 
-            let tok = iter.next().unwrap();
-            let exp = Token::Key(String::from("array"));
-            assert!(tok == &exp, "'array' token was expected, {:?} was parsed", tok);
+    #[test]
+    fn test_move_by_id() {
+        use error::StoreErrorKind;
 
-            let tok = iter.next().unwrap();
-            let exp = Token::Index(idx);
-            assert!(tok == &exp, "'{}' token was expected, {:?} was parsed", idx, tok);
+        let (dir, store) = get_store_with_config(None);
+        {
+            let mut entry = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("content of a");
+            store.update(entry).unwrap();
         }
 
-        let mut header = create_header();
-        let res = EntryHeader::walk_header(&mut header, tokens);
-        assert_eq!(&mut Value::Integer(exp), res.unwrap());
+        // The entry is loaded, but not borrowed
+        store.move_by_id(PathBuf::from("test/a~0.1.0"), PathBuf::from("moved/a~0.1.0")).unwrap();
+        assert!(!dir.path().join("test/a~0.1.0").exists());
+        {
+            let mut entry = store.retrieve(PathBuf::from("moved/a~0.1.0")).unwrap();
+            assert_eq!(entry.get_content(), "content of a");
+            *entry.get_content_mut() = String::from("changed");
+        }
+        assert!(!dir.path().join("test/a~0.1.0").exists());
+
+        // The entry is not loaded
+        drop(store);
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        store.move_by_id(PathBuf::from("moved/a~0.1.0"), PathBuf::from("test/a~0.1.0")).unwrap();
+        assert_eq!(store.retrieve_copy(PathBuf::from("test/a~0.1.0")).unwrap().get_content(),
+                   "changed");
+
+        let _borrowed = store.retrieve(PathBuf::from("test/a~0.1.0")).unwrap();
+        match store.move_by_id(PathBuf::from("test/a~0.1.0"), PathBuf::from("moved/a~0.1.0")) {
+            Err(e) => assert_eq!(e.err_type(), StoreErrorKind::IdLocked),
+            Ok(_)  => panic!("Moved a borrowed entry"),
+        }
     }
 
     #[test]
-    fn test_header_read() {
-        let v = create_header();
-        let h = match v {
-            Value::Table(t) => EntryHeader::from_table(t),
-            _ => panic!("create_header() doesn't return a table!"),
+    fn test_move_and_relink() {
+        let (dir, store) = get_store_with_config(None);
+        let link = |name: &str| {
+            Value::String(String::from(dir.path().join(name).to_str().unwrap()))
         };
 
-        assert!(if let Ok(Some(Value::Table(_)))  = h.read("a") { true } else { false });
-        assert!(if let Ok(Some(Value::Array(_)))   = h.read("a.array") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.1") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.9") { true } else { false });
+        {
+            let mut a = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            let mut b = store.create(PathBuf::from("test/b~0.1.0")).unwrap();
+            let mut c = store.create(PathBuf::from("test/c~0.1.0")).unwrap();
+            a.get_header_mut().set("imag.links", Value::Array(vec![link("test/b~0.1.0")])).unwrap();
+            b.get_header_mut().set("imag.links", Value::Array(vec![link("test/a~0.1.0")])).unwrap();
+            c.get_header_mut().set("imag.links", Value::Array(vec![link("test/a~0.1.0")])).unwrap();
+        }
 
-        assert!(if let Ok(Some(Value::Table(_))) = h.read("c") { true } else { false });
-        assert!(if let Ok(Some(Value::Array(_)))  = h.read("c.array") { true } else { false });
-        assert!(if let Ok(Some(Value::String(_))) = h.read("c.array.1") { true } else { false });
-        assert!(if let Ok(None) = h.read("c.array.9") { true } else { false });
+        let relinked = store.move_and_relink(PathBuf::from("test/b~0.1.0"),
+                                             PathBuf::from("moved/b~0.1.0"));
+        assert_eq!(relinked.unwrap(), 1);
 
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("d.array.0.d1") { true } else { false });
-        assert!(if let Ok(None) = h.read("d.array.0.d2") { true } else { false });
-        assert!(if let Ok(None) = h.read("d.array.0.d3") { true } else { false });
+        let a = store.retrieve_copy(PathBuf::from("test/a~0.1.0")).unwrap();
+        assert_eq!(a.get_header().read("imag.links").unwrap(),
+                   Some(Value::Array(vec![link("moved/b~0.1.0")])));
 
-        assert!(if let Ok(None) = h.read("d.array.1.d1") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("d.array.1.d2") { true } else { false });
-        assert!(if let Ok(None) = h.read("d.array.1.d3") { true } else { false });
+        let b = store.retrieve_copy(PathBuf::from("moved/b~0.1.0")).unwrap();
+        assert_eq!(b.get_header().read("imag.links").unwrap(),
+                   Some(Value::Array(vec![link("test/a~0.1.0")])));
+    }
 
-        assert!(if let Ok(None) = h.read("d.array.2.d1") { true } else { false });
-        assert!(if let Ok(None) = h.read("d.array.2.d2") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("d.array.2.d3") { true } else { false });
+    #[test]
+    fn test_internal_links_roundtrip() {
+        use super::Entry;
+        use error::StoreErrorKind;
+
+        let mut entry = Entry::new(PathBuf::from("test/a~0.1.0"));
+        assert_eq!(entry.internal_links().unwrap(), Vec::<StoreId>::new());
+
+        let links : Vec<StoreId> = vec!["test/c~0.1.0", "test/b~0.1.0",
+                                        "test/c~0.1.0", "test/a~0.1.0"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        entry.set_internal_links(&links[..]).unwrap();
+
+        let expected : Vec<StoreId> = vec!["test/a~0.1.0", "test/b~0.1.0", "test/c~0.1.0"]
+            .into_iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(entry.internal_links().unwrap(), expected);
+        assert_eq!(entry.get_header().read("imag.links").unwrap(),
+                   Some(Value::Array(vec![
+                        Value::String(String::from("test/a~0.1.0")),
+                        Value::String(String::from("test/b~0.1.0")),
+                        Value::String(String::from("test/c~0.1.0")),
+                   ])));
+
+        entry.get_header_mut().set("imag.links", Value::Array(vec![Value::Integer(1)])).unwrap();
+        let err = entry.internal_links().unwrap_err();
+        assert_eq!(err.err_type(), StoreErrorKind::HeaderTypeFailure);
+    }
 
-        assert!(if let Ok(Some(Value::String(_))) = h.read("d.something") { true } else { false });
-        assert!(if let Ok(Some(Value::Table(_))) = h.read("d.and") { true } else { false });
-        assert!(if let Ok(Some(Value::Table(_))) = h.read("d.and.something") { true } else { false });
-        assert!(if let Ok(Some(Value::String(_))) = h.read("d.and.something.totally") { true } else { false });
+    #[test]
+    fn test_entries_for_module() {
+        let (_dir, store) = get_store_with_config(None);
+        for name in ["a", "b"].iter() {
+            let mut entry = store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            *entry.get_content_mut() = format!("content of {}", name);
+        }
+
+        let mut contents : Vec<String> = store.entries_for_module("test")
+            .unwrap()
+            .map(|entry| entry.unwrap().get_content().clone())
+            .collect();
+        contents.sort();
+        assert_eq!(contents, vec!["content of a", "content of b"]);
+
+        // The entries are only borrowed while the iterator yields them
+        let mut iter = store.entries_for_module("test").unwrap();
+        let first = iter.next().unwrap().unwrap();
+        assert!(store.retrieve(first.get_location().clone()).is_err());
+        drop(first);
+        assert!(iter.next().unwrap().is_ok());
+        assert!(iter.next().is_none());
     }
 
     #[test]
-    fn test_header_set_override() {
-        let _ = env_logger::init();
-        let v = create_header();
-        let mut h = match v {
-            Value::Table(t) => EntryHeader::from_table(t),
-            _ => panic!("create_header() doesn't return a table!"),
-        };
+    fn test_export_jsonl() {
+        use std::fs::File;
+        use std::io::Write;
+        use rustc_serialize::json::Json;
+
+        let (dir, store) = get_store_with_config(None);
+        for name in ["a", "b"].iter() {
+            let mut entry = store.create(PathBuf::from(format!("test/{}~0.1.0", name))).unwrap();
+            *entry.get_content_mut() = format!("content of {}", name);
+        }
+        let broken = dir.path().join("test/c~0.1.0");
+        write!(File::create(&broken).unwrap(), "no header").unwrap();
+
+        let mut out = vec![];
+        store.export_jsonl(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines : Vec<Json> = out.lines().map(|l| Json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 3);
+
+        let a = lines[0].as_object().unwrap();
+        assert_eq!(a["id"].as_string().unwrap(), dir.path().join("test/a~0.1.0").to_str().unwrap());
+        assert_eq!(a["content"].as_string().unwrap(), "content of a");
+        assert_eq!(lines[0].find_path(&["header", "imag", "version"]).unwrap().as_string(),
+                   Some(version!().as_ref()));
+        assert_eq!(lines[1].find("content").unwrap().as_string(), Some("content of b"));
+
+        let c = lines[2].as_object().unwrap();
+        assert_eq!(c["id"].as_string().unwrap(), broken.to_str().unwrap());
+        assert!(c.contains_key("error"));
+        assert!(!c.contains_key("content"));
+    }
 
-        println!("Testing index 0");
-        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(0));
+    fn write_old_entry(store: &Store, id: &str) {
+        let mut entry = store.create(PathBuf::from(id)).unwrap();
+        entry.get_header_mut().set("imag.version", Value::String(String::from("0.0.1"))).unwrap();
+        entry.get_header_mut().insert("test", Value::Table(BTreeMap::new())).unwrap();
+        entry.get_header_mut().insert("test.old", Value::String(String::from("value"))).unwrap();
+    }
 
-        println!("Altering index 0");
-        assert_eq!(h.set("a.array.0", Value::Integer(42)).unwrap().unwrap(), Value::Integer(0));
+    fn register_migrations(store: &Store) {
+        use semver::Version;
+
+        store.register_migration(Version::parse("0.0.5").unwrap(),
+                                 Version::parse(version!()).unwrap(),
+                                 Box::new(|h| {
+                                     h.insert("test.migrated", Value::Boolean(true)).map(|_| ())
+                                 })).unwrap();
+        store.register_migration(Version::parse("0.0.1").unwrap(),
+                                 Version::parse("0.0.5").unwrap(),
+                                 Box::new(|h| {
+                                     let old = try!(h.read("test.old")).unwrap();
+                                     try!(h.delete("test.old"));
+                                     h.insert("test.new", old).map(|_| ())
+                                 })).unwrap();
+    }
 
-        println!("Values now: {:?}", h);
+    #[test]
+    fn test_migrate_entry() {
+        let (_dir, store) = get_store_with_config(None);
+        write_old_entry(&store, "test/old~0.1.0");
+        register_migrations(&store);
 
-        println!("Testing all indexes");
-        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(42));
-        assert_eq!(h.read("a.array.1").unwrap().unwrap(), Value::Integer(1));
-        assert_eq!(h.read("a.array.2").unwrap().unwrap(), Value::Integer(2));
-        assert_eq!(h.read("a.array.3").unwrap().unwrap(), Value::Integer(3));
-        assert_eq!(h.read("a.array.4").unwrap().unwrap(), Value::Integer(4));
-        assert_eq!(h.read("a.array.5").unwrap().unwrap(), Value::Integer(5));
-        assert_eq!(h.read("a.array.6").unwrap().unwrap(), Value::Integer(6));
-        assert_eq!(h.read("a.array.7").unwrap().unwrap(), Value::Integer(7));
-        assert_eq!(h.read("a.array.8").unwrap().unwrap(), Value::Integer(8));
-        assert_eq!(h.read("a.array.9").unwrap().unwrap(), Value::Integer(9));
+        // Migrations are opt-in when retrieving
+        {
+            let entry = store.retrieve(PathBuf::from("test/old~0.1.0")).unwrap();
+            assert_eq!(entry.get_header().read("imag.version").unwrap(),
+                       Some(Value::String(String::from("0.0.1"))));
+        }
+
+        assert!(store.migrate_entry(PathBuf::from("test/old~0.1.0")).unwrap());
+        let entry = store.retrieve_copy(PathBuf::from("test/old~0.1.0")).unwrap();
+        assert_eq!(entry.get_header().read("imag.version").unwrap(),
+                   Some(Value::String(String::from(version!()))));
+        assert_eq!(entry.get_header().read("test.old").unwrap(), None);
+        assert_eq!(entry.get_header().read("test.new").unwrap(),
+                   Some(Value::String(String::from("value"))));
+        assert_eq!(entry.get_header().read("test.migrated").unwrap(), Some(Value::Boolean(true)));
+
+        // A current entry is not migrated again
+        assert!(!store.migrate_entry(PathBuf::from("test/old~0.1.0")).unwrap());
     }
 
     #[test]
-    fn test_header_set_new() {
-        let _ = env_logger::init();
-        let v = create_header();
-        let mut h = match v {
-            Value::Table(t) => EntryHeader::from_table(t),
-            _ => panic!("create_header() doesn't return a table!"),
-        };
+    fn test_migrate_entry_failing_migration() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use semver::Version;
+
+        use error::{StoreError, StoreErrorKind};
+
+        let (_dir, store) = get_store_with_config(None);
+        write_old_entry(&store, "test/old~0.1.0");
+
+        let applied = Arc::new(AtomicUsize::new(0));
+        let count = applied.clone();
+        store.register_migration(Version::parse("0.0.1").unwrap(),
+                                 Version::parse("0.0.2").unwrap(),
+                                 Box::new(|h| h.delete("test.old").map(|_| ()))).unwrap();
+        store.register_migration(Version::parse("0.0.2").unwrap(),
+                                 Version::parse("0.0.3").unwrap(),
+                                 Box::new(|_| Err(StoreError::new(StoreErrorKind::MigrationError,
+                                                                  None)))).unwrap();
+        store.register_migration(Version::parse("0.0.3").unwrap(),
+                                 Version::parse("0.0.5").unwrap(),
+                                 Box::new(move |_| {
+                                     count.fetch_add(1, Ordering::SeqCst);
+                                     Ok(())
+                                 })).unwrap();
+
+        let res = store.migrate_entry(PathBuf::from("test/old~0.1.0"));
+        assert_eq!(res.unwrap_err().err_type(), StoreErrorKind::MigrationError);
+        assert_eq!(applied.load(Ordering::SeqCst), 0);
+
+        // Neither the failed nor the earlier steps were written
+        let entry = store.retrieve_copy(PathBuf::from("test/old~0.1.0")).unwrap();
+        assert_eq!(entry.get_header().read("imag.version").unwrap(),
+                   Some(Value::String(String::from("0.0.1"))));
+        assert_eq!(entry.get_header().read("test.old").unwrap(),
+                   Some(Value::String(String::from("value"))));
+    }
 
-        assert!(h.read("a.foo").is_ok());
-        assert!(h.read("a.foo").unwrap().is_none());
+    #[test]
+    fn test_migrate_on_retrieve() {
+        use hook::position::HookPosition;
+
+        let mut cfg = get_store_config("");
+        set_hook_aspects(&mut cfg, "post-retrieve-hook-aspects", &["migrate"]);
+        let (_dir, mut store) = get_store_with_config(cfg);
+        write_old_entry(&store, "test/old~0.1.0");
+        register_migrations(&store);
+
+        let hook = Box::new(store.migration_hook());
+        store.register_hook(HookPosition::PostRetrieve, &String::from("migrate"), hook).unwrap();
+
+        let entry = store.retrieve(PathBuf::from("test/old~0.1.0")).unwrap();
+        assert_eq!(entry.get_header().read("imag.version").unwrap(),
+                   Some(Value::String(String::from(version!()))));
+        assert_eq!(entry.get_header().read("test.migrated").unwrap(), Some(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn test_entry_from_str_crlf() {
+        use super::Entry;
+
+        let s = concat!("---\r\n",
+                        "[imag]\r\n",
+                        "links = []\r\n",
+                        "version = \"0.1.0\"\r\n",
+                        "---\r\n",
+                        "first line\r\n",
+                        "second line\r\n");
+        let entry = Entry::from_str(PathBuf::from("test/crlf~0.1.0"), s).unwrap();
+        assert_eq!(entry.get_header().read("imag.version").unwrap(),
+                   Some(Value::String(String::from("0.1.0"))));
+        assert_eq!(entry.get_content(), "first line\r\nsecond line\r\n");
+    }
+
+    #[test]
+    fn test_archive_and_unarchive() {
+        use error::StoreErrorKind;
 
+        let (dir, store) = get_store_with_config(None);
         {
-            let v = h.set("a.foo", Value::Integer(42));
-            assert!(v.is_ok());
-            assert!(v.unwrap().is_none());
+            let mut entry = store.create(PathBuf::from("test/sub/a~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("content of a");
+        }
 
-            assert!(if let Ok(Some(Value::Table(_))) = h.read("a") { true } else { false });
-            assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.foo") { true } else { false });
+        let archived = store.archive(PathBuf::from("test/sub/a~0.1.0"), "archive").unwrap();
+        assert_eq!(archived, dir.path().join("archive/test/sub/a~0.1.0"));
+        assert!(!store.exists(PathBuf::from("test/sub/a~0.1.0")));
+
+        let entry = store.retrieve_copy(archived.clone()).unwrap();
+        assert_eq!(entry.get_content(), "content of a");
+        assert_eq!(entry.get_header().read("imag.archived").unwrap(), Some(Value::Boolean(true)));
+
+        let err = store.unarchive(PathBuf::from("test/other~0.1.0"), "archive").err().unwrap();
+        assert_eq!(err.err_type(), StoreErrorKind::EntryNotArchived);
+
+        let unarchived = store.unarchive(archived, "archive").unwrap();
+        assert_eq!(unarchived, dir.path().join("test/sub/a~0.1.0"));
+        let entry = store.retrieve_copy(unarchived).unwrap();
+        assert_eq!(entry.get_content(), "content of a");
+        assert_eq!(entry.get_header().read("imag.archived").unwrap(), None);
+    }
+
+    #[test]
+    fn test_delete_matching() {
+        use std::error::Error;
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use error::StoreErrorKind;
+        use hook::position::HookPosition;
+        use self::counting_hook::{CountingHook, FailingHook};
+
+        let mut cfg = get_store_config("");
+        set_hook_aspects(&mut cfg, "pre-delete-hook-aspects", &["fail"]);
+        set_hook_aspects(&mut cfg, "post-delete-hook-aspects", &["count"]);
+        let (_dir, mut store) = get_store_with_config(cfg);
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let hook = Box::new(CountingHook(count.clone()));
+        store.register_hook(HookPosition::PostDelete, &String::from("count"), hook).unwrap();
+        let hook = Box::new(FailingHook("undeletable~0.1.0"));
+        store.register_hook(HookPosition::PreDelete, &String::from("fail"), hook).unwrap();
+
+        for id in ["diary/a~0.1.0", "diary/sub/b~0.1.0", "other/c~0.1.0"].iter() {
+            let _ = store.create(PathBuf::from(id)).unwrap();
         }
 
         {
-            let v = h.set("new", Value::Table(BTreeMap::new()));
-            assert!(v.is_ok());
-            assert!(v.unwrap().is_none());
-
-            let v = h.set("new.subset", Value::Table(BTreeMap::new()));
-            assert!(v.is_ok());
-            assert!(v.unwrap().is_none());
+            let _borrowed = store.retrieve(PathBuf::from("diary/a~0.1.0")).unwrap();
+            let err = store.delete_matching("diary/**/*").err().unwrap();
+            assert_eq!(err.err_type(), StoreErrorKind::IdLocked);
+            assert!(store.exists(PathBuf::from("diary/sub/b~0.1.0")));
+            assert_eq!(count.load(Ordering::SeqCst), 0);
+        }
 
-            let v = h.set("new.subset.dest", Value::Integer(1337));
-            assert!(v.is_ok());
-            assert!(v.unwrap().is_none());
+        assert_eq!(store.delete_matching("diary/**/*").unwrap(), 2);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert!(!store.exists(PathBuf::from("diary/a~0.1.0")));
+        assert!(!store.exists(PathBuf::from("diary/sub/b~0.1.0")));
+        assert!(store.exists(PathBuf::from("other/c~0.1.0")));
 
-            assert!(if let Ok(Some(Value::Table(_))) = h.read("new") { true } else { false });
-            assert!(if let Ok(Some(Value::Table(_))) = h.read("new.subset") { true } else { false });
-            assert!(if let Ok(Some(Value::Integer(_))) = h.read("new.subset.dest") { true } else { false });
+        // A deletion failing partway reports the number of deleted entries
+        for id in ["diary/a~0.1.0", "diary/undeletable~0.1.0", "diary/z~0.1.0"].iter() {
+            let _ = store.create(PathBuf::from(id)).unwrap();
         }
+        let err = store.delete_matching("diary/*").err().unwrap();
+        assert_eq!(err.err_type(), StoreErrorKind::PartialDeletion);
+        assert!(format!("{}", err.cause().unwrap()).starts_with("1 entries were deleted"));
+        assert!(!store.exists(PathBuf::from("diary/a~0.1.0")));
+        assert!(store.exists(PathBuf::from("diary/undeletable~0.1.0")));
+        assert!(store.exists(PathBuf::from("diary/z~0.1.0")));
     }
 
-
     #[test]
-    fn test_header_insert_override() {
-        let _ = env_logger::init();
-        let v = create_header();
-        let mut h = match v {
-            Value::Table(t) => EntryHeader::from_table(t),
-            _ => panic!("create_header() doesn't return a table!"),
-        };
+    fn test_non_fatal_aspect() {
+        use hook::position::HookPosition;
+        use self::counting_hook::FailingHook;
 
-        println!("Testing index 0");
-        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(0));
+        let mut cfg = get_store_config("[observer]\nnon-fatal = true");
+        set_hook_aspects(&mut cfg, "pre-create-hook-aspects", &["observer", "guard"]);
+        let (dir, mut store) = get_store_with_config(cfg);
 
-        println!("Altering index 0");
-        assert_eq!(h.insert("a.array.0", Value::Integer(42)).unwrap(), false);
-        println!("...should have failed");
+        let hook = Box::new(FailingHook("observed~0.1.0"));
+        store.register_hook(HookPosition::PreCreate, &String::from("observer"), hook).unwrap();
+        let hook = Box::new(FailingHook("guarded~0.1.0"));
+        store.register_hook(HookPosition::PreCreate, &String::from("guard"), hook).unwrap();
 
-        println!("Testing all indexes");
-        assert_eq!(h.read("a.array.0").unwrap().unwrap(), Value::Integer(0));
-        assert_eq!(h.read("a.array.1").unwrap().unwrap(), Value::Integer(1));
-        assert_eq!(h.read("a.array.2").unwrap().unwrap(), Value::Integer(2));
-        assert_eq!(h.read("a.array.3").unwrap().unwrap(), Value::Integer(3));
-        assert_eq!(h.read("a.array.4").unwrap().unwrap(), Value::Integer(4));
-        assert_eq!(h.read("a.array.5").unwrap().unwrap(), Value::Integer(5));
-        assert_eq!(h.read("a.array.6").unwrap().unwrap(), Value::Integer(6));
-        assert_eq!(h.read("a.array.7").unwrap().unwrap(), Value::Integer(7));
-        assert_eq!(h.read("a.array.8").unwrap().unwrap(), Value::Integer(8));
-        assert_eq!(h.read("a.array.9").unwrap().unwrap(), Value::Integer(9));
+        {
+            let mut entry = store.create(PathBuf::from("test/observed~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("written anyways");
+        }
+        let entry = store.retrieve_copy(PathBuf::from("test/observed~0.1.0")).unwrap();
+        assert_eq!(entry.get_content(), "written anyways");
+
+        assert!(store.create(PathBuf::from("test/guarded~0.1.0")).is_err());
+        assert!(!dir.path().join("test/guarded~0.1.0").exists());
     }
 
     #[test]
-    fn test_header_insert_new() {
-        let _ = env_logger::init();
-        let v = create_header();
-        let mut h = match v {
-            Value::Table(t) => EntryHeader::from_table(t),
-            _ => panic!("create_header() doesn't return a table!"),
-        };
+    fn test_export_entry_bundle() {
+        use std::fs::{File, create_dir};
+        use std::io::{Read, Write};
+        use error::StoreErrorKind;
+        use super::Entry;
 
-        assert!(h.read("a.foo").is_ok());
-        assert!(h.read("a.foo").unwrap().is_none());
+        let (dir, store) = get_store_with_config(None);
+        let assets = TempDir::new("imag-assets").unwrap();
+        let asset = assets.path().join("picture.png");
+        write!(File::create(&asset).unwrap(), "not really a picture").unwrap();
+        create_dir(assets.path().join("other dir")).unwrap();
+        let other_asset = assets.path().join("other dir/picture.png");
+        write!(File::create(&other_asset).unwrap(), "another picture").unwrap();
 
         {
-            let v = h.insert("a.foo", Value::Integer(42));
-            assert!(v.is_ok());
-            assert_eq!(v.unwrap(), true);
+            let mut entry = store.create(PathBuf::from("test/note~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("a note with a picture");
+            let header = entry.get_header_mut();
+            header.insert("imag.content", Value::Table(BTreeMap::new())).unwrap();
+            header.insert("imag.content.first",
+                          Value::String(format!("file://{}", asset.display()))).unwrap();
+            header.insert("imag.content.second",
+                          Value::String(format!("file://{}", asset.display()))).unwrap();
+            let other_url = format!("file://{}", other_asset.display()).replace(" ", "%20");
+            header.insert("imag.content.third", Value::String(other_url)).unwrap();
+            header.insert("imag.content.other",
+                          Value::String(String::from("https://example.com/remote.png"))).unwrap();
+        }
 
-            assert!(if let Ok(Some(Value::Table(_))) = h.read("a") { true } else { false });
-            assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.foo") { true } else { false });
+        let dest = dir.path().join("bundle");
+        store.export_entry_bundle(PathBuf::from("test/note~0.1.0"), &dest).unwrap();
+
+        let read = |name: &str| {
+            let mut s = String::new();
+            File::open(dest.join(name)).unwrap().read_to_string(&mut s).unwrap();
+            s
+        };
+        assert_eq!(read("picture.png"), "not really a picture");
+        assert_eq!(read("picture-1.png"), "another picture");
+        assert!(!dest.join("remote.png").exists());
+        assert!(!dest.join("picture-2.png").exists());
+
+        let entry = Entry::from_str(PathBuf::from("test/note~0.1.0"), &read("note~0.1.0")[..])
+            .unwrap();
+        assert_eq!(entry.get_content(), "a note with a picture");
+        let header = entry.get_header();
+        let string = |s: &str| Some(Value::String(String::from(s)));
+        assert_eq!(header.read("imag.content.first").unwrap(), string("picture.png"));
+        assert_eq!(header.read("imag.content.second").unwrap(), string("picture.png"));
+        assert_eq!(header.read("imag.content.third").unwrap(), string("picture-1.png"));
+        assert_eq!(header.read("imag.content.other").unwrap(),
+                   string("https://example.com/remote.png"));
+
+        // Files which cannot be copied are a FileError
+        {
+            let mut entry = store.create(PathBuf::from("test/broken~0.1.0")).unwrap();
+            let url = format!("file://{}", assets.path().join("missing.png").display());
+            entry.get_header_mut().insert("imag.uri", Value::String(url)).unwrap();
         }
+        let err = store.export_entry_bundle(PathBuf::from("test/broken~0.1.0"), &dest);
+        assert_eq!(err.err().unwrap().err_type(), StoreErrorKind::FileError);
+    }
 
+    #[test]
+    fn test_exists() {
+        let (dir, store) = get_store_with_config(None);
+        assert!(!store.exists(PathBuf::from("test/a~0.1.0")));
         {
-            let v = h.insert("new", Value::Table(BTreeMap::new()));
-            assert!(v.is_ok());
-            assert_eq!(v.unwrap(), true);
+            let _entry = store.create(PathBuf::from("test/a~0.1.0")).unwrap();
+            assert!(store.exists(PathBuf::from("test/a~0.1.0")));
+        }
 
-            let v = h.insert("new.subset", Value::Table(BTreeMap::new()));
-            assert!(v.is_ok());
-            assert_eq!(v.unwrap(), true);
+        // Does not create an entry in the cache
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        assert!(store.exists(PathBuf::from("test/a~0.1.0")));
+        assert!(!store.exists(PathBuf::from("test/b~0.1.0")));
+        assert!(store.entries.read().unwrap().is_empty());
+    }
 
-            let v = h.insert("new.subset.dest", Value::Integer(1337));
-            assert!(v.is_ok());
-            assert_eq!(v.unwrap(), true);
+    #[test]
+    fn test_create_locks_entry() {
+        use error::StoreErrorKind;
+
+        let (dir, store) = get_store_with_config(None);
+        let _entry = store.create(PathBuf::from("test/locked~0.1.0")).unwrap();
+
+        // A second store is treated like a second process by flock()
+        let other = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        let err = other.create(PathBuf::from("test/locked~0.1.0")).err().unwrap();
+        assert_eq!(err.err_type(), StoreErrorKind::EntryAlreadyExists);
+        let err = other.retrieve(PathBuf::from("test/locked~0.1.0")).err().unwrap();
+        assert_eq!(err.err_type(), StoreErrorKind::IdLocked);
+    }
 
-            assert!(if let Ok(Some(Value::Table(_))) = h.read("new") { true } else { false });
-            assert!(if let Ok(Some(Value::Table(_))) = h.read("new.subset") { true } else { false });
-            assert!(if let Ok(Some(Value::Integer(_))) = h.read("new.subset.dest") { true } else { false });
+    #[test]
+    fn test_entries_are_unlocked_when_released() {
+        use error::StoreErrorKind;
+
+        let (dir, store) = get_store_with_config(None);
+        let other = Store::new(PathBuf::from(dir.path()), None).unwrap();
+        for name in &["test/a~0.1.0", "test/b~0.1.0"] {
+            let _ = store.create(PathBuf::from(*name)).unwrap();
+        }
+
+        {
+            let _a = store.retrieve(PathBuf::from("test/a~0.1.0")).unwrap();
+            let err = other.retrieve(PathBuf::from("test/a~0.1.0")).err().unwrap();
+            assert_eq!(err.err_type(), StoreErrorKind::IdLocked);
         }
+        assert!(store.retrieve_copy(PathBuf::from("test/b~0.1.0")).is_ok());
+
+        // Cached entries which are not borrowed keep no file open
+        assert!(store.entries.read().unwrap().values().all(|se| !se.is_opened()));
+        assert!(other.retrieve(PathBuf::from("test/a~0.1.0")).is_ok());
+        assert!(other.retrieve(PathBuf::from("test/b~0.1.0")).is_ok());
     }
 
     #[test]
-    fn test_header_delete() {
-        let _ = env_logger::init();
-        let v = create_header();
-        let mut h = match v {
-            Value::Table(t) => EntryHeader::from_table(t),
-            _ => panic!("create_header() doesn't return a table!"),
-        };
+    fn test_clear_poison() {
+        use std::thread;
 
-        assert!(if let Ok(Some(Value::Table(_)))   = h.read("a") { true } else { false });
-        assert!(if let Ok(Some(Value::Array(_)))   = h.read("a.array") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.1") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(_))) = h.read("a.array.9") { true } else { false });
+        let (_dir, store) = get_store_with_config(None);
 
-        assert!(if let Ok(Some(Value::Integer(1))) = h.delete("a.array.1") { true } else { false });
-        assert!(if let Ok(Some(Value::Integer(9))) = h.delete("a.array.8") { true } else { false });
-        assert!(if let Ok(Some(Value::Array(_)))   = h.delete("a.array") { true } else { false });
-        assert!(if let Ok(Some(Value::Table(_)))   = h.delete("a") { true } else { false });
+        thread::scope(|scope| {
+            let handle = scope.spawn(|| {
+                let _guard = store.entries.write().unwrap();
+                panic!("Poisoning the store");
+            });
+            assert!(handle.join().is_err());
+        });
 
+        assert!(store.create(PathBuf::from("test/poison~0.1.0")).is_err());
+        assert!(store.clear_poison().is_ok());
+
+        {
+            let entry = store.create(PathBuf::from("test/poison~0.1.0"));
+            assert!(entry.is_ok());
+        }
+        assert!(store.retrieve(PathBuf::from("test/poison~0.1.0")).is_ok());
     }
 
 }
-
@@ -90,15 +90,80 @@ macro_rules! module_entry_path_mod {
     )
 }
 
+/// The way entries are laid out on the filesystem
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoreLayout {
+    /// Each path component of a `StoreId` is a directory on the filesystem (the default)
+    Hierarchical,
+
+    /// Everything after the module name is encoded into a single file name, so
+    /// `diary/2016/1-2-3:4~0.1.0` lives in `diary/2016%2F1-2-3%3A4~0.1.0`
+    Flat,
+}
+
+/// Characters which are percent-encoded in the `Flat` layout
+const ENCODED_CHARS: [char; 10] = ['%', '/', '\\', ':', '<', '>', '"', '|', '?', '*'];
+
+/// Percent-encode a path so it can be used as a single file name
+pub fn encode_path(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for c in s.chars() {
+        if ENCODED_CHARS.contains(&c) {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Decode a file name which was encoded with `encode_path()`
+///
+/// Returns `None` if the string contains an invalid escape sequence.
+pub fn decode_path(s: &str) -> Option<String> {
+    let mut decoded = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            decoded.push(c);
+            continue;
+        }
+
+        let hex : String = chars.by_ref().take(2).collect();
+        if hex.len() != 2 {
+            return None;
+        }
+        match u8::from_str_radix(&hex[..], 16) {
+            Ok(b) => decoded.push(b as char),
+            Err(_) => return None,
+        }
+    }
+    Some(decoded)
+}
+
 pub struct StoreIdIterator {
-    paths: Paths,
+    paths: Option<Paths>,
+    layout: StoreLayout,
 }
 
 impl StoreIdIterator {
 
     pub fn new(paths: Paths) -> StoreIdIterator {
+        StoreIdIterator::with_layout(paths, StoreLayout::Hierarchical)
+    }
+
+    pub fn with_layout(paths: Paths, layout: StoreLayout) -> StoreIdIterator {
         StoreIdIterator {
-            paths: paths,
+            paths: Some(paths),
+            layout: layout,
+        }
+    }
+
+    /// An iterator which yields no ids
+    pub fn empty() -> StoreIdIterator {
+        StoreIdIterator {
+            paths: None,
+            layout: StoreLayout::Hierarchical,
         }
     }
 
@@ -108,7 +173,39 @@ impl Iterator for StoreIdIterator {
     type Item = StoreId;
 
     fn next(&mut self) -> Option<StoreId> {
-        self.paths.next().and_then(|o| o.ok())
+        // Directories and hidden files are never entries
+        let path = loop {
+            match self.paths.as_mut().and_then(|paths| paths.next()) {
+                Some(Ok(path)) => {
+                    let hidden = path.file_name()
+                        .and_then(|name| name.to_str())
+                        .map(|name| name.starts_with("."))
+                        .unwrap_or(false);
+
+                    if !hidden && !path.is_dir() {
+                        break path;
+                    }
+                },
+                Some(Err(_)) => continue,
+                None => return None,
+            }
+        };
+
+        if self.layout != StoreLayout::Flat {
+            return Some(path);
+        }
+
+        let decoded = path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(decode_path);
+
+        match (path.parent(), decoded) {
+            (Some(parent), Some(decoded)) => Some(parent.join(decoded)),
+            _ => {
+                warn!("Could not decode file name: {:?}", path);
+                Some(path)
+            }
+        }
     }
 
 }
@@ -120,6 +217,34 @@ mod test {
 
     module_entry_path_mod!("test", "0.2.0-alpha+leet1337");
 
+    #[test]
+    fn test_encode_decode_path_roundtrip() {
+        use storeid::{encode_path, decode_path};
+
+        let id = "2016/01/02-10:30~0.1.0";
+        let encoded = encode_path(id);
+
+        assert_eq!(encoded, "2016%2F01%2F02-10%3A30~0.1.0");
+        assert!(!encoded.contains('/'));
+        assert_eq!(decode_path(&encoded[..]).unwrap(), id);
+    }
+
+    #[test]
+    fn test_encode_decode_path_with_percent() {
+        use storeid::{encode_path, decode_path};
+
+        let id = "100%/done:yes";
+        assert_eq!(decode_path(&encode_path(id)[..]).unwrap(), id);
+    }
+
+    #[test]
+    fn test_decode_invalid_path() {
+        use storeid::decode_path;
+
+        assert!(decode_path("foo%2").is_none());
+        assert!(decode_path("foo%ZZbar").is_none());
+    }
+
     #[test]
     fn correct_path() {
         let p = module_path::ModuleEntryPath::new("test");
@@ -0,0 +1,46 @@
+//! Helpers for tests of crates which use the store
+//!
+//! Only available with the `testing` feature.
+
+use std::path::PathBuf;
+
+use tempdir::TempDir;
+
+use store::Store;
+
+/// Create a store with the default configuration in a new temporary directory
+///
+/// The directory and everything in it is removed when the returned `TempDir` is dropped, so it
+/// has to be kept alive as long as the store is used.
+pub fn tempstore() -> (TempDir, Store) {
+    let dir = TempDir::new("imag-store").unwrap();
+    let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+    (dir, store)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::tempstore;
+
+    #[test]
+    fn test_tempstore() {
+        let (dir, store) = tempstore();
+        let path = PathBuf::from(dir.path());
+
+        {
+            let mut entry = store.create(PathBuf::from("test/tempstore~0.1.0")).unwrap();
+            *entry.get_content_mut() = String::from("content");
+        }
+
+        let mut file = path.clone();
+        file.push("test/tempstore~0.1.0");
+        assert!(file.is_file());
+
+        drop(store);
+        drop(dir);
+        assert!(!path.exists());
+    }
+
+}
@@ -0,0 +1,182 @@
+//! Wrapping of entry content, see the `wrap-content-at` store configuration key
+//!
+//! Only paragraphs are wrapped. Fenced and indented code blocks, headings (including the text and
+//! underline of setext headings), thematic breaks, list items, quotes, tables, link reference
+//! definitions, lines ending in a hard break and blank lines are kept as they are, so markdown
+//! content stays intact.
+
+/// Wrap the paragraphs of `content` so their lines are at most `width` characters long
+///
+/// Words longer than `width` are put on a line of their own. Wrapping already wrapped content
+/// does not change it.
+pub fn wrap_content(content: &str, width: usize) -> String {
+    let mut lines : Vec<String> = vec![];
+    let mut paragraph : Vec<&str> = vec![];
+    let mut in_fence = false;
+
+    let content_lines : Vec<&str> = content.lines().collect();
+    for (i, line) in content_lines.iter().enumerate() {
+        let line = *line;
+        let trimmed = line.trim_left();
+        let is_fence = trimmed.starts_with("```") || trimmed.starts_with("~~~");
+        let is_heading_text = content_lines.get(i + 1)
+            .map(|next| is_setext_underline(next))
+            .unwrap_or(false);
+
+        if in_fence || is_fence || is_heading_text || is_verbatim(line) {
+            lines.extend(wrap_paragraph(&paragraph, width));
+            paragraph.clear();
+            lines.push(String::from(line));
+            if is_fence {
+                in_fence = !in_fence;
+            }
+        } else {
+            paragraph.extend(line.split_whitespace());
+        }
+    }
+    lines.extend(wrap_paragraph(&paragraph, width));
+
+    let mut wrapped = lines.join("\n");
+    if content.ends_with("\n") {
+        wrapped.push('\n');
+    }
+    wrapped
+}
+
+/// Whether a line is not part of a paragraph and has to be kept as it is
+fn is_verbatim(line: &str) -> bool {
+    line.trim().is_empty() ||
+        line.starts_with("    ") ||
+        line.starts_with("\t") ||
+        line.ends_with("  ") ||
+        is_setext_underline(line) ||
+        is_thematic_break(line) ||
+        starts_block(line.trim_left())
+}
+
+/// Whether a line underlines the line before it as setext heading, like `===` or `---`
+fn is_setext_underline(line: &str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() &&
+        (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'))
+}
+
+/// Whether a line is a thematic break, like `***`, `___` or `- - -`
+fn is_thematic_break(line: &str) -> bool {
+    let chars : Vec<char> = line.chars().filter(|c| !c.is_whitespace()).collect();
+    chars.len() >= 3 &&
+        ['*', '-', '_'].iter().any(|marker| chars.iter().all(|c| c == marker))
+}
+
+/// Whether a line starting with `word` would be read as something else than paragraph text
+fn starts_block(word: &str) -> bool {
+    let numbered = word.chars().take_while(|c| c.is_digit(10)).count();
+
+    word.starts_with("#") ||
+        word.starts_with(">") ||
+        word.starts_with("|") ||
+        word.starts_with("```") ||
+        word.starts_with("~~~") ||
+        word == "-" || word.starts_with("- ") ||
+        word == "*" || word.starts_with("* ") ||
+        word == "+" || word.starts_with("+ ") ||
+        (word.starts_with("[") && word.contains("]:")) ||
+        is_setext_underline(word) ||
+        is_thematic_break(word) ||
+        (numbered > 0 && (word[numbered..].starts_with(".") || word[numbered..].starts_with(")")))
+}
+
+fn wrap_paragraph(words: &[&str], width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in words {
+        // A word which would start a block cannot start a line, otherwise wrapping the result
+        // again would not give the same result
+        let fits = line.chars().count() + 1 + word.chars().count() <= width;
+        if line.is_empty() {
+            line.push_str(word);
+        } else if fits || starts_block(word) {
+            line.push(' ');
+            line.push_str(word);
+        } else {
+            lines.push(line);
+            line = String::from(*word);
+        }
+    }
+
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::wrap_content;
+
+    #[test]
+    fn test_wrap_paragraph() {
+        let content = "This is a rather long paragraph which has to be wrapped.\n";
+        assert_eq!(wrap_content(content, 20),
+                   "This is a rather\nlong paragraph which\nhas to be wrapped.\n");
+    }
+
+    #[test]
+    fn test_wrap_joins_short_lines() {
+        assert_eq!(wrap_content("a b\nc\nd e f", 5), "a b c\nd e f");
+    }
+
+    #[test]
+    fn test_wrap_keeps_blocks() {
+        let content = "# A heading which is longer than the width\n\
+                       \n\
+                       ```\n\
+                       let code = \"which is longer than the width\";\n\
+                       ```\n\
+                       \n    indented code which is longer than the width\n\
+                       - a list item which is longer than the width\n\
+                       > a quote which is longer than the width\n";
+        assert_eq!(wrap_content(content, 10), content);
+    }
+
+    #[test]
+    fn test_wrap_keeps_setext_headings() {
+        let content = "Some text\nTitle\n=====\nSubtitle\n--------\n";
+        assert_eq!(wrap_content(content, 40), content);
+    }
+
+    #[test]
+    fn test_wrap_keeps_thematic_breaks() {
+        let content = "a b\n***\nc d\n___\ne f\n- - -\ng h\n";
+        assert_eq!(wrap_content(content, 40), content);
+    }
+
+    #[test]
+    fn test_wrap_keeps_hard_breaks() {
+        let content = "first line  \nsecond line\nthird line\n";
+        assert_eq!(wrap_content(content, 40), "first line  \nsecond line third line\n");
+    }
+
+    #[test]
+    fn test_wrap_keeps_link_reference_definitions() {
+        let content = "Some text\n[x]: https://example.com/a/rather/long/url\nmore text\n";
+        assert_eq!(wrap_content(content, 20), content);
+    }
+
+    #[test]
+    fn test_wrap_long_words() {
+        assert_eq!(wrap_content("a averylongword b", 5), "a\naverylongword\nb");
+    }
+
+    #[test]
+    fn test_wrap_is_idempotent() {
+        let content = "Some text - with a dash and # a hash and 1. a number which could start \
+                       blocks, like === and *** and [x]: too.\n\nAnd a second paragraph.\n";
+        for width in 1..40 {
+            let wrapped = wrap_content(content, width);
+            assert_eq!(wrap_content(&wrapped, width), wrapped);
+        }
+    }
+
+}
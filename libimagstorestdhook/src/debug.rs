@@ -49,6 +49,7 @@ impl HookDataAccessorProvider for DebugHook {
             HP::PostRetrieve => HDA::MutableAccess(&self.accessor),
             HP::PreUpdate    => HDA::MutableAccess(&self.accessor),
             HP::PostUpdate   => HDA::MutableAccess(&self.accessor),
+            HP::PostWrite    => HDA::NonMutableAccess(&self.accessor),
             HP::PreDelete    => HDA::StoreIdAccess(&self.accessor),
             HP::PostDelete   => HDA::StoreIdAccess(&self.accessor),
         }
@@ -10,6 +10,7 @@ pub enum TagErrorKind {
     HeaderReadError,
     HeaderWriteError,
     NotATag,
+    StoreReadError,
 }
 
 fn tag_error_type_as_str(e: &TagErrorKind) -> &'static str {
@@ -18,6 +19,7 @@ fn tag_error_type_as_str(e: &TagErrorKind) -> &'static str {
         &TagErrorKind::HeaderReadError  => "Error while reading entry header",
         &TagErrorKind::HeaderWriteError => "Error while writing entry header",
         &TagErrorKind::NotATag          => "String is not a tag",
+        &TagErrorKind::StoreReadError   => "Error while reading from the store",
     }
 }
 
@@ -3,6 +3,7 @@ extern crate itertools;
 #[macro_use] extern crate log;
 extern crate regex;
 extern crate toml;
+#[cfg(test)] extern crate tempdir;
 
 extern crate libimagstore;
 
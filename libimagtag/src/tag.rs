@@ -1 +1,73 @@
+use std::collections::BTreeMap;
+
+use itertools::Itertools;
+
+use libimagstore::store::Store;
+
+use error::{TagError, TagErrorKind};
+use result::Result;
+use tagable::Tagable;
+
 pub type Tag = String;
+
+/// Count how often two tags are set on the same entry, for all entries in the store
+///
+/// The pairs are ordered, so the smaller tag is always the first element and each pair is
+/// counted once per entry. Pairs which never occur together are not in the map.
+pub fn cooccurrence(store: &Store) -> Result<BTreeMap<(Tag, Tag), usize>> {
+    let mut counts = BTreeMap::new();
+    let ids = try!(store.retrieve_all()
+        .map_err(|e| TagError::new(TagErrorKind::StoreReadError, Some(Box::new(e)))));
+
+    for id in ids {
+        let entry = try!(store.retrieve_copy(id)
+            .map_err(|e| TagError::new(TagErrorKind::StoreReadError, Some(Box::new(e)))));
+        let mut tags : Vec<Tag> = try!(entry.get_tags()).into_iter().unique().collect();
+        tags.sort();
+
+        for (i, a) in tags.iter().enumerate() {
+            for b in &tags[(i + 1)..] {
+                *counts.entry((a.clone(), b.clone())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use libimagstore::store::Store;
+    use tempdir::TempDir;
+
+    use tagable::Tagable;
+    use super::cooccurrence;
+
+    #[test]
+    fn test_cooccurrence() {
+        let dir = TempDir::new("imag-tag-test").unwrap();
+        let store = Store::new(PathBuf::from(dir.path()), None).unwrap();
+
+        let entries : [(&str, &[&str]); 4] = [
+            ("test/a~0.1.0", &["rust", "code", "imag"]),
+            ("test/b~0.1.0", &["imag", "rust"]),
+            ("test/c~0.1.0", &["cooking"]),
+            ("test/d~0.1.0", &[]),
+        ];
+        for &(id, tags) in entries.iter() {
+            let mut entry = store.create(PathBuf::from(id)).unwrap();
+            entry.set_tags(tags.iter().map(|t| String::from(*t)).collect()).unwrap();
+        }
+
+        let counts = cooccurrence(&store).unwrap();
+        let pair = |a: &str, b: &str| (String::from(a), String::from(b));
+        assert_eq!(counts.len(), 3);
+        assert_eq!(counts.get(&pair("imag", "rust")), Some(&2));
+        assert_eq!(counts.get(&pair("code", "imag")), Some(&1));
+        assert_eq!(counts.get(&pair("code", "rust")), Some(&1));
+        assert_eq!(counts.get(&pair("rust", "imag")), None);
+    }
+
+}
@@ -4,5 +4,7 @@ extern crate regex;
 
 pub mod ismatch;
 pub mod key_value_split;
+pub mod preview;
+pub mod template;
 pub mod trace;
 pub mod variants;
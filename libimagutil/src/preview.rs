@@ -0,0 +1,58 @@
+//! Helpers for shortening text for previews
+
+/// The marker which is appended to truncated text by `preview()`
+pub const DEFAULT_MARKER: &'static str = "…";
+
+/// Shorten `s` to at most `max` characters, appending `DEFAULT_MARKER` if it was truncated
+pub fn preview(s: &str, max: usize) -> String {
+    preview_with_marker(s, max, DEFAULT_MARKER)
+}
+
+/// Shorten `s` to at most `max` characters, appending `marker` if it was truncated
+///
+/// The marker counts towards `max`, so the result is never longer than `max` characters. If the
+/// marker itself does not fit, it is truncated as well.
+pub fn preview_with_marker(s: &str, max: usize, marker: &str) -> String {
+    if s.chars().count() <= max {
+        return String::from(s);
+    }
+
+    let marker_len = marker.chars().count();
+    if marker_len >= max {
+        return marker.chars().take(max).collect();
+    }
+
+    let mut out : String = s.chars().take(max - marker_len).collect();
+    out.push_str(marker);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::{preview, preview_with_marker};
+
+    #[test]
+    fn test_short_text_is_unchanged() {
+        assert_eq!(preview("hello", 5), "hello");
+        assert_eq!(preview_with_marker("hello", 5, "..."), "hello");
+    }
+
+    #[test]
+    fn test_unicode_marker_at_boundary() {
+        assert_eq!(preview("hello world", 6), "hello…");
+        assert_eq!(preview("hello world", 6).chars().count(), 6);
+    }
+
+    #[test]
+    fn test_ascii_marker_at_boundary() {
+        assert_eq!(preview_with_marker("hello world", 8, "..."), "hello...");
+        assert_eq!(preview_with_marker("hello world", 10, "..."), "hello w...");
+        assert_eq!(preview_with_marker("hello world", 11, "..."), "hello world");
+    }
+
+    #[test]
+    fn test_marker_longer_than_max() {
+        assert_eq!(preview_with_marker("hello world", 2, "..."), "..");
+    }
+
+}
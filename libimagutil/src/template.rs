@@ -0,0 +1,122 @@
+//! Minimal templating with `{{var}}` substitution
+//!
+//! A backslash escapes a literal brace or backslash, so `\{{var}}` renders as `{{var}}`.
+
+use std::collections::BTreeMap;
+
+/// Render `template`, replacing each `{{var}}` with its value from `vars`
+///
+/// Unknown variables are left in the output as they are.
+pub fn render_template(template: &str, vars: &BTreeMap<String, String>) -> String {
+    render(template, vars, false).unwrap_or_else(|_| unreachable!())
+}
+
+/// Render `template` like `render_template()`, but fail with the name of the first unknown
+/// variable
+pub fn render_template_strict(template: &str, vars: &BTreeMap<String, String>)
+    -> Result<String, String>
+{
+    render(template, vars, true)
+}
+
+fn render(template: &str, vars: &BTreeMap<String, String>, strict: bool) -> Result<String, String> {
+    let mut out   = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                match chars.peek().cloned() {
+                    Some(next @ '{') | Some(next @ '}') | Some(next @ '\\') => {
+                        chars.next();
+                        out.push(next);
+                    },
+                    _ => out.push(c),
+                }
+            },
+
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+
+                let mut name   = String::new();
+                let mut closed = false;
+                while let Some(c) = chars.next() {
+                    if c == '}' && chars.peek() == Some(&'}') {
+                        chars.next();
+                        closed = true;
+                        break;
+                    }
+                    name.push(c);
+                }
+
+                if !closed {
+                    debug!("Unclosed variable in template: '{{{{{}'", name);
+                    out.push_str("{{");
+                    out.push_str(&name[..]);
+                    continue;
+                }
+
+                match vars.get(name.trim()) {
+                    Some(value) => out.push_str(&value[..]),
+                    None if strict => return Err(String::from(name.trim())),
+                    None => {
+                        debug!("Unknown variable in template: '{}'", name.trim());
+                        out.push_str("{{");
+                        out.push_str(&name[..]);
+                        out.push_str("}}");
+                    },
+                }
+            },
+
+            _ => out.push(c),
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+
+    use super::{render_template, render_template_strict};
+
+    fn vars() -> BTreeMap<String, String> {
+        let mut vars = BTreeMap::new();
+        vars.insert(String::from("name"), String::from("imag"));
+        vars.insert(String::from("date"), String::from("2016-04-01"));
+        vars
+    }
+
+    #[test]
+    fn test_substitution() {
+        let s = render_template("{{name}} at {{ date }}", &vars());
+        assert_eq!(s, "imag at 2016-04-01");
+    }
+
+    #[test]
+    fn test_missing_variable_is_kept() {
+        let s = render_template("{{name}}: {{unknown}}", &vars());
+        assert_eq!(s, "imag: {{unknown}}");
+    }
+
+    #[test]
+    fn test_missing_variable_strict() {
+        assert_eq!(render_template_strict("{{name}}: {{unknown}}", &vars()),
+                   Err(String::from("unknown")));
+        assert_eq!(render_template_strict("{{name}}", &vars()), Ok(String::from("imag")));
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        let s = render_template("\\{{name}} is {{name}}, \\\\ and \\}", &vars());
+        assert_eq!(s, "{{name}} is imag, \\ and }");
+    }
+
+    #[test]
+    fn test_unclosed_variable() {
+        let s = render_template("{{name", &vars());
+        assert_eq!(s, "{{name");
+    }
+
+}